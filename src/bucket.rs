@@ -0,0 +1,481 @@
+use std::fs;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use xmltree::{Element, XMLNode};
+
+// limits S3 puts on bucket configuration documents
+const MAX_LIFECYCLE_RULES: usize = 1000;
+const MAX_CORS_RULES: usize = 100;
+const MAX_ID_LEN: usize = 255;
+const CORS_METHODS: [&str; 5] = ["GET", "PUT", "POST", "DELETE", "HEAD"];
+
+/// Lifecycle rules in the JSON layout of `aws s3api put-bucket-lifecycle-configuration`,
+/// the same keys are used in TOML files.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub(crate) struct Lifecycle {
+    pub(crate) rules: Vec<LifecycleRule>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub(crate) struct LifecycleRule {
+    #[serde(rename = "ID", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) id: Option<String>,
+    pub(crate) status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) filter: Option<Filter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) expiration: Option<Expiration>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) transitions: Vec<Transition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) noncurrent_version_expiration: Option<NoncurrentVersionExpiration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) abort_incomplete_multipart_upload: Option<AbortIncompleteMultipartUpload>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub(crate) struct Filter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) tag: Option<Tag>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) and: Option<And>,
+}
+
+/// A prefix and several tags an object must all have.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub(crate) struct And {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) tags: Vec<Tag>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub(crate) struct Tag {
+    pub(crate) key: String,
+    pub(crate) value: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub(crate) struct Expiration {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) days: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) expired_object_delete_marker: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub(crate) struct Transition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) days: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) date: Option<String>,
+    pub(crate) storage_class: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub(crate) struct NoncurrentVersionExpiration {
+    pub(crate) noncurrent_days: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub(crate) struct AbortIncompleteMultipartUpload {
+    pub(crate) days_after_initiation: u32,
+}
+
+/// CORS rules in the JSON layout of `aws s3api put-bucket-cors`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Cors {
+    #[serde(rename = "CORSRules")]
+    pub(crate) cors_rules: Vec<CorsRule>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub(crate) struct CorsRule {
+    #[serde(rename = "ID", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) allowed_headers: Vec<String>,
+    pub(crate) allowed_methods: Vec<String>,
+    pub(crate) allowed_origins: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) expose_headers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) max_age_seconds: Option<u32>,
+}
+
+/// Reads a document from a `.toml` file, anything else is read as JSON.
+pub(crate) fn load<T: DeserializeOwned>(path: &str) -> Result<T, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("cant read {}: {}", path, err))?;
+    match is_toml(path) {
+        true => toml::from_str(&text).map_err(|err| format!("{} is not valid: {}", path, err)),
+        false => serde_json::from_str(&text).map_err(|err| format!("{} is not valid: {}", path, err)),
+    }
+}
+
+/// Writes a document as TOML for `.toml` paths and as pretty JSON otherwise.
+pub(crate) fn render<T: serde::Serialize>(document: &T, path: &str) -> Result<String, String> {
+    match is_toml(path) {
+        true => toml::to_string_pretty(document).map_err(|err| format!("cant write TOML: {}", err)),
+        false => serde_json::to_string_pretty(document).map_err(|err| format!("cant write JSON: {}", err)),
+    }
+}
+
+fn is_toml(path: &str) -> bool {
+    path.to_lowercase().ends_with(".toml")
+}
+
+/// Reads a bucket policy from JSON or TOML and checks its shape, returns it as compact JSON.
+pub(crate) fn load_policy(path: &str) -> Result<String, String> {
+    let policy: serde_json::Value = load(path)?;
+    let statements = policy.get("Statement").and_then(|statements| statements.as_array())
+        .ok_or("policy needs a Statement list")?;
+    if statements.is_empty() {
+        return Err("policy needs at least one statement, use `bucket policy rm` to remove it".to_string());
+    }
+    if let Some(version) = policy.get("Version").and_then(|version| version.as_str()) {
+        if version != "2012-10-17" && version != "2008-10-17" {
+            return Err(format!("unknown policy Version {}, use 2012-10-17", version));
+        }
+    }
+    for (index, statement) in statements.iter().enumerate() {
+        let has = |key: &str| statement.get(key).is_some();
+        let number = index + 1;
+        match statement.get("Effect").and_then(|effect| effect.as_str()) {
+            Some("Allow") | Some("Deny") => {}
+            _ => return Err(format!("statement {} needs Effect Allow or Deny", number)),
+        }
+        if !has("Principal") && !has("NotPrincipal") {
+            return Err(format!("statement {} needs a Principal", number));
+        }
+        if !has("Action") && !has("NotAction") {
+            return Err(format!("statement {} needs an Action", number));
+        }
+        if !has("Resource") && !has("NotResource") {
+            return Err(format!("statement {} needs a Resource", number));
+        }
+    }
+    serde_json::to_string(&policy).map_err(|err| err.to_string())
+}
+
+impl Lifecycle {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.rules.is_empty() || self.rules.len() > MAX_LIFECYCLE_RULES {
+            return Err(format!("lifecycle needs 1 to {} rules", MAX_LIFECYCLE_RULES));
+        }
+        for (index, rule) in self.rules.iter().enumerate() {
+            let name = rule.id.clone().unwrap_or(format!("{}", index + 1));
+            check_id(&rule.id)?;
+            if rule.status != "Enabled" && rule.status != "Disabled" {
+                return Err(format!("rule {}: Status must be Enabled or Disabled", name));
+            }
+            if rule.expiration.is_none() && rule.transitions.is_empty() && rule.noncurrent_version_expiration.is_none() && rule.abort_incomplete_multipart_upload.is_none() {
+                return Err(format!("rule {}: needs an action like Expiration or AbortIncompleteMultipartUpload", name));
+            }
+            if let Some(expiration) = &rule.expiration {
+                let set = [expiration.days.is_some(), expiration.date.is_some(), expiration.expired_object_delete_marker.is_some()];
+                if set.iter().filter(|set| **set).count() != 1 {
+                    return Err(format!("rule {}: Expiration needs exactly one of Days, Date or ExpiredObjectDeleteMarker", name));
+                }
+                check_days(&name, "Expiration Days", expiration.days)?;
+                check_date(&name, &expiration.date)?;
+            }
+            for transition in &rule.transitions {
+                if transition.days.is_some() == transition.date.is_some() {
+                    return Err(format!("rule {}: Transition needs either Days or Date", name));
+                }
+                check_date(&name, &transition.date)?;
+            }
+            if let Some(noncurrent) = &rule.noncurrent_version_expiration {
+                check_days(&name, "NoncurrentDays", Some(noncurrent.noncurrent_days))?;
+            }
+            if let Some(abort) = &rule.abort_incomplete_multipart_upload {
+                check_days(&name, "DaysAfterInitiation", Some(abort.days_after_initiation))?;
+                if rule.filter.as_ref().is_some_and(|filter| filter.tag.is_some() || filter.and.as_ref().is_some_and(|and| !and.tags.is_empty())) {
+                    return Err(format!("rule {}: AbortIncompleteMultipartUpload cant be used with a Tag filter", name));
+                }
+            }
+            if let Some(filter) = &rule.filter {
+                if [filter.prefix.is_some(), filter.tag.is_some(), filter.and.is_some()].iter().filter(|set| **set).count() > 1 {
+                    return Err(format!("rule {}: Filter takes one of Prefix, Tag or And", name));
+                }
+                if filter.and.as_ref().is_some_and(|and| and.tags.len() + and.prefix.iter().count() < 2) {
+                    return Err(format!("rule {}: And needs at least two of Prefix and Tags, use Prefix or Tag for one", name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn to_xml(&self) -> String {
+        let rules: String = self.rules.iter().map(|rule| {
+            let mut xml = String::new();
+            if let Some(id) = &rule.id {
+                xml += &tag("ID", &escape(id));
+            }
+            xml += &match &rule.filter {
+                Some(Filter { and: Some(and), .. }) => {
+                    let prefix = and.prefix.as_ref().map(|prefix| tag("Prefix", &escape(prefix))).unwrap_or_default();
+                    tag("Filter", &tag("And", &(prefix + &and.tags.iter().map(tag_xml).collect::<String>())))
+                }
+                Some(Filter { tag: Some(filter_tag), .. }) => tag("Filter", &tag_xml(filter_tag)),
+                Some(Filter { prefix: Some(prefix), .. }) => tag("Filter", &tag("Prefix", &escape(prefix))),
+                _ => tag("Filter", ""),
+            };
+            xml += &tag("Status", &rule.status);
+            if let Some(expiration) = &rule.expiration {
+                let mut inner = String::new();
+                if let Some(days) = expiration.days { inner += &tag("Days", &days.to_string()); }
+                if let Some(date) = &expiration.date { inner += &tag("Date", &escape(&midnight(date))); }
+                if let Some(marker) = expiration.expired_object_delete_marker { inner += &tag("ExpiredObjectDeleteMarker", &marker.to_string()); }
+                xml += &tag("Expiration", &inner);
+            }
+            for transition in &rule.transitions {
+                let mut inner = String::new();
+                if let Some(days) = transition.days { inner += &tag("Days", &days.to_string()); }
+                if let Some(date) = &transition.date { inner += &tag("Date", &escape(&midnight(date))); }
+                inner += &tag("StorageClass", &escape(&transition.storage_class));
+                xml += &tag("Transition", &inner);
+            }
+            if let Some(noncurrent) = &rule.noncurrent_version_expiration {
+                xml += &tag("NoncurrentVersionExpiration", &tag("NoncurrentDays", &noncurrent.noncurrent_days.to_string()));
+            }
+            if let Some(abort) = &rule.abort_incomplete_multipart_upload {
+                xml += &tag("AbortIncompleteMultipartUpload", &tag("DaysAfterInitiation", &abort.days_after_initiation.to_string()));
+            }
+            tag("Rule", &xml)
+        }).collect();
+        tag("LifecycleConfiguration", &rules)
+    }
+
+    pub(crate) fn from_xml(xml: &str) -> Result<Lifecycle, String> {
+        let root = Element::parse(xml.as_bytes()).map_err(|err| err.to_string())?;
+        let rules = children(&root, "Rule").into_iter().map(|rule| LifecycleRule {
+            id: text(rule, "ID"),
+            status: text(rule, "Status").unwrap_or_default(),
+            filter: rule.get_child("Filter").map(|filter| Filter {
+                prefix: text(filter, "Prefix").filter(|prefix| !prefix.is_empty()),
+                tag: filter.get_child("Tag").map(tag_of),
+                and: filter.get_child("And").map(|and| And {
+                    prefix: text(and, "Prefix").filter(|prefix| !prefix.is_empty()),
+                    tags: children(and, "Tag").into_iter().map(tag_of).collect(),
+                }),
+            }).or(text(rule, "Prefix").map(|prefix| Filter { prefix: Some(prefix), ..Filter::default() })),
+            expiration: rule.get_child("Expiration").map(|expiration| Expiration {
+                days: number(expiration, "Days"),
+                date: text(expiration, "Date"),
+                expired_object_delete_marker: text(expiration, "ExpiredObjectDeleteMarker").map(|marker| marker == "true"),
+            }),
+            transitions: children(rule, "Transition").into_iter().map(|transition| Transition {
+                days: number(transition, "Days"),
+                date: text(transition, "Date"),
+                storage_class: text(transition, "StorageClass").unwrap_or_default(),
+            }).collect(),
+            noncurrent_version_expiration: rule.get_child("NoncurrentVersionExpiration")
+                .and_then(|noncurrent| number(noncurrent, "NoncurrentDays"))
+                .map(|noncurrent_days| NoncurrentVersionExpiration { noncurrent_days }),
+            abort_incomplete_multipart_upload: rule.get_child("AbortIncompleteMultipartUpload")
+                .and_then(|abort| number(abort, "DaysAfterInitiation"))
+                .map(|days_after_initiation| AbortIncompleteMultipartUpload { days_after_initiation }),
+        }).collect();
+        Ok(Lifecycle { rules })
+    }
+}
+
+impl Cors {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.cors_rules.is_empty() || self.cors_rules.len() > MAX_CORS_RULES {
+            return Err(format!("CORS needs 1 to {} rules", MAX_CORS_RULES));
+        }
+        for (index, rule) in self.cors_rules.iter().enumerate() {
+            let name = rule.id.clone().unwrap_or(format!("{}", index + 1));
+            check_id(&rule.id)?;
+            if rule.allowed_origins.is_empty() {
+                return Err(format!("rule {}: needs AllowedOrigins", name));
+            }
+            if rule.allowed_methods.is_empty() {
+                return Err(format!("rule {}: needs AllowedMethods", name));
+            }
+            if let Some(method) = rule.allowed_methods.iter().find(|method| !CORS_METHODS.contains(&method.as_str())) {
+                return Err(format!("rule {}: method {} is not one of {}", name, method, CORS_METHODS.join(", ")));
+            }
+            if let Some(origin) = rule.allowed_origins.iter().find(|origin| origin.matches('*').count() > 1) {
+                return Err(format!("rule {}: origin {} can have only one *", name, origin));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn to_xml(&self) -> String {
+        let rules: String = self.cors_rules.iter().map(|rule| {
+            let mut xml = String::new();
+            if let Some(id) = &rule.id {
+                xml += &tag("ID", &escape(id));
+            }
+            let list = |name: &str, values: &Vec<String>| values.iter().map(|value| tag(name, &escape(value))).collect::<String>();
+            xml += &list("AllowedHeader", &rule.allowed_headers);
+            xml += &list("AllowedMethod", &rule.allowed_methods);
+            xml += &list("AllowedOrigin", &rule.allowed_origins);
+            xml += &list("ExposeHeader", &rule.expose_headers);
+            if let Some(max_age) = rule.max_age_seconds {
+                xml += &tag("MaxAgeSeconds", &max_age.to_string());
+            }
+            tag("CORSRule", &xml)
+        }).collect();
+        tag("CORSConfiguration", &rules)
+    }
+
+    pub(crate) fn from_xml(xml: &str) -> Result<Cors, String> {
+        let root = Element::parse(xml.as_bytes()).map_err(|err| err.to_string())?;
+        let texts = |rule: &Element, name: &str| children(rule, name).into_iter().filter_map(|child| child.get_text().map(|text| text.to_string())).collect();
+        let cors_rules = children(&root, "CORSRule").into_iter().map(|rule| CorsRule {
+            id: text(rule, "ID"),
+            allowed_headers: texts(rule, "AllowedHeader"),
+            allowed_methods: texts(rule, "AllowedMethod"),
+            allowed_origins: texts(rule, "AllowedOrigin"),
+            expose_headers: texts(rule, "ExposeHeader"),
+            max_age_seconds: number(rule, "MaxAgeSeconds"),
+        }).collect();
+        Ok(Cors { cors_rules })
+    }
+}
+
+fn check_id(id: &Option<String>) -> Result<(), String> {
+    match id {
+        Some(id) if id.chars().count() > MAX_ID_LEN => Err(format!("rule ID {}... is longer than {} characters", id.chars().take(20).collect::<String>(), MAX_ID_LEN)),
+        _ => Ok(()),
+    }
+}
+
+fn check_days(rule: &str, what: &str, days: Option<u32>) -> Result<(), String> {
+    match days {
+        Some(0) => Err(format!("rule {}: {} must be at least 1", rule, what)),
+        _ => Ok(()),
+    }
+}
+
+// lifecycle dates must be midnight UTC in ISO 8601
+fn check_date(rule: &str, date: &Option<String>) -> Result<(), String> {
+    match date {
+        Some(date) if chrono::NaiveDate::parse_from_str(date.trim_end_matches("T00:00:00Z").trim_end_matches("T00:00:00.000Z"), "%Y-%m-%d").is_err() => {
+            Err(format!("rule {}: Date {} must look like 2025-01-31 or 2025-01-31T00:00:00Z", rule, date))
+        }
+        _ => Ok(()),
+    }
+}
+
+// S3 wants lifecycle dates as midnight UTC timestamps, a bare 2025-01-31 is taken as that
+fn midnight(date: &str) -> String {
+    match chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        Ok(_) => format!("{}T00:00:00Z", date),
+        Err(_) => date.to_string(),
+    }
+}
+
+fn tag_xml(filter_tag: &Tag) -> String {
+    tag("Tag", &(tag("Key", &escape(&filter_tag.key)) + &tag("Value", &escape(&filter_tag.value))))
+}
+
+fn tag_of(element: &Element) -> Tag {
+    Tag { key: text(element, "Key").unwrap_or_default(), value: text(element, "Value").unwrap_or_default() }
+}
+
+fn tag(name: &str, content: &str) -> String {
+    format!("<{}>{}</{}>", name, content, name)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+fn children<'a>(element: &'a Element, name: &str) -> Vec<&'a Element> {
+    element.children.iter()
+        .filter_map(|child| match child {
+            XMLNode::Element(child) if child.name == name => Some(child),
+            _ => None,
+        })
+        .collect()
+}
+
+fn text(element: &Element, name: &str) -> Option<String> {
+    element.get_child(name).map(|child| child.get_text().map(|text| text.to_string()).unwrap_or_default())
+}
+
+fn number(element: &Element, name: &str) -> Option<u32> {
+    text(element, name).and_then(|number| number.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lifecycle(json: &str) -> Lifecycle {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn lifecycle_rules_are_checked() {
+        let valid = lifecycle(r#"{"Rules":[{"ID":"logs","Status":"Enabled","Filter":{"Prefix":"logs/"},"Expiration":{"Days":30}}]}"#);
+        assert_eq!(valid.validate(), Ok(()));
+        for (json, error) in [
+            (r#"{"Rules":[]}"#, "1 to 1000 rules"),
+            (r#"{"Rules":[{"Status":"On","Expiration":{"Days":30}}]}"#, "Enabled or Disabled"),
+            (r#"{"Rules":[{"Status":"Enabled"}]}"#, "needs an action"),
+            (r#"{"Rules":[{"Status":"Enabled","Expiration":{"Days":0}}]}"#, "at least 1"),
+            (r#"{"Rules":[{"Status":"Enabled","Expiration":{"Days":1,"Date":"2025-01-31"}}]}"#, "exactly one"),
+            (r#"{"Rules":[{"Status":"Enabled","Expiration":{"Date":"31.01.2025"}}]}"#, "must look like"),
+            (r#"{"Rules":[{"Status":"Enabled","Transitions":[{"StorageClass":"GLACIER"}]}]}"#, "either Days or Date"),
+            (r#"{"Rules":[{"Status":"Enabled","Filter":{"Prefix":"a","Tag":{"Key":"k","Value":"v"}},"Expiration":{"Days":1}}]}"#, "one of Prefix, Tag or And"),
+            (r#"{"Rules":[{"Status":"Enabled","Filter":{"And":{"Prefix":"a"}},"Expiration":{"Days":1}}]}"#, "at least two"),
+            (r#"{"Rules":[{"Status":"Enabled","Filter":{"Tag":{"Key":"k","Value":"v"}},"AbortIncompleteMultipartUpload":{"DaysAfterInitiation":7}}]}"#, "Tag filter"),
+        ] {
+            let err = lifecycle(json).validate().unwrap_err();
+            assert!(err.contains(error), "{} gave {}", json, err);
+        }
+    }
+
+    #[test]
+    fn bare_dates_are_sent_as_midnight() {
+        let rules = lifecycle(r#"{"Rules":[{"Status":"Enabled","Expiration":{"Date":"2025-01-31"},
+            "Transitions":[{"Date":"2025-01-01T00:00:00Z","StorageClass":"GLACIER"}]}]}"#);
+        assert_eq!(rules.validate(), Ok(()));
+        let xml = rules.to_xml();
+        assert!(xml.contains("<Expiration><Date>2025-01-31T00:00:00Z</Date></Expiration>"), "{}", xml);
+        assert!(xml.contains("<Date>2025-01-01T00:00:00Z</Date><StorageClass>GLACIER</StorageClass>"), "{}", xml);
+    }
+
+    #[test]
+    fn lifecycle_xml_round_trip() {
+        let rules = lifecycle(r#"{"Rules":[
+            {"ID":"a&b","Status":"Enabled","Filter":{"Prefix":"logs/"},"Expiration":{"Days":30},
+             "Transitions":[{"Days":7,"StorageClass":"STANDARD_IA"}],"NoncurrentVersionExpiration":{"NoncurrentDays":10},
+             "AbortIncompleteMultipartUpload":{"DaysAfterInitiation":2}},
+            {"Status":"Disabled","Filter":{"Tag":{"Key":"k","Value":"v<1>"}},"Expiration":{"ExpiredObjectDeleteMarker":true}},
+            {"Status":"Enabled","Filter":{"And":{"Prefix":"tmp/","Tags":[{"Key":"a","Value":"1"},{"Key":"b","Value":"2"}]}},"Expiration":{"Days":1}}]}"#);
+        assert_eq!(rules.validate(), Ok(()));
+        let back = Lifecycle::from_xml(&rules.to_xml()).unwrap();
+        assert_eq!(serde_json::to_value(&back).unwrap(), serde_json::to_value(&rules).unwrap());
+    }
+
+    #[test]
+    fn old_rule_prefixes_are_read() {
+        let xml = "<LifecycleConfiguration><Rule><Prefix>old/</Prefix><Status>Enabled</Status><Expiration><Days>3</Days></Expiration></Rule></LifecycleConfiguration>";
+        let rules = Lifecycle::from_xml(xml).unwrap();
+        assert_eq!(rules.rules[0].filter.as_ref().and_then(|filter| filter.prefix.as_deref()), Some("old/"));
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use colored::Colorize;
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+use crate::config::{config_folder, default_profile, profile_exists, set_default_profile, S3Config};
+use crate::console::{ask, ask_secret};
+use crate::crypto::{decrypt_with_key, encrypt_with_key, passphrase_key, PASSPHRASE_LANES, PASSPHRASE_MEM_KIB, PASSPHRASE_PASSES};
+use crate::envelope::fingerprint;
+use crate::migrate::backup;
+
+const BEGIN: &str = "-----BEGIN S3PO CONFIG BUNDLE-----";
+const END: &str = "-----END S3PO CONFIG BUNDLE-----";
+// layout of the binary part, raised when it changes
+const BUNDLE_FORMAT: u8 = 1;
+const SALT_LEN: usize = 16;
+// format, memory, passes, lanes and salt come before the sealed json
+const HEADER_LEN: usize = 1 + 4 + 4 + 4 + SALT_LEN;
+// passphrase for scripts, asked for when not set
+const PASSPHRASE_VAR: &str = "S3PO_BUNDLE_PASSPHRASE";
+const MIN_PASSPHRASE_LEN: usize = 8;
+// file in the config folder with one "<time> <key fingerprint> <where>" line per key backup
+const KEY_BACKUP_LOG: &str = "key-backups";
+/// Prefix of the bundles `config backup` stores in a bucket.
+pub(crate) const CONFIG_BACKUP_PREFIX: &str = "s3po-config/";
+
+/// The configs of one machine, sealed in a bundle.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub(crate) struct Bundle {
+    pub(crate) created: String,
+    /// the config loaded on start, if one was set
+    pub(crate) default: Option<String>,
+    /// every .toml file of the config folder by file name
+    pub(crate) files: BTreeMap<String, String>,
+}
+
+impl Bundle {
+    /// Reads all configs from the config folder.
+    pub(crate) fn collect() -> Result<Bundle, String> {
+        let folder = config_folder();
+        let mut files = BTreeMap::new();
+        let entries = fs::read_dir(&folder).map_err(|err| format!("cant read config folder {}: {}", folder.display(), err))?;
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if !file_name.ends_with(".toml") || !entry.path().is_file() {
+                continue;
+            }
+            let text = fs::read_to_string(entry.path()).map_err(|err| format!("cant read {}: {}", entry.path().display(), err))?;
+            files.insert(file_name, text);
+        }
+        if files.is_empty() {
+            return Err(format!("there are no configs in {}", folder.display()));
+        }
+        Ok(Bundle { created: chrono::Utc::now().to_rfc3339(), default: default_profile(), files })
+    }
+
+    /// Fingerprints of the public keys of all configs in the bundle.
+    pub(crate) fn fingerprints(&self) -> Vec<String> {
+        self.files.values()
+            .filter_map(|text| text.parse::<toml::Table>().ok())
+            .filter_map(|table| table.get("pk_bs58").and_then(|pk| pk.as_str()).map(key_fingerprint))
+            .filter(|fingerprint| !fingerprint.is_empty())
+            .collect()
+    }
+
+    /// Encrypts the bundle with a key derived from `passphrase` and armors it as text.
+    pub(crate) fn seal(&self, passphrase: &str) -> Result<String, String> {
+        self.seal_with(passphrase, [PASSPHRASE_MEM_KIB, PASSPHRASE_PASSES, PASSPHRASE_LANES])
+    }
+
+    // seal with the given argon2 memory, passes and lanes
+    fn seal_with(&self, passphrase: &str, params: [u32; 3]) -> Result<String, String> {
+        let salt: [u8; SALT_LEN] = rand::thread_rng().gen();
+        let [mem_kib, passes, lanes] = params;
+        let key = passphrase_key(passphrase, &salt, mem_kib, passes, lanes)?;
+        let json = serde_json::to_vec(self).map_err(|err| format!("cant serialize the bundle: {}", err))?;
+
+        let mut sealed = vec![BUNDLE_FORMAT];
+        for param in params {
+            sealed.extend(param.to_le_bytes());
+        }
+        sealed.extend(salt);
+        sealed.extend(encrypt_with_key(&key, &json));
+
+        let encoded = STANDARD.encode(&sealed);
+        let lines: Vec<&str> = encoded.as_bytes().chunks(76).map(|line| std::str::from_utf8(line).unwrap_or_default()).collect();
+        Ok(format!("{}\n{}\n{}\n", BEGIN, lines.join("\n"), END))
+    }
+
+    /// Reverses `seal`. Text around the armor, like log lines, is ignored.
+    pub(crate) fn open(text: &str, passphrase: &str) -> Result<Bundle, String> {
+        let start = text.find(BEGIN).ok_or("no s3po config bundle found")? + BEGIN.len();
+        let end = text[start..].find(END).ok_or("the config bundle is cut off")? + start;
+        let encoded: String = text[start..end].chars().filter(|c| !c.is_whitespace()).collect();
+        let sealed = STANDARD.decode(encoded).map_err(|err| format!("the config bundle is damaged: {}", err))?;
+        if sealed.len() < HEADER_LEN {
+            return Err("the config bundle is cut off".to_string());
+        }
+        if sealed[0] != BUNDLE_FORMAT {
+            return Err(format!("the config bundle has format {}, this s3po reads format {}", sealed[0], BUNDLE_FORMAT));
+        }
+        let param = |at: usize| u32::from_le_bytes([sealed[at], sealed[at + 1], sealed[at + 2], sealed[at + 3]]);
+        let (mem_kib, passes, lanes) = (param(1), param(5), param(9));
+        // the bundle may come from anywhere, it must not make the key derivation costlier than seal does
+        if mem_kib > PASSPHRASE_MEM_KIB || passes > PASSPHRASE_PASSES || lanes > PASSPHRASE_LANES {
+            return Err(format!("the config bundle asks for {} KiB, {} passes and {} lanes of key derivation, more than s3po uses, refusing it", mem_kib, passes, lanes));
+        }
+        let key = passphrase_key(passphrase, &sealed[13..HEADER_LEN], mem_kib, passes, lanes)?;
+        let json = decrypt_with_key(&key, &sealed[HEADER_LEN..]).ok_or("wrong passphrase or a changed bundle")?;
+        serde_json::from_slice(&json).map_err(|err| format!("the config bundle is damaged: {}", err))
+    }
+
+    /// Writes the configs into the config folder. An existing config that differs is
+    /// kept, replaced after a backup or imported under a new name, as the user answers.
+    pub(crate) fn merge(&self) {
+        let folder = config_folder();
+        if let Err(err) = fs::create_dir_all(&folder) {
+            println!("cant create config folder {}: {}", folder.display(), err);
+            return;
+        }
+        for (file_name, text) in &self.files {
+            // a crafted bundle must not write outside the config folder
+            if file_name.contains(['/', '\\']) || file_name.starts_with('.') || !file_name.ends_with(".toml") {
+                println!("{} {}", "skipped bundle entry".yellow(), file_name.yellow());
+                continue;
+            }
+            let path = folder.join(file_name);
+            let name = file_name.trim_end_matches(".toml");
+            let target = match fs::read_to_string(&path) {
+                Err(_) => path,
+                Ok(local) if local == *text => {
+                    println!("config {} is unchanged", name);
+                    continue;
+                }
+                Ok(_) => match ask(&format!("config {} differs from the one in the bundle: keep it, replace it or import as a new config? (k/r/n)", name)).to_lowercase().as_str() {
+                    "r" | "replace" => match backup(&path) {
+                        Ok(backup_path) => {
+                            println!("{}: {}", "the local config is kept as".blue(), backup_path.display());
+                            path
+                        }
+                        Err(err) => {
+                            println!("{}", err.red());
+                            continue;
+                        }
+                    },
+                    "n" | "new" => {
+                        let new_name = (1..).map(|n| format!("{}-imported{}", name, if n == 1 { String::new() } else { n.to_string() }))
+                            .find(|new_name| !profile_exists(new_name))
+                            .unwrap_or_default();
+                        folder.join(format!("{}.toml", new_name))
+                    }
+                    _ => {
+                        println!("config {} kept", name);
+                        continue;
+                    }
+                },
+            };
+            match fs::write(&target, text) {
+                Ok(_) => println!("config {} imported", target.file_stem().unwrap_or_default().to_string_lossy().green()),
+                Err(err) => println!("cant write {}: {}", target.display(), err),
+            }
+        }
+        if let Some(default) = &self.default {
+            if default_profile().is_none() && profile_exists(default) {
+                set_default_profile(default);
+            }
+        }
+    }
+}
+
+/// `config export --all`: seals all configs with a passphrase and writes the bundle to
+/// `file`, or to stdout when it is redirected.
+pub(crate) fn export_bundle(file: Option<String>) {
+    if file.is_none() && io::stdout().is_terminal() {
+        println!("{}", "the bundle goes to stdout, redirect it: s3po config export --all > configs.s3po".yellow());
+        return;
+    }
+    let bundle = match Bundle::collect() {
+        Ok(bundle) => bundle,
+        Err(err) => {
+            eprintln!("{}", err.red());
+            return;
+        }
+    };
+    let Some(passphrase) = new_passphrase() else { return };
+    let armored = match bundle.seal(&passphrase) {
+        Ok(armored) => armored,
+        Err(err) => {
+            eprintln!("{}", err.red());
+            return;
+        }
+    };
+    let written = match &file {
+        Some(file) => fs::write(file, armored),
+        None => io::stdout().write_all(armored.as_bytes()),
+    };
+    match written {
+        Ok(_) => {
+            record_key_backup(&bundle, file.as_deref().unwrap_or("a bundle on stdout"));
+            eprintln!("{} configs sealed{}", bundle.files.len(), file.map(|file| format!(" into {}", file)).unwrap_or_default());
+        }
+        Err(err) => eprintln!("cant write the bundle: {}", err),
+    }
+}
+
+/// `config import <bundle>`: opens a bundle and merges its configs.
+pub(crate) fn import_bundle(file: &str) {
+    let text = match fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(err) => {
+            println!("cant read {}: {}", file, err);
+            return;
+        }
+    };
+    match Bundle::open(&text, &passphrase()) {
+        Ok(bundle) => {
+            println!("bundle from {} with {} configs", bundle.created, bundle.files.len());
+            bundle.merge();
+        }
+        Err(err) => println!("{}", err.red()),
+    }
+}
+
+/// Whether `path` names a config bundle rather than the config of another tool.
+pub(crate) fn is_bundle(path: &str) -> bool {
+    path.ends_with(".s3po") || fs::read_to_string(path).is_ok_and(|text| text.contains(BEGIN))
+}
+
+/// The passphrase of a bundle to open, from S3PO_BUNDLE_PASSPHRASE or asked for.
+pub(crate) fn passphrase() -> String {
+    env::var(PASSPHRASE_VAR).unwrap_or_else(|_| ask_secret("Bundle passphrase"))
+}
+
+/// Asks for a new passphrase twice, or takes it from S3PO_BUNDLE_PASSPHRASE.
+pub(crate) fn new_passphrase() -> Option<String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_VAR) {
+        return Some(passphrase);
+    }
+    let passphrase = ask_secret("Passphrase for the bundle");
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        eprintln!("{}", format!("the passphrase needs at least {} characters", MIN_PASSPHRASE_LEN).yellow());
+        return None;
+    }
+    if ask_secret("Repeat the passphrase") != passphrase {
+        eprintln!("{}", "the passphrases differ".yellow());
+        return None;
+    }
+    Some(passphrase)
+}
+
+/// Notes that the keys in `bundle` were saved to `destination`.
+pub(crate) fn record_key_backup(bundle: &Bundle, destination: &str) {
+    let path = config_folder().join(KEY_BACKUP_LOG);
+    let mut log = fs::read_to_string(&path).unwrap_or_default();
+    let time = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    for fingerprint in bundle.fingerprints() {
+        log += &format!("{} {} {}\n", time, fingerprint, destination);
+    }
+    if let Err(err) = fs::write(&path, log) {
+        eprintln!("cant note the key backup in {}: {}", path.display(), err);
+    }
+}
+
+/// When and where the key with `pk_bs58` was last backed up, None if never.
+pub(crate) fn last_key_backup(pk_bs58: &str) -> Option<String> {
+    let fingerprint = key_fingerprint(pk_bs58);
+    let log = fs::read_to_string(config_folder().join(KEY_BACKUP_LOG)).ok()?;
+    log.lines().rev()
+        .filter_map(|line| line.split_once(' ').map(|(time, rest)| (time, rest.split_once(' ').unwrap_or((rest, "")))))
+        .find(|(_, (logged, _))| !fingerprint.is_empty() && *logged == fingerprint)
+        .map(|(time, (_, destination))| format!("{} to {}", time, destination))
+}
+
+/// Warns on stderr when the secret key of `config` has never been backed up.
+pub(crate) fn warn_without_key_backup(config: &S3Config) {
+    if config.sk_bs58.trim().is_empty() || last_key_backup(&config.pk_bs58).is_some() {
+        return;
+    }
+    eprintln!("{}", "!!! the encryption key of this config has never been backed up !!!".red().bold());
+    eprintln!("{}", "losing this machine means losing every encrypted file, run config backup <bucket> or config export --all".red());
+}
+
+// fingerprint of a bs58 public key, empty when it is not one
+pub(crate) fn key_fingerprint(pk_bs58: &str) -> String {
+    match bs58::decode(pk_bs58.trim()).into_vec() {
+        Ok(pk) if !pk.is_empty() => fingerprint(&pk),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cheap argon2 settings, the real ones take a second per derivation
+    const TEST_PARAMS: [u32; 3] = [1024, 1, 1];
+
+    fn bundle() -> Bundle {
+        let mut files = BTreeMap::new();
+        files.insert("work.toml".to_string(), "name = \"work\"\n".to_string());
+        Bundle { created: "2026-01-01T00:00:00+00:00".to_string(), default: Some("work".to_string()), files }
+    }
+
+    #[test]
+    fn seal_open_round_trip() {
+        let armored = bundle().seal_with("correct horse", TEST_PARAMS).unwrap();
+        assert!(armored.starts_with(BEGIN) && armored.trim_end().ends_with(END));
+        let opened = Bundle::open(&format!("log line\n{}more log\n", armored), "correct horse").unwrap();
+        assert_eq!(opened.files, bundle().files);
+        assert_eq!(opened.default, bundle().default);
+    }
+
+    #[test]
+    fn wrong_passphrase_is_refused() {
+        let armored = bundle().seal_with("correct horse", TEST_PARAMS).unwrap();
+        assert_eq!(Bundle::open(&armored, "wrong horse").unwrap_err(), "wrong passphrase or a changed bundle");
+    }
+
+    #[test]
+    fn oversized_params_are_refused() {
+        for (at, oversized) in [(1, PASSPHRASE_MEM_KIB + 1), (5, PASSPHRASE_PASSES + 1), (9, PASSPHRASE_LANES + 1), (1, u32::MAX)] {
+            let armored = bundle().seal_with("correct horse", TEST_PARAMS).unwrap();
+            let start = armored.find(BEGIN).unwrap() + BEGIN.len();
+            let end = armored.find(END).unwrap();
+            let encoded: String = armored[start..end].chars().filter(|c| !c.is_whitespace()).collect();
+            let mut sealed = STANDARD.decode(encoded).unwrap();
+            sealed[at..at + 4].copy_from_slice(&oversized.to_le_bytes());
+            let crafted = format!("{}\n{}\n{}\n", BEGIN, STANDARD.encode(&sealed), END);
+            assert!(Bundle::open(&crafted, "correct horse").unwrap_err().contains("refusing it"));
+        }
+    }
+
+    #[test]
+    fn damaged_bundles_are_refused() {
+        assert!(Bundle::open("nothing here", "pass").is_err());
+        assert!(Bundle::open(&format!("{}\nAAAA\n{}", BEGIN, END), "pass").is_err());
+        let armored = bundle().seal_with("correct horse", TEST_PARAMS).unwrap();
+        assert!(Bundle::open(&armored[..armored.len() / 2], "correct horse").is_err());
+    }
+}
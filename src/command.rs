@@ -1,48 +1,100 @@
 use std::env;
 use std::process::exit;
 use colored::Colorize;
-use crate::config::S3Config;
-use crate::s3::S3Client;
+use crate::config::{has_configs, select_profile, S3Config};
+use crate::console::execute;
+use crate::s3::{fetch, S3Client};
+use crate::throttle::{parse_rate, set_override};
+
+/// Removes `--name value` or `--name=value` from `args` and returns the value.
+pub(crate) fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    let pos = args.iter().position(|arg| arg == name || arg.starts_with(&prefix))?;
+    let arg = args.remove(pos);
+    if let Some(value) = arg.strip_prefix(&prefix) {
+        return Some(value.to_string());
+    }
+    if pos < args.len() {
+        return Some(args.remove(pos));
+    }
+    println!("{} {}", name.yellow(), "needs a value".yellow());
+    None
+}
+
+/// Removes a boolean flag given under any of `names` from `args`, returns whether it was there.
+pub(crate) fn take_flag(args: &mut Vec<String>, names: &[&str]) -> bool {
+    let before = args.len();
+    args.retain(|arg| !names.contains(&arg.as_str()));
+    args.len() != before
+}
+
+/// Splits `bucket/key/with/slashes` into bucket and key. A path without a slash
+/// is a key in `current_bucket` when one is selected with `cd`, otherwise a bucket.
+pub(crate) fn split_remote(path: &str, current_bucket: &str) -> (String, String) {
+    let path = path.trim_start_matches('/');
+    match path.split_once('/') {
+        Some((bucket, key)) => (bucket.to_string(), key.to_string()),
+        None if !current_bucket.is_empty() => (current_bucket.to_string(), path.to_string()),
+        None => (path.to_string(), String::new()),
+    }
+}
+
+// options that are accepted before any command, in console and command line mode
+fn apply_global_options(args: &mut Vec<String>) {
+    if let Some(config_name) = take_option(args, "--config") {
+        select_profile(&config_name);
+    }
+    if let Some(rate) = take_option(args, "--limit-rate") {
+        match parse_rate(&rate) {
+            Ok(rate) => set_override(rate),
+            Err(err) => {
+                println!("{}", err.red());
+                exit(1);
+            }
+        }
+    }
+}
 
 pub(crate) async fn parse_args() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    apply_global_options(&mut args);
     if args.len() < 2 || args[1].trim() == "console" {
         return;
     }
 
-    println!("command line mode");
+    // share links are fetched by people who have no config at all
+    if args[1] == "fetch" {
+        let local_file_path = take_option(&mut args, "-o");
+        match args.get(2) {
+            Some(url) => fetch(url.clone(), local_file_path).await,
+            None => println!("{}", "usage: s3po fetch <url> [-o <file name>]".yellow()),
+        }
+        exit(0);
+    }
+
+    // a new machine has no config yet, the endpoint is asked for to fetch the backup
+    if args[1] == "config" && args.get(2).map(String::as_str) == Some("restore") && !has_configs() {
+        let object_name = take_option(&mut args, "--key");
+        match args.get(3) {
+            Some(bucket_name) => {
+                let mut config = S3Config::default();
+                config.ask_endpoint();
+                S3Client { config, bucket: String::new() }.restore_config(bucket_name.clone(), object_name).await;
+                if has_configs() {
+                    println!("{}", "run s3po again to work with the restored configs".green());
+                }
+            }
+            None => println!("{}", "usage: s3po config restore <bucket> [--key <object>]".yellow()),
+        }
+        exit(0);
+    }
+
+    eprintln!("command line mode");
 
     let mut conf: S3Config = S3Config::init();
     let mut s3cli = S3Client{ config: conf.clone(), bucket: "".to_string() };
 
-    if args[1].trim() == "ls" {
-        println!("ls");
-        if args.len() == 3 {
-            s3cli.ls(args[2].trim().to_string()).await;
-        } else {
-            s3cli.ls("".to_string()).await;
-        }
-    } else if args[1].trim() == "mkdir" {
-        println!("mkdir");
-    } else if args[1].trim() == "get" {
-        println!("get");
-        if args.len() > 3 {
-            s3cli.get_file_encrypted(args[2].to_string(), args[3].to_string(), args[3].to_string()).await;
-        } else {
-            println!("{}", "error getting file... too less args".blue());
-        }
-    } else if args[1].trim() == "put" {
-        println!("put");
-        if args.len() > 3 {
-            s3cli.put_file_encrypted(args[2].to_string(), args[3].to_string(), args[3].to_string()).await;
-        } else {
-            println!("{}", "error putting file... too less args".blue());
-        }
-    } else if args[1].trim() == "rm" {
-        println!("rm");
-    } else if args[1].trim() == "config" {
-        println!("config");
-    }
+    execute(&args[1..], &mut conf, &mut s3cli).await;
 
     exit(0);
-}
\ No newline at end of file
+}
@@ -1,4 +1,7 @@
+use std::env;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::process::exit;
 use colored::Colorize;
 use serde_derive::{Deserialize, Serialize};
@@ -7,15 +10,62 @@ use crate::crypto::{gen_new_keys};
 use chrono::offset::Utc;
 use chrono::DateTime;
 use confy::ConfyError;
+use crate::bundle::{key_fingerprint, last_key_backup, warn_without_key_backup};
+use crate::creds::{resolve, CredentialSource};
+use crate::migrate::{backup, migrate, CONFIG_VERSION};
+use crate::endpoint::{base_url, check_endpoint, describe_endpoint, validate};
+use crate::retry::RetryPolicy;
+use crate::rules::{check_rules, Rule};
+use crate::sse::{check_sse, Sse};
+use crate::throttle::{check_limits, describe};
+
+// profile loaded when none is selected or set as default
+const FALLBACK_PROFILE: &str = "default-config";
+// file in the config folder that holds the name of the default profile
+const DEFAULT_POINTER: &str = "default-profile";
+// folder in the config folder that deleted configs are moved to
+const TRASH: &str = "trash";
+
+// profile given with --config, it wins over S3PO_CONFIG and the default
+static SELECTED: Mutex<Option<String>> = Mutex::new(None);
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub(crate) struct S3Config {
+    /// schema version, see migrate.rs
+    #[serde(default)]
+    pub(crate) version: u32,
     pub(crate) name: String,
     pub(crate) base_url: String,
+    #[serde(default)]
     pub(crate) access_key: String,
+    #[serde(default)]
     pub(crate) secret_key: String,
     pub(crate) sk_bs58: String,
     pub(crate) pk_bs58: String,
+    #[serde(default)]
+    pub(crate) limit_rate: String,
+    #[serde(default)]
+    pub(crate) limit_schedule: Vec<String>,
+    #[serde(default)]
+    pub(crate) retry: RetryPolicy,
+    #[serde(default)]
+    pub(crate) sse: String,
+    #[serde(default)]
+    pub(crate) credentials: CredentialSource,
+    #[serde(default)]
+    pub(crate) region: String,
+    #[serde(default)]
+    pub(crate) use_https: Option<bool>,
+    #[serde(default)]
+    pub(crate) port: Option<u16>,
+    #[serde(default)]
+    pub(crate) addressing_style: String,
+    #[serde(default)]
+    pub(crate) ca_bundle: String,
+    #[serde(default)]
+    pub(crate) insecure_skip_verify: bool,
+    #[serde(default)]
+    pub(crate) rules: Vec<Rule>,
 }
 
 impl S3Config {
@@ -23,41 +73,136 @@ impl S3Config {
         println!("{}", "    Create new config ... ".red());
         let mut cfg: S3Config = S3Config::default();
         cfg.name = ask("Please enter config name: ");
-        cfg.base_url = ask("Please enter the s3 base url: ");
-        cfg.access_key = ask("Please enter the s3 access key: ");
-        cfg.secret_key = ask("Please enter the s3 secret key: ");
+        cfg.ask_endpoint();
         let mut cfg = gen_new_keys(cfg);
         if cfg.name.trim() == String::from("")  {
             cfg.name = "default".parse().unwrap();
         }
         confy::store("s3po", Some(cfg.name.as_str()), cfg.clone()).expect("error writing config ...");
+        let make_default = match default_profile() {
+            None => true,
+            Some(current) => current != cfg.name && y_or_n(&format!("Load {} instead of {} on the next start?", cfg.name, current)),
+        };
+        if make_default {
+            set_default_profile(&cfg.name);
+        }
         cfg
     }
-    pub(crate) fn delete(self, config_name: String) {
-        if !config_name.ends_with(".toml") {
-            let config_name = config_name.clone() + ".toml";
+
+    /// Asks for the endpoint and the s3 keys, until the endpoint is valid.
+    pub(crate) fn ask_endpoint(&mut self) {
+        loop {
+            self.base_url = ask("Please enter the s3 base url: ");
+            self.region = ask("Please enter the s3 region (empty - from the url or the server): ");
+            if base_url(self).is_ok_and(|base_url| base_url.https) {
+                self.ca_bundle = ask("Please enter a CA bundle file for a private CA (empty - system certificates): ");
+            }
+            match validate(self) {
+                Ok(_) => break,
+                Err(err) => println!("{}", err.red()),
+            }
         }
-        let config_folder = self.get_config_folder();
-        let config_path = config_folder + "/" + &*config_name;
-        match fs::remove_file(config_path) {
-            Ok(_) => println!("Config deleted successfully!"),
-            Err(err) => println!("Error deleting config file: {}", err),
+        self.access_key = ask("Please enter the s3 access key (empty - from environment, credentials file or credential process): ");
+        if self.access_key.trim().is_empty() {
+            println!("{}", "set the [credentials] table of the config to pick the source, see README".yellow());
+        } else {
+            self.secret_key = ask("Please enter the s3 secret key: ");
         }
     }
 
-    pub(crate) fn load(config_name: String) -> S3Config {
-        if config_name.ends_with(".toml") {
-            let config_name = String::from(config_name.strip_suffix(".toml").unwrap());
+    /// Moves a config to the trash after the user typed its name, `config undelete`
+    /// brings it back. The loaded config and the one loaded on start are refused.
+    pub(crate) fn delete(&self, config_name: String) {
+        let config_name = profile_name(&config_name);
+        let config_path = config_folder().join(format!("{}.toml", config_name));
+        if config_name.is_empty() || !config_path.is_file() {
+            println!("{} {}", "there is no config".yellow(), config_name.yellow());
+            return;
+        }
+        if config_name == self.name {
+            println!("{}", format!("config {} is loaded, load another one with config use before deleting it", config_name).yellow());
+            return;
+        }
+        if default_profile().as_deref() == Some(config_name.as_str()) {
+            println!("{}", format!("config {} is loaded on start, make another one the default with config default <name> before deleting it", config_name).yellow());
+            return;
+        }
+        let pk_bs58 = config_key(&config_path);
+        let fingerprint = key_fingerprint(&pk_bs58);
+        if !fingerprint.is_empty() && last_key_backup(&pk_bs58).is_none() && !key_in_other_config(&pk_bs58, &config_name) {
+            println!("{}", format!("!!! the key {} of config {} is not backed up anywhere !!!", fingerprint, config_name).red().bold());
+            println!("{}", "every file encrypted to it is lost once the trash is emptied, run config export --all or config backup <bucket> first".red());
+        }
+        println!("config {} with key {}", config_name.green(), if fingerprint.is_empty() { "none".to_string() } else { fingerprint });
+        if ask("type the name of the config to delete it: ").trim() != config_name {
+            println!("{}", "the name doesn't match, nothing was deleted".yellow());
+            return;
+        }
+        let trash = config_folder().join(TRASH);
+        let trash_path = trash_path(&trash, &config_name, &chrono::Local::now().format("%Y%m%d-%H%M%S").to_string());
+        match fs::create_dir_all(&trash).and_then(|_| fs::rename(&config_path, &trash_path)) {
+            Ok(_) => println!("config {} moved to {}, config undelete {} brings it back", config_name, trash_path.display(), config_name),
+            Err(err) => println!("cant move config {} to the trash: {}", config_name, err),
         }
-        confy::load("s3po", Some(config_name.as_str())).expect("error loading config ... ")
     }
 
+    /// Lists the trash, or moves the newest deleted config with that name back.
+    pub(crate) fn undelete(config_name: Option<String>) {
+        let mut trashed = trashed_configs();
+        let Some(config_name) = config_name.map(|name| profile_name(&name)) else {
+            if trashed.is_empty() {
+                println!("the trash is empty");
+            }
+            for (name, stamp, _) in trashed {
+                println!("{} deleted {}", name, stamp);
+            }
+            return;
+        };
+        trashed.retain(|(name, _, _)| *name == config_name);
+        let Some((_, _, trash_path)) = trashed.pop() else {
+            println!("{} {}", "there is no deleted config".yellow(), config_name.yellow());
+            return;
+        };
+        let config_path = config_folder().join(format!("{}.toml", config_name));
+        if config_path.exists() {
+            println!("{}", format!("config {} exists, delete it first, the deleted one stays in {}", config_name, trash_path.display()).yellow());
+            return;
+        }
+        match fs::rename(&trash_path, &config_path) {
+            Ok(_) => println!("config {} is back, config use {} loads it", config_name.green(), config_name),
+            Err(err) => println!("cant move config {} out of the trash: {}", config_name, err),
+        }
+    }
 
-    fn recreate_or_fix() -> S3Config {
-        let config_path = confy::get_configuration_file_path("s3po", None).expect("can't get config path ...");
+    pub(crate) fn load(config_name: String) -> Result<S3Config, String> {
+        let config_name = profile_name(&config_name);
+        let config_path = confy::get_configuration_file_path("s3po", Some(config_name.as_str())).map_err(|err| err.to_string())?;
+        migrate(&config_path, &config_name)?;
+        let mut cfg: S3Config = confy::load("s3po", Some(config_name.as_str())).map_err(|err| format!("error loading config {}: {}", config_name, err))?;
+        cfg.name = config_name;
+        check_limits(&cfg);
+        check_sse(&cfg);
+        check_credentials(&cfg);
+        check_endpoint(&cfg);
+        check_rules(&cfg);
+        Ok(cfg)
+    }
+
+
+    fn recreate_or_fix(config_name: &str) -> S3Config {
+        let config_path = confy::get_configuration_file_path("s3po", Some(config_name)).expect("can't get config path ...");
         println!("{}: {}", "can't load config from".blue(), config_path.to_str().unwrap());
         let recreate: bool = y_or_n("You want to rewrite new config file? (or fix it manually)");
         if recreate {
+            if config_path.exists() {
+                match backup(&config_path) {
+                    Ok(backup_path) => println!("{}: {}", "the old config is kept as".blue(), backup_path.display()),
+                    Err(err) => {
+                        println!("{}", err.red());
+                        exit(1);
+                    }
+                }
+            }
             return Self::create()
         } else {
             println!("{}: {}", "fix this config file manually".red(), config_path.to_str().unwrap());
@@ -66,23 +211,48 @@ impl S3Config {
         }
     }
     fn check(&self) -> bool {
-        self.name.trim() == "" || self.base_url.trim() == "" || self.access_key.trim() == "" || self.secret_key.trim() == "" || self.sk_bs58.trim() == "" || self.pk_bs58.trim() == ""
+        self.name.trim() == "" || self.base_url.trim() == "" || self.sk_bs58.trim() == "" || self.pk_bs58.trim() == ""
     }
     pub(crate) fn init() -> S3Config {
-        let cfg:Result<S3Config, ConfyError> = confy::load("s3po", Some("default-config"));
+        let (config_name, chosen_by) = startup_profile();
+        let config_name = match (profile_exists(&config_name), chosen_by) {
+            (true, _) => config_name,
+            (false, Some(chosen_by)) => {
+                println!("{} {} {}", "there is no config".red(), config_name.red(), format!("(from {}), see config list", chosen_by).red());
+                exit(1);
+            }
+            (false, None) if config_name != FALLBACK_PROFILE => {
+                println!("{}", format!("the default config {} is gone, loading {}", config_name, FALLBACK_PROFILE).yellow());
+                FALLBACK_PROFILE.to_string()
+            }
+            (false, None) => config_name,
+        };
+        let config_path = confy::get_configuration_file_path("s3po", Some(config_name.as_str())).expect("can't get config path ...");
+        // a config that can't be migrated must not reach recreate_or_fix, which rewrites it
+        if let Err(err) = migrate(&config_path, &config_name) {
+            println!("{}", err.red());
+            exit(1);
+        }
+        let cfg:Result<S3Config, ConfyError> = confy::load("s3po", Some(config_name.as_str()));
         match cfg {
-            Ok(cfg) => {
+            Ok(mut cfg) => {
+                cfg.name = config_name;
                 if cfg.check() {
-                    return Self::recreate_or_fix();
+                    return Self::recreate_or_fix(&cfg.name);
                 }
-                let config_path = confy::get_configuration_file_path("s3po", None).expect("can't get config path ...");
-                println!("{}: {}", "used config from".blue(), config_path.to_str().unwrap());
-                println!();
+                let config_path = cfg.get_loaded_config_path();
+                eprintln!("{}: {}", "used config from".blue(), config_path);
+                eprintln!();
+                check_limits(&cfg);
+                check_sse(&cfg);
+                check_credentials(&cfg);
+                check_endpoint(&cfg);
+                warn_without_key_backup(&cfg);
                 return cfg
             }
             Err(err) => {
                 println!("{}", err);
-                return Self::recreate_or_fix()
+                return Self::recreate_or_fix(&config_name)
             }
         }
     }
@@ -101,12 +271,24 @@ impl S3Config {
     pub(crate) fn list(&self) {
         match fs::read_dir(self.get_config_folder().as_str()) {
             Ok(entries) => {
+                let default_name = default_profile().unwrap_or(FALLBACK_PROFILE.to_string());
                 for entry in entries {
                     if let Ok(entry) = entry {
                         let file_name = entry.file_name().into_string().unwrap();
+                        // configs and their backups, not the files kept next to them
+                        if !file_name.contains(".toml") {
+                            continue;
+                        }
                         let time_modified: DateTime<Utc> = entry.metadata().unwrap().modified().unwrap().into();
                         let file_modified = time_modified.format("%d/%m/%Y %T");
-                        println!("{} {}", file_name, file_modified);
+                        let profile = profile_name(&file_name);
+                        let marks = match (profile == self.name, profile == default_name) {
+                            (true, true) => " (loaded, default)",
+                            (true, false) => " (loaded)",
+                            (false, true) => " (default)",
+                            (false, false) => "",
+                        };
+                        println!("{} {}{}", file_name, file_modified, marks.green());
                     }
                 }
             },
@@ -117,16 +299,158 @@ impl S3Config {
     pub(crate) fn print(&self) {
         println!("{}: {}", "loaded config".yellow(), self.get_loaded_config_path().blue());
         println!("s3 server url: {}", self.base_url);
-        println!("s3 access key: {}", self.access_key);
-        println!("s3 secret key: {}", self.secret_key);
+        println!("endpoint: {}", describe_endpoint(self));
+        match resolve(self) {
+            Ok(credentials) if credentials.origin == "config" => {
+                println!("s3 access key: {}", self.access_key);
+                println!("s3 secret key: {}", self.secret_key);
+            }
+            Ok(credentials) => println!("s3 access key: {} from {}", credentials.access_key, credentials.origin),
+            Err(err) => println!("s3 credentials: {}", err),
+        }
         println!("data encryption public key: {}", self.pk_bs58);
         println!("data encryption secret key: {}", self.sk_bs58);
+        println!("bandwidth limit: {}", describe(self));
+        println!("retry: {} attempts, backoff {}..{} ms, request timeout {} s, overall timeout {} s",
+                 self.retry.max_attempts, self.retry.base_delay_ms, self.retry.max_delay_ms,
+                 self.retry.request_timeout_secs, self.retry.overall_timeout_secs);
+        match Sse::parse(&self.sse, self) {
+            Ok(sse) => println!("server side encryption: {}", sse),
+            Err(err) => println!("server side encryption: {}", err),
+        }
+        for rule in &self.rules {
+            println!("rule {}", rule.describe());
+        }
     }
 }
 
 impl ::std::default::Default for S3Config {
-    fn default() -> Self { Self { name: "default".into(), base_url: "".into(), access_key: "".into(), secret_key: "".into(), sk_bs58: "".into(), pk_bs58: "".into() } }
+    fn default() -> Self { Self { version: CONFIG_VERSION, name: "default".into(), base_url: "".into(), access_key: "".into(), secret_key: "".into(), sk_bs58: "".into(), pk_bs58: "".into(), limit_rate: "".into(), limit_schedule: vec![], retry: RetryPolicy::default(), sse: "".into(), credentials: CredentialSource::default(), region: "".into(), use_https: None, port: None, addressing_style: "".into(), ca_bundle: "".into(), insecure_skip_verify: false, rules: vec![] } }
 }
 
+// a config without usable keys still loads, requests fail until the source is fixed
+fn check_credentials(config: &S3Config) {
+    if let Err(err) = resolve(config) {
+        println!("{}", err.yellow());
+    }
+}
 
+/// Whether any config was created on this machine.
+pub(crate) fn has_configs() -> bool {
+    fs::read_dir(config_folder()).is_ok_and(|entries| entries.flatten().any(|entry| entry.file_name().to_string_lossy().ends_with(".toml")))
+}
 
+/// Selects the profile for this run, from `--config`.
+pub(crate) fn select_profile(config_name: &str) {
+    *SELECTED.lock().unwrap() = Some(profile_name(config_name));
+}
+
+/// The profile set with `config default`, if any.
+pub(crate) fn default_profile() -> Option<String> {
+    let name = fs::read_to_string(config_folder().join(DEFAULT_POINTER)).ok()?;
+    Some(name.trim().to_string()).filter(|name| !name.is_empty())
+}
+
+/// Makes `config_name` the profile loaded on start.
+pub(crate) fn set_default_profile(config_name: &str) {
+    let folder = config_folder();
+    let written = fs::create_dir_all(&folder).and_then(|_| fs::write(folder.join(DEFAULT_POINTER), format!("{}\n", config_name)));
+    match written {
+        Ok(_) => println!("config {} is loaded on start now", config_name.green()),
+        Err(err) => println!("cant save the default config: {}", err),
+    }
+}
+
+pub(crate) fn profile_exists(config_name: &str) -> bool {
+    confy::get_configuration_file_path("s3po", Some(profile_name(config_name).as_str())).map(|path| path.exists()).unwrap_or(false)
+}
+
+// a profile can be given by its file name too
+fn profile_name(config_name: &str) -> String {
+    config_name.trim().strip_suffix(".toml").unwrap_or(config_name.trim()).to_string()
+}
+
+// the profile init loads and what chose it, None for the default
+fn startup_profile() -> (String, Option<&'static str>) {
+    if let Some(config_name) = SELECTED.lock().unwrap().clone() {
+        return (config_name, Some("--config"));
+    }
+    if let Some(config_name) = env::var("S3PO_CONFIG").ok().filter(|name| !name.trim().is_empty()) {
+        return (profile_name(&config_name), Some("S3PO_CONFIG"));
+    }
+    (default_profile().unwrap_or(FALLBACK_PROFILE.to_string()), None)
+}
+
+// a free `<name>.<stamp>.toml` in the trash, a config deleted twice in a second gets `<stamp>-02`, the padding keeps them sorted
+fn trash_path(trash: &Path, config_name: &str, stamp: &str) -> PathBuf {
+    let mut trash_path = trash.join(format!("{}.{}.toml", config_name, stamp));
+    let mut count = 1;
+    while trash_path.exists() {
+        count += 1;
+        trash_path = trash.join(format!("{}.{}-{:02}.toml", config_name, stamp, count));
+    }
+    trash_path
+}
+
+fn trashed_configs() -> Vec<(String, String, PathBuf)> {
+    trashed_in(&config_folder().join(TRASH))
+}
+
+// name, deletion time and path of the configs in the trash, oldest first
+fn trashed_in(trash: &Path) -> Vec<(String, String, PathBuf)> {
+    let Ok(entries) = fs::read_dir(trash) else { return vec![] };
+    let mut trashed: Vec<(String, String, PathBuf)> = entries.flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let (name, stamp) = file_name.strip_suffix(".toml")?.rsplit_once('.')?;
+            Some((name.to_string(), stamp.to_string(), entry.path()))
+        })
+        .collect();
+    trashed.sort_by(|a, b| a.1.cmp(&b.1));
+    trashed
+}
+
+// public key of a config file, read without loading it, so broken configs can go too
+fn config_key(config_path: &PathBuf) -> String {
+    fs::read_to_string(config_path).ok()
+        .and_then(|text| text.parse::<toml::Table>().ok())
+        .and_then(|table| table.get("pk_bs58").and_then(|pk| pk.as_str()).map(String::from))
+        .unwrap_or_default()
+}
+
+// whether another config has the same keypair, then deleting one loses nothing
+fn key_in_other_config(pk_bs58: &str, config_name: &str) -> bool {
+    let Ok(entries) = fs::read_dir(config_folder()) else { return false };
+    entries.flatten().any(|entry| {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        file_name.strip_suffix(".toml").is_some_and(|name| name != config_name) && config_key(&entry.path()).trim() == pk_bs58.trim()
+    })
+}
+
+/// The folder that holds the configs.
+pub(crate) fn config_folder() -> PathBuf {
+    let path = confy::get_configuration_file_path("s3po", Some(FALLBACK_PROFILE)).expect("can't get config path ...");
+    path.parent().map(PathBuf::from).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trash_keeps_configs_with_the_same_name_apart() {
+        let trash = env::temp_dir().join(format!("s3po-trash-{}", std::process::id()));
+        fs::create_dir_all(&trash).unwrap();
+        for (name, stamp) in [("work", "20260101-120000"), ("work.eu", "20260101-120000"), ("work", "20260101-120000"), ("work", "20251231-235959")] {
+            fs::write(trash_path(&trash, name, stamp), name).unwrap();
+        }
+        let trashed: Vec<(String, String)> = trashed_in(&trash).into_iter().map(|(name, stamp, _)| (name, stamp)).collect();
+        assert_eq!(trashed.len(), 4);
+        assert_eq!(trashed[0], ("work".to_string(), "20251231-235959".to_string()));
+        assert!(trashed.contains(&("work.eu".to_string(), "20260101-120000".to_string())));
+        // the newest is what undelete brings back
+        let newest = trashed.iter().rev().find(|(name, _)| name == "work").unwrap();
+        assert_eq!(newest.1, "20260101-120000-02");
+        fs::remove_dir_all(&trash).unwrap();
+    }
+}
@@ -1,13 +1,25 @@
 use std::io;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
 use std::process::exit;
 use std::string::String;
 
 use colored::Colorize;
-
-use crate::config::S3Config;
-use crate::crypto::{decrypt_config, encrypt_config, gen_new_keys, random_mnemonic, test_crypto};
-use crate::s3::S3Client;
+use reqwest::Method;
+
+use crate::config::{default_profile, profile_exists, set_default_profile, S3Config};
+use crate::crypto::{gen_new_keys, random_bytes, random_mnemonic, test_crypto};
+use crate::command::{split_remote, take_flag, take_option};
+use crate::s3::{fetch, PutOptions, S3Client, MAX_PRESIGN_SECS};
+use crate::sse::Sse;
+use crate::find::{take_action, Filter};
+use crate::profiles::{export_aws, import};
+use crate::bundle::{export_bundle, import_bundle, is_bundle};
+use crate::throttle::{clear_override, describe, parse_rate, set_override};
+use crate::units::{parse_duration, parse_timestamp};
+
+// validity of presigned urls and share links without --expires
+const DEFAULT_EXPIRES: &str = "24h";
 
 pub fn print_todo() {
     println!();
@@ -18,10 +30,11 @@ pub fn print_todo() {
     println!();
 }
 
+// on stderr, so command output can be piped
 pub fn print_intro() {
-    println!();
-    println!("{}","s3po v0.1.1".red());
-    println!();
+    eprintln!();
+    eprintln!("{}","s3po v0.1.1".red());
+    eprintln!();
 }
 
 pub fn print_help() {
@@ -31,28 +44,150 @@ pub fn print_help() {
     println!("{}","  help                               - for see this help".green());
     println!("{}","  ls                                 - list buckets".green());
     println!("{}","  ls <bucket name>                   - list files/objects in specified <bucket name>".green());
+    println!("{}","  ls --versions <bucket>[/<prefix>]  - list all versions and delete markers".green());
+    println!("{}","  ls -l <bucket name>                - list objects with their retention and legal hold".green());
     println!("{}","  cd <bucket name>                   - change current bucket to specified <bucket name>".green());
     println!("{}","  cd (cd ..)                         - return too root server folder".green());
     println!("{}","  mkdir <bucket name>                - creates new bucket".green());
+    println!("{}","  mkdir --object-lock <bucket name>  - creates a versioned bucket that can retain objects".green());
     println!("{}","  rm < bucket name / filename >      - delete bucket if exists or file if current bucket is not root".green());
     println!("{}","  rm <bucket name> <file name>       - delete file/objects in specified bucket".green());
+    println!("{}","  rm -r [--yes] <bucket>/<prefix>    - delete all objects under prefix, asks for confirmation without --yes".green());
+    println!("{}","  rm -r --versions <bucket>/<prefix> - also delete old versions and delete markers under prefix".green());
+    println!("{}","  rm --version-id <id> <bucket>/<obj> - permanently delete one version, deleting a delete marker undeletes".green());
+    println!("{}","  rm --bypass-governance ...         - deletes a version retained in GOVERNANCE mode, if allowed to".green());
+    println!("{}","  rmdir [--force] [--yes] <bucket>   - delete bucket, with --force empties it first".green());
     println!("{}","  put <bucket name> <file name>      - encrypt and upload <file name> to specified <bucket name>".green());
     println!("{}","  put <file name>                    - encrypt and upload <file name> to current bucket".green());
+    println!("{}","  put/upload ... [--meta k=v] [--tag k=v] [--content-type <type>] [--storage-class <class>] - sets object metadata and tags".green());
+    println!("{}","  put ... --seal-meta                - keeps --meta values encrypted inside the file instead of headers".green());
+    println!("{}","  put/upload ... --retain-until <time> [--retention-mode governance|compliance] - retains the object in a lock bucket".green());
+    println!("{}","  put/upload ... --force             - uploads even when a rule of the config refuses it".green());
+    println!("{}","  legal-hold on|off <bucket>/<obj> [--version-id <id>] - puts an object under legal hold or releases it".green());
+    println!("{}","  tag <bucket>/<obj> [k=v ...]       - adds tags to an object or prints its tags".green());
+    println!("{}","  untag <bucket>/<obj> [key ...]     - removes the named tags or all tags".green());
     println!("{}","  get <bucket name> <file name>      - decrypt and download <file name> from specified <bucket name>".green());
     println!("{}","  get <file name>                    - decrypt and download <file name> from current bucket".green());
+    println!("{}","  get --version-id <id> ...          - get/download an older version".green());
+    println!("{}","  bucket policy|lifecycle|cors get <bucket> [<file>] - prints the document or saves it as .json/.toml".green());
+    println!("{}","  bucket policy|lifecycle|cors set <bucket> <file> - checks a .json/.toml document and applies it".green());
+    println!("{}","  bucket policy|lifecycle|cors rm <bucket> - removes the bucket policy, lifecycle rules or CORS rules".green());
+    println!("{}","  versioning on|off|status <bucket>  - enables, suspends or shows bucket versioning".green());
+    println!("{}","  restore <bucket>/<obj> --as-of <time> - makes the version from that time current again".green());
+    println!("{}","  cp <bucket>/<obj> <bucket>/<obj>   - copies object on the server side, encrypted .x objects stay encrypted".green());
+    println!("{}","  cp -r <bucket>/<pfx> <bucket>/<pfx> - copies all objects under prefix".green());
+    println!("{}","  mv [-r] <bucket>/<obj> <bucket>/<obj> - moves/renames object(s) on the server side".green());
+    println!("{}","  stat (head) <bucket>/<obj>         - shows object metadata and, for .x objects, the encryption header".green());
+    println!("{}","  du <bucket>[/<prefix>] [--depth 1] - counts objects and bytes by folder, storage class, encryption and age".green());
+    println!("{}","  du ... --plaintext [--json]        - also sums sizes before encryption, prints json for scripts".green());
+    println!("{}","  find <bucket>[/<prefix>] [tests] [action] - lists objects that pass all tests, e.g. -name '*.sql.x' -larger 1G -older 30d".green());
+    println!("{}","    tests: -name/-path <glob> -larger/-smaller <size> -older/-newer <age> -encrypted -plain -storage-class <class>".green());
+    println!("{}","    actions: -print (default), -print0, -get (decrypts .x), -delete [--yes]".green());
+    println!("{}","  diff <local dir> <bucket>[/<prefix>] - shows files only local (+), only remote (-) and changed (~)".green());
+    println!("{}","  diff ... --upload-list             - prints only the local files that are missing or changed remotely".green());
+    println!("{}","  presign get|put <bucket>/<obj> [--expires 24h] - prints a presigned url, valid up to 7d".green());
+    println!("{}","  share-link <file> [<bucket>/<obj>] [--expires 24h] - uploads with a one-time key and prints a link to share".green());
+    println!("{}","  fetch <url> [-o <file name>]       - downloads a presigned url or share link, works without config".green());
     println!("{}","  upload <bucket name> <file name>   - upload <file name> to specified <bucket name> without encryption".green());
     println!("{}","  download <bucket name> <file name> - download <file name> from specified <bucket name> without decryption".green());
     println!("{}","  config (config print/cat)          - prints used/current/loaded config".green());
     println!("{}","  config list                        - lists all created configs".green());
     println!("{}","  config folder                      - prints path to configs folder".green());
     println!("{}","  config create (add/new)            - creates new config".green());
-    println!("{}","  config delete (del/rm) <name>      - delete the config with name".green());
+    println!("{}","  config delete (del/rm) <name>      - moves the config to the trash after asking for its name".green());
+    println!("{}","  config undelete [<name>]           - lists the trash or brings the newest deleted config back".green());
+    println!("{}","  config import aws|rclone|s3cmd [<path>] - creates configs from the profiles of these tools".green());
+    println!("{}","  config export [aws] [<dir>]        - prints the current config as an AWS CLI profile or writes it to <dir>/credentials and config".green());
+    println!("{}","  config export --all [<file>]       - seals all configs with a passphrase into one bundle, on stdout without <file>".green());
+    println!("{}","  config import <bundle>             - opens a bundle and adds its configs, asks before changing existing ones".green());
+    println!("{}","  config backup <bucket>             - stores all configs as a passphrase-encrypted bundle under s3po-config/".green());
+    println!("{}","  config restore <bucket> [--key <object>] - fetches the newest or the given bundle and adds its configs".green());
+    println!("{}","  config test [<name>] [--bucket <b>] - checks dns, tls, clock, credentials, read/write/delete and the keypair".green());
     println!("{}","  config use <name>                  - loads new config and use it to all commands".green());
+    println!("{}","  config default [<name>]            - shows or sets the config loaded on start".green());
+    println!("{}","  <command> --sse off|s3|kms[:<key id>]|c - server side encryption for put/get/cp/upload/download/stat".green());
+    println!("{}","  limit-rate [<rate>/off/config]     - shows or sets bandwidth limit for all transfers, e.g. 5M, config - the limits of the config".green());
     println!("{}","  keys                               - generates new crypto keys !danger! - rewrites existing keys".green());
     println!("{}","  q (exit/quit)                      - to exit this app".green());
     println!();
 }
 
+// loads another config for the rest of the session
+fn use_config(config_name: String, conf: &mut S3Config, s3cli: &mut S3Client) {
+    if !profile_exists(&config_name) {
+        println!("{} {}", "there is no config".yellow(), config_name.yellow());
+        return;
+    }
+    *conf = match S3Config::load(config_name) {
+        Ok(cfg) => cfg,
+        Err(err) => {
+            println!("{}", err.red());
+            return;
+        }
+    };
+    s3cli.config = conf.clone();
+    if default_profile().unwrap_or("default-config".to_string()) != conf.name {
+        println!("config default {} keeps it for the next start", conf.name);
+    }
+}
+
+// takes the upload options of put and upload, None when one of them is broken
+fn take_put_options(args: &mut Vec<String>) -> Option<PutOptions> {
+    let mut options = PutOptions::default();
+    while let Some(pair) = take_option(args, "--meta") {
+        options.metadata.extend(parse_pairs(&[pair.as_str()])?.into_iter().map(|(key, value)| (key.to_lowercase(), value)));
+    }
+    while let Some(pair) = take_option(args, "--tag") {
+        options.tags.extend(parse_pairs(&[pair.as_str()])?);
+    }
+    options.content_type = take_option(args, "--content-type");
+    options.storage_class = take_option(args, "--storage-class");
+    options.seal_metadata = take_flag(args, &["--seal-meta"]);
+    options.force = take_flag(args, &["--force"]);
+    let mode = take_option(args, "--retention-mode").unwrap_or("governance".to_string()).to_lowercase();
+    if let Some(retain_until) = take_option(args, "--retain-until") {
+        if mode != "governance" && mode != "compliance" {
+            println!("{} {}", "--retention-mode is governance or compliance, got".yellow(), mode.yellow());
+            return None;
+        }
+        match parse_timestamp(&retain_until) {
+            Some(retain_until) if retain_until > chrono::Utc::now() => options.retention = Some((mode, retain_until)),
+            _ => {
+                println!("{} {}", "--retain-until takes a future time like 2030-01-01 or 2030-01-01T12:00:00Z, got".yellow(), retain_until.yellow());
+                return None;
+            }
+        }
+    }
+    Some(options)
+}
+
+// splits key=value arguments, prints the broken one and gives None
+fn parse_pairs(args: &[&str]) -> Option<Vec<(String, String)>> {
+    let mut pairs = Vec::new();
+    for arg in args {
+        match arg.split_once('=') {
+            Some((key, value)) if !key.is_empty() => pairs.push((key.to_string(), value.to_string())),
+            _ => {
+                println!("{} {}", "expected key=value, got".yellow(), arg.yellow());
+                return None;
+            }
+        }
+    }
+    Some(pairs)
+}
+
+// takes --expires from the args, prints why and gives None when it is not usable
+fn take_expires(args: &mut Vec<String>) -> Option<u64> {
+    let expires = take_option(args, "--expires").unwrap_or(DEFAULT_EXPIRES.to_string());
+    match parse_duration(&expires) {
+        Some(secs) if secs > 0 && secs <= MAX_PRESIGN_SECS => Some(secs),
+        _ => {
+            println!("{} {}", "--expires must be between 1s and 7d, got".yellow(), expires.yellow());
+            None
+        }
+    }
+}
+
 pub(crate) fn y_or_n(question: &str) -> bool {
 
     let stdin = io::stdin();
@@ -90,6 +225,38 @@ pub(crate) fn ask(question: &str) -> String {
     return input
 }
 
+/// Asks for a secret on stderr, so stdout can carry data, without echo on a terminal.
+pub(crate) fn ask_secret(question: &str) -> String {
+    let stdin = io::stdin();
+    let input = &mut String::new();
+
+    eprint!("{} : ", question.red());
+    io::stderr().flush().expect("error flashing terminal");
+
+    let hide = stdin.is_terminal() && set_echo(false);
+    stdin.read_line(input).expect("error reading user input");
+    if hide {
+        set_echo(true);
+        eprintln!();
+    }
+    input.trim_end_matches(['\r', '\n']).to_string()
+}
+
+// turns terminal echo off or on again, false when that is not possible
+#[cfg(unix)]
+fn set_echo(on: bool) -> bool {
+    std::process::Command::new("stty")
+        .arg(if on { "echo" } else { "-echo" })
+        .stdin(std::process::Stdio::inherit())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(not(unix))]
+fn set_echo(_on: bool) -> bool {
+    false
+}
+
 pub(crate) async fn console_loop() {
     let mut conf: S3Config = S3Config::init();
     let mut s3cli = S3Client{ config: conf.clone(), bucket: "".to_string() };
@@ -98,213 +265,588 @@ pub(crate) async fn console_loop() {
 
     loop {
         input.clear();
+        let prompt = format!(" s3po ({}) > ", s3cli.config.name);
         if s3cli.bucket == String::from("") {
-            print!("{}", " s3po > ".red());
+            print!("{}", prompt.red());
         } else {
             let bucket_name = s3cli.bucket.clone() + " > ";
-            print!("{}{}", " s3po > ".red(), bucket_name.red());
+            print!("{}{}", prompt.red(), bucket_name.red());
         }
 
         io::stdout().flush().expect("error flashing terminal");
 
         stdin.read_line(input).expect("error reading user input");
-        let input= &mut String::from(input.trim());
+        // the console splits a line at spaces, the command line gets the args of the shell
+        let args: Vec<String> = input.trim().split(" ").map(String::from).collect();
 
-        if input == "help" {
-            print_help();
-            continue
-        }
+        execute(&args, &mut conf, &mut s3cli).await;
+    }
+}
 
-        if input == "encrypt" {
-            //encrypt_config(conf.clone());
-            println!("{}", "must encrypt something".blue());
-            continue
+/// Runs one command given as its words, the same way for the console and the command line mode.
+pub(crate) async fn execute(args: &[String], conf: &mut S3Config, s3cli: &mut S3Client) {
+    let input = args.join(" ");
+    let input = input.as_str();
+    // --sse replaces the server side encryption of the config and its rules for one command
+    if args.iter().any(|arg| arg == "--sse" || arg.starts_with("--sse=")) {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let Some(sse) = take_option(&mut input_vec, "--sse") else { return };
+        if let Err(err) = Sse::parse(&sse, &s3cli.config) {
+            println!("{}", err.yellow());
+            return
         }
+        let config = s3cli.config.clone();
+        s3cli.config.sse = sse;
+        s3cli.config.rules.iter_mut().for_each(|rule| rule.sse.clear());
+        Box::pin(execute(&input_vec, conf, s3cli)).await;
+        s3cli.config = config;
+        return
+    }
 
-        if input == "decrypt" {
-            //decrypt_config(conf.clone());
-            println!("{}", "must decrypt something".blue());
-            continue
+    if input == "help" {
+        print_help();
+        return
+    }
+
+    if input == "encrypt" {
+        println!("{}", "must encrypt something".blue());
+        return
+    }
+
+    if input == "decrypt" {
+        println!("{}", "must decrypt something".blue());
+        return
+    }
+
+    if input == "keys" {
+        gen_new_keys(conf.clone());
+        return
+    }
+
+    if input.starts_with("limit-rate") {
+        let input_vec: Vec<&str> = args.iter().map(String::as_str).collect();
+        if input_vec.len() > 1 && input_vec[1] == "config" {
+            clear_override();
+        } else if input_vec.len() > 1 {
+            match parse_rate(input_vec[1]) {
+                Ok(rate) => set_override(rate),
+                Err(err) => println!("{}", err.yellow()),
+            }
         }
+        println!("bandwidth limit: {}", describe(conf));
+        return
+    }
+
+    if input == "todo" {
+        print_todo();
+        return
+    }
+
+    if input == "mnemonic" {
+        println!("{}", random_mnemonic());
+        return
+    }
 
-        if input == "keys" {
-            gen_new_keys(conf.clone());
-            continue
+    if input.starts_with("cp ") || input.starts_with("mv ") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let recursive = take_flag(&mut input_vec, &["-r", "-R", "--recursive"]);
+        if input_vec.len() == 3 {
+            let src = split_remote(&input_vec[1], &s3cli.bucket);
+            let dst = split_remote(&input_vec[2], &s3cli.bucket);
+            s3cli.cp(src, dst, recursive, input_vec[0] == "mv").await;
+        } else {
+            println!("{}", "usage: cp [-r] <bucket>/<object> <bucket>/<object>".yellow());
         }
+        return
+    }
 
-        if input == "todo" {
-            print_todo();
-            continue
+    if input.starts_with("find") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let yes = take_flag(&mut input_vec, &["-y", "--yes"]);
+        let filter = match Filter::take(&mut input_vec) {
+            Ok(filter) => filter,
+            Err(err) => {
+                println!("{}", err.yellow());
+                return
+            }
+        };
+        let action = match take_action(&mut input_vec) {
+            Ok(action) => action,
+            Err(err) => {
+                println!("{}", err.yellow());
+                return
+            }
+        };
+        let (bucket_name, prefix) = split_remote(input_vec.get(1).unwrap_or(&s3cli.bucket), &s3cli.bucket);
+        if bucket_name.is_empty() || input_vec.len() > 2 {
+            println!("{}", "usage: find <bucket>[/<prefix>] [-name <glob>] [-larger 1G] [-older 30d] [-encrypted] [-delete|-get|-print0]".yellow());
+        } else {
+            s3cli.find(bucket_name, prefix, &filter, action, yes).await;
         }
+        return
+    }
 
-        if input == "mnemonic" {
-            println!("{}", random_mnemonic());
-            continue
+    if input.starts_with("diff") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let upload_list = take_flag(&mut input_vec, &["--upload-list"]);
+        if input_vec.len() == 3 {
+            let (bucket_name, prefix) = split_remote(&input_vec[2], &s3cli.bucket);
+            s3cli.diff(input_vec[1].clone(), bucket_name, prefix, upload_list).await;
+        } else {
+            println!("{}", "usage: diff <local dir> <bucket>[/<prefix>] [--upload-list]".yellow());
         }
+        return
+    }
 
-        if input.starts_with("cd") {
-            let input_vec: Vec<_>  = input.split(" ").collect();
-            if input_vec.len() > 1 {
-                s3cli.set_bucket(input_vec[1].to_string()).await;
-            } else {
-                s3cli.set_bucket("".to_string()).await;
+    if input == "du" || input.starts_with("du ") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let json = take_flag(&mut input_vec, &["--json"]);
+        let plaintext = take_flag(&mut input_vec, &["--plaintext"]);
+        let depth = match take_option(&mut input_vec, "--depth").map(|depth| depth.parse::<usize>()) {
+            None => 1,
+            Some(Ok(depth)) => depth,
+            Some(Err(_)) => {
+                println!("{}", "--depth takes a number of folder levels".yellow());
+                return
             }
-            continue
+        };
+        let (bucket_name, prefix) = split_remote(input_vec.get(1).unwrap_or(&s3cli.bucket), &s3cli.bucket);
+        if bucket_name.is_empty() || input_vec.len() > 2 {
+            println!("{}", "usage: du <bucket>[/<prefix>] [--depth <n>] [--plaintext] [--json]".yellow());
+        } else {
+            s3cli.du(bucket_name, prefix, depth, plaintext, json).await;
         }
+        return
+    }
 
-        if input.starts_with("mkdir") {
-            let input_vec: Vec<_>  = input.split(" ").collect();
-            if input_vec.len() > 1 {
-                let bucket_name = input_vec[1].to_string();
-                s3cli.mkdir(bucket_name).await;
-            } else {
-                let bucket_name = ask("Enter new bucket name");
-                s3cli.mkdir(bucket_name).await;
+    if input.starts_with("stat") || input.starts_with("head") {
+        let input_vec: Vec<&str> = args.iter().map(String::as_str).collect();
+        if input_vec.len() == 2 {
+            let (bucket_name, object_name) = split_remote(input_vec[1], &s3cli.bucket);
+            s3cli.stat(bucket_name, object_name).await;
+        } else {
+            println!("{}", "usage: stat <bucket>/<object>".yellow());
+        }
+        return
+    }
+
+    if input.starts_with("presign") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let Some(expires) = take_expires(&mut input_vec) else { return };
+        let method = match input_vec.get(1).map(|method| method.to_lowercase()).as_deref() {
+            Some("get") => Method::GET,
+            Some("put") => Method::PUT,
+            _ => {
+                println!("{}", "usage: presign get|put <bucket>/<object> [--expires 24h]".yellow());
+                return
             }
-            continue
+        };
+        if input_vec.len() != 3 {
+            println!("{}", "usage: presign get|put <bucket>/<object> [--expires 24h]".yellow());
+            return
+        }
+        let (bucket_name, object_name) = split_remote(&input_vec[2], &s3cli.bucket);
+        if let Some(url) = s3cli.presign(method, bucket_name, object_name, expires).await {
+            println!("{}", url);
+        }
+        return
+    }
+
+    if input.starts_with("share-link") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let Some(expires) = take_expires(&mut input_vec) else { return };
+        if input_vec.len() < 2 || input_vec.len() > 3 {
+            println!("{}", "usage: share-link <file name> [<bucket>/<object>] [--expires 24h]".yellow());
+            return
         }
+        let local_file_path = input_vec[1].clone();
+        let (bucket_name, mut object_name) = match input_vec.get(2) {
+            Some(remote) => split_remote(remote, &s3cli.bucket),
+            None => (s3cli.bucket.clone(), String::new()),
+        };
+        if bucket_name.is_empty() {
+            println!("{}", "select a bucket with cd or give <bucket>/<object>".yellow());
+            return
+        }
+        // a random folder keeps shared objects apart and their names unguessable
+        if object_name.is_empty() || object_name.ends_with('/') {
+            let folder: String = random_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+            let file_name = Path::new(&local_file_path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or(local_file_path.clone());
+            object_name = format!("{}shared/{}/{}", object_name, folder, file_name);
+        }
+        if let Some(link) = s3cli.share_link(bucket_name, object_name, local_file_path, expires).await {
+            println!("{}", "share link, anyone with it can download and decrypt the file:".blue());
+            println!("{}", link);
+        }
+        return
+    }
 
-        if input.starts_with("ls") || input.starts_with("list") {
-            let input_vec: Vec<_>  = input.split(" ").collect();
-            if input_vec.len() > 1 {
-                s3cli.ls(input_vec[1].to_string()).await;
+    if input.starts_with("fetch") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let local_file_path = take_option(&mut input_vec, "-o");
+        if input_vec.len() == 2 {
+            fetch(input_vec[1].clone(), local_file_path).await;
+        } else {
+            println!("{}", "usage: fetch <url> [-o <file name>]".yellow());
+        }
+        return
+    }
+
+    if input.starts_with("cd") {
+        let input_vec: Vec<&str> = args.iter().map(String::as_str).collect();
+        if input_vec.len() > 1 {
+            s3cli.set_bucket(input_vec[1].to_string()).await;
+        } else {
+            s3cli.set_bucket("".to_string()).await;
+        }
+        return
+    }
+
+    if input.starts_with("mkdir") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let object_lock = take_flag(&mut input_vec, &["--object-lock"]);
+        if input_vec.len() > 1 {
+            let bucket_name = input_vec[1].to_string();
+            s3cli.mkdir(bucket_name, object_lock).await;
+        } else {
+            let bucket_name = ask("Enter new bucket name");
+            s3cli.mkdir(bucket_name, object_lock).await;
+        }
+        return
+    }
+
+    if input.starts_with("ls") || input.starts_with("list") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        if take_flag(&mut input_vec, &["--versions"]) {
+            let (bucket_name, prefix) = split_remote(input_vec.get(1).unwrap_or(&s3cli.bucket), &s3cli.bucket);
+            if bucket_name.is_empty() {
+                println!("{}", "usage: ls --versions <bucket>[/<prefix>]".yellow());
             } else {
-                if s3cli.bucket == "".to_string() {
-                    s3cli.ls("".to_string()).await;
-                } else {
-                    s3cli.ls(s3cli.bucket.clone()).await;
-                }
+                s3cli.ls_versions(bucket_name, prefix).await;
             }
-            continue
+            return
         }
-
-        if input.starts_with("upload") {
-            let input_vec: Vec<_>  = input.split(" ").collect();
-            if input_vec.len() > 2 {
-                s3cli.put(input_vec[1].to_string(), input_vec[2].to_string(), input_vec[2].to_string()).await;
-                continue
+        if take_flag(&mut input_vec, &["-l"]) {
+            let bucket_name = input_vec.get(1).cloned().unwrap_or(s3cli.bucket.clone());
+            if bucket_name.is_empty() {
+                println!("{}", "usage: ls -l <bucket name>".yellow());
+            } else {
+                s3cli.ls_long(bucket_name).await;
             }
-            println!("{}", "error putting file... too less args".blue());
-            continue
-        }
-        if input.starts_with("put2") {
-            let input_vec: Vec<_>  = input.split(" ").collect();
-            if input_vec.len() > 2 {
-                s3cli.put2(input_vec[1].to_string(), input_vec[2].to_string(), input_vec[2].to_string()).await;
-                continue
+            return
+        }
+        if input_vec.len() > 1 {
+            s3cli.ls(input_vec[1].to_string()).await;
+        } else {
+            if s3cli.bucket.is_empty() {
+                s3cli.ls("".to_string()).await;
+            } else {
+                s3cli.ls(s3cli.bucket.clone()).await;
             }
-            println!("{}", "error putting file... too less args".blue());
-            continue
         }
+        return
+    }
 
-        if input.starts_with("put") {
-            let input_vec: Vec<_>  = input.split(" ").collect();
-            if input_vec.len() == 2 {
-                s3cli.put_file_encrypted(s3cli.bucket.clone(), input_vec[1].to_string(), input_vec[1].to_string()).await;
-                continue
-            }
-            if input_vec.len() > 2 {
-                s3cli.put_file_encrypted(input_vec[1].to_string(), input_vec[2].to_string(), input_vec[2].to_string()).await;
-                continue
-            }
-            println!("{}", "error putting file... too less args".blue());
-            continue
+    if input.starts_with("upload") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let Some(mut options) = take_put_options(&mut input_vec) else { return };
+        if options.seal_metadata {
+            println!("{}", "--seal-meta needs an encrypted put, metadata is sent in clear headers".yellow());
+            options.seal_metadata = false;
+        }
+        if input_vec.len() > 2 {
+            s3cli.put(input_vec[1].to_string(), input_vec[2].to_string(), input_vec[2].to_string(), &options).await;
+            return
         }
+        println!("{}", "error putting file... too less args".blue());
+        return
+    }
+    if input.starts_with("put2") {
+        let input_vec: Vec<&str> = args.iter().map(String::as_str).collect();
+        if input_vec.len() > 2 {
+            s3cli.put2(input_vec[1].to_string(), input_vec[2].to_string(), input_vec[2].to_string()).await;
+            return
+        }
+        println!("{}", "error putting file... too less args".blue());
+        return
+    }
 
-        if input.starts_with("download") {
-            let input_vec: Vec<_>  = input.split(" ").collect();
-            if input_vec.len() > 2 {
-                s3cli.get(input_vec[1].to_string(), input_vec[2].to_string(), input_vec[2].to_string()).await;
-                continue
-            }
-            println!("{}", "error getting file... too less args".blue());
-            continue
+    if input.starts_with("put") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let Some(options) = take_put_options(&mut input_vec) else { return };
+        if input_vec.len() == 2 {
+            s3cli.put_file_encrypted(s3cli.bucket.clone(), input_vec[1].to_string(), input_vec[1].to_string(), &options).await;
+            return
         }
+        if input_vec.len() > 2 {
+            s3cli.put_file_encrypted(input_vec[1].to_string(), input_vec[2].to_string(), input_vec[2].to_string(), &options).await;
+            return
+        }
+        println!("{}", "error putting file... too less args".blue());
+        return
+    }
 
-        if input.starts_with("get") {
-            let input_vec: Vec<_>  = input.split(" ").collect();
-            if input_vec.len() == 2 {
-                s3cli.get_file_encrypted(s3cli.bucket.clone(), input_vec[1].to_string(), input_vec[1].to_string()).await;
-                continue
-            }
-            if input_vec.len() > 2 {
-                s3cli.get_file_encrypted(input_vec[1].to_string(), input_vec[2].to_string(), input_vec[2].to_string()).await;
-                continue
+    if input.starts_with("download") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let version_id = take_option(&mut input_vec, "--version-id");
+        if input_vec.len() > 2 {
+            s3cli.get(input_vec[1].to_string(), input_vec[2].to_string(), input_vec[2].to_string(), version_id).await;
+            return
+        }
+        println!("{}", "error getting file... too less args".blue());
+        return
+    }
+
+    if input.starts_with("get") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let version_id = take_option(&mut input_vec, "--version-id");
+        if input_vec.len() == 2 {
+            s3cli.get_file_encrypted(s3cli.bucket.clone(), input_vec[1].to_string(), input_vec[1].to_string(), version_id).await;
+            return
+        }
+        if input_vec.len() > 2 {
+            s3cli.get_file_encrypted(input_vec[1].to_string(), input_vec[2].to_string(), input_vec[2].to_string(), version_id).await;
+            return
+        }
+        println!("{}", "error getting file... too less args".blue());
+        return
+    }
+
+    if input.starts_with("tag ") || input == "tag" {
+        let input_vec: Vec<&str> = args.iter().map(String::as_str).collect();
+        if input_vec.len() < 2 {
+            println!("{}", "usage: tag <bucket>/<object> [key=value ...]".yellow());
+            return
+        }
+        let Some(tags) = parse_pairs(&input_vec[2..]) else { return };
+        let (bucket_name, object_name) = split_remote(input_vec[1], &s3cli.bucket);
+        s3cli.tag(bucket_name, object_name, tags).await;
+        return
+    }
+
+    if input.starts_with("untag") {
+        let input_vec: Vec<&str> = args.iter().map(String::as_str).collect();
+        if input_vec.len() < 2 {
+            println!("{}", "usage: untag <bucket>/<object> [key ...]".yellow());
+            return
+        }
+        let (bucket_name, object_name) = split_remote(input_vec[1], &s3cli.bucket);
+        s3cli.untag(bucket_name, object_name, input_vec[2..].iter().map(|key| key.to_string()).collect()).await;
+        return
+    }
+
+    if input.starts_with("bucket ") {
+        let input_vec: Vec<&str> = args.iter().map(String::as_str).collect();
+        let kinds = ["policy", "lifecycle", "cors"];
+        let actions = ["get", "set", "rm"];
+        if input_vec.len() < 4 || input_vec.len() > 5 || !kinds.contains(&input_vec[1]) || !actions.contains(&input_vec[2]) {
+            println!("{}", "usage: bucket policy|lifecycle|cors get|set|rm <bucket name> [<file.json|file.toml>]".yellow());
+            return
+        }
+        s3cli.bucket_config(input_vec[1].to_string(), input_vec[2].to_string(), input_vec[3].to_string(), input_vec.get(4).map(|path| path.to_string())).await;
+        return
+    }
+
+    if input.starts_with("versioning") {
+        let input_vec: Vec<&str> = args.iter().map(String::as_str).collect();
+        match input_vec.len() {
+            3 if ["on", "off", "status"].contains(&input_vec[1]) => s3cli.versioning(input_vec[2].to_string(), input_vec[1].to_string()).await,
+            _ => println!("{}", "usage: versioning on|off|status <bucket name>".yellow()),
+        }
+        return
+    }
+
+    if input.starts_with("restore") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let as_of = take_option(&mut input_vec, "--as-of");
+        match (input_vec.len(), as_of.as_deref().map(parse_timestamp)) {
+            (2, Some(Some(as_of))) => {
+                let (bucket_name, object_name) = split_remote(&input_vec[1], &s3cli.bucket);
+                s3cli.restore(bucket_name, object_name, as_of).await;
             }
-            println!("{}", "error getting file... too less args".blue());
-            continue
+            (2, Some(None)) => println!("{}", "--as-of takes a time like 2024-05-01T12:00:00Z, 2024-05-01T12:00 or 2024-05-01".yellow()),
+            _ => println!("{}", "usage: restore <bucket>/<object> --as-of <time>".yellow()),
         }
+        return
+    }
 
-        if input.starts_with("rm") || input.starts_with("del") {
-            let input_vec: Vec<_>  = input.split(" ").collect();
-            if input_vec.len() == 2 {
-                if s3cli.bucket == "".to_string() || s3cli.bucket == "/" {
-                    s3cli.rm(input_vec[1].to_string()).await;
+    if input.starts_with("legal-hold") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let version_id = take_option(&mut input_vec, "--version-id");
+        match (input_vec.get(1).map(String::as_str), input_vec.len()) {
+            (Some(state @ ("on" | "off")), 3) => {
+                let (bucket_name, object_name) = split_remote(&input_vec[2], &s3cli.bucket);
+                if object_name.is_empty() {
+                    println!("{}", "legal hold is set on an object, not on a bucket".yellow());
                 } else {
-                    s3cli.rm_obj(s3cli.bucket.clone(), input_vec[1].to_string()).await;
+                    s3cli.legal_hold(bucket_name, object_name, version_id, state == "on").await;
                 }
-
-            } else if input_vec.len() == 3 {
-                s3cli.rm_obj(input_vec[1].to_string(), input_vec[2].to_string()).await;
-            } else  {
-                println!("{}", "specify bucket name to remove bucket or bucket name and object name to remove object".yellow());
             }
-            continue
+            _ => println!("{}", "usage: legal-hold on|off <bucket>/<object> [--version-id <id>]".yellow()),
         }
+        return
+    }
+
+    if input.starts_with("rmdir") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let force = take_flag(&mut input_vec, &["-f", "--force"]);
+        let yes = take_flag(&mut input_vec, &["-y", "--yes"]);
+        if input_vec.len() == 2 {
+            s3cli.rmdir(input_vec[1].to_string(), force, yes).await;
+        } else {
+            println!("{}", "usage: rmdir [--force] [--yes] <bucket name>".yellow());
+        }
+        return
+    }
 
-        if input.starts_with("config") {
-            let input_vec: Vec<_>  = input.split(" ").collect();
+    if input.starts_with("rm") || input.starts_with("del") {
+        let mut input_vec: Vec<String> = args.to_vec();
+        let recursive = take_flag(&mut input_vec, &["-r", "-R", "--recursive"]);
+        let yes = take_flag(&mut input_vec, &["-y", "--yes"]);
+        let all_versions = take_flag(&mut input_vec, &["--versions"]);
+        let version_id = take_option(&mut input_vec, "--version-id");
+        let bypass_governance = take_flag(&mut input_vec, &["--bypass-governance"]);
+        if recursive {
             if input_vec.len() == 2 {
-                if input_vec[1] == "cat" || input_vec[1] == "print" {
-                    conf.print();
-                }
-                if input_vec[1] == "folder" {
-                    println!("config folder: {}", conf.get_config_folder().green());
-                }
-                if input_vec[1] == "list" || input_vec[1] == "ls" {
-                    conf.list();
-                }
-                if input_vec[1] == "create" || input_vec[1] == "add" || input_vec[1] == "new" {
-                    S3Config::create();
-                }
-                if input_vec[1] == "delete" || input_vec[1] == "rm" || input_vec[1] == "del" {
-                    let config_name = ask("Enter a name of config or filename to remove: ");
-                    conf.clone().delete(config_name);
-                }
-                if input_vec[1] == "use" || input_vec[1] == "load" || input_vec[1] == "set" {
-                    let config_name = ask("Enter a name of config or filename to use: ");
-                    conf = S3Config::load(config_name);
-                    s3cli.config = conf.clone();
-                }
-            } else if input_vec.len() == 3 {
-                if input_vec[1] == "use" || input_vec[1] == "load" || input_vec[1] == "set" {
-                    let config_name = input_vec[2].to_string();
-                    conf = S3Config::load(config_name);
-                    s3cli.config = conf.clone();
-                }
-                if input_vec[1] == "delete" || input_vec[1] == "rm" || input_vec[1] == "del" {
-                    let config_name = input_vec[2].to_string();
-                    conf.clone().delete(config_name);
-                }
+                let (bucket_name, prefix) = split_remote(&input_vec[1], &s3cli.bucket);
+                s3cli.rm_recursive(bucket_name, prefix, yes, all_versions).await;
             } else {
-                conf.print();
+                println!("{}", "usage: rm -r [--versions] [--yes] <bucket>/<prefix>".yellow());
             }
-            continue
+            return
         }
+        if input_vec.len() == 2 && version_id.is_some() {
+            let (bucket_name, object_name) = split_remote(&input_vec[1], &s3cli.bucket);
+            s3cli.rm_obj(bucket_name, object_name, version_id, bypass_governance).await;
+        } else if input_vec.len() == 2 {
+            if s3cli.bucket.is_empty() || s3cli.bucket == "/" {
+                s3cli.rm(input_vec[1].to_string()).await;
+            } else {
+                s3cli.rm_obj(s3cli.bucket.clone(), input_vec[1].to_string(), None, bypass_governance).await;
+            }
 
-        if input == "q" || input == "exit" || input == "quit" {
-            println!("{}", "buy...".yellow());
-            exit(0);
+        } else if input_vec.len() == 3 {
+            s3cli.rm_obj(input_vec[1].to_string(), input_vec[2].to_string(), version_id, bypass_governance).await;
+        } else  {
+            println!("{}", "specify bucket name to remove bucket or bucket name and object name to remove object".yellow());
         }
+        return
+    }
 
-        //tests
-
-        if input == "test_crypto" {
-            test_crypto();
-            continue
+    if input.starts_with("config") {
+        let input_vec: Vec<&str> = args.iter().map(String::as_str).collect();
+        if input_vec.get(1) == Some(&"import") {
+            match input_vec.get(2) {
+                Some(&format) if ["aws", "rclone", "s3cmd"].contains(&format) => import(format, input_vec.get(3).map(|path| path.to_string()), conf),
+                Some(&file) if is_bundle(file) => import_bundle(file),
+                Some(format) => import(format, input_vec.get(3).map(|path| path.to_string()), conf),
+                None => println!("{}", "usage: config import aws|rclone|s3cmd [<path>] or config import <bundle>".yellow()),
+            }
+            return
+        }
+        if input_vec.get(1) == Some(&"export") {
+            match input_vec.get(2) {
+                Some(&"--all") => export_bundle(input_vec.get(3).map(|file| file.to_string())),
+                Some(&"aws") | None => export_aws(conf, input_vec.get(3).map(|dir| dir.to_string())),
+                Some(format) => println!("{} {}", "can't export to".yellow(), format.yellow()),
+            }
+            return
+        }
+        if input_vec.get(1) == Some(&"backup") {
+            match input_vec.get(2) {
+                Some(bucket_name) => s3cli.backup_config(bucket_name.to_string()).await,
+                None => println!("{}", "usage: config backup <bucket>".yellow()),
+            }
+            return
+        }
+        if input_vec.get(1) == Some(&"restore") {
+            let mut args: Vec<String> = input_vec[2..].iter().map(|arg| arg.to_string()).collect();
+            let object_name = take_option(&mut args, "--key");
+            match args.first() {
+                Some(bucket_name) => s3cli.restore_config(bucket_name.clone(), object_name).await,
+                None => println!("{}", "usage: config restore <bucket> [--key <object>]".yellow()),
+            }
+            return
+        }
+        if input_vec.get(1) == Some(&"test") {
+            let mut args: Vec<String> = input_vec[2..].iter().map(|arg| arg.to_string()).collect();
+            let bucket_name = take_option(&mut args, "--bucket");
+            match args.first() {
+                Some(config_name) if !profile_exists(config_name) => println!("{} {}", "there is no config".yellow(), config_name.yellow()),
+                Some(config_name) => match S3Config::load(config_name.to_string()) {
+                    Ok(config) => S3Client { config, bucket: String::new() }.test_config(bucket_name).await,
+                    Err(err) => println!("{}", err.red()),
+                },
+                None => s3cli.test_config(bucket_name).await,
+            }
+            return
+        }
+        if input_vec.len() == 2 {
+            if input_vec[1] == "cat" || input_vec[1] == "print" {
+                conf.print();
+            }
+            if input_vec[1] == "folder" {
+                println!("config folder: {}", conf.get_config_folder().green());
+            }
+            if input_vec[1] == "list" || input_vec[1] == "ls" {
+                conf.list();
+            }
+            if input_vec[1] == "create" || input_vec[1] == "add" || input_vec[1] == "new" {
+                S3Config::create();
+            }
+            if input_vec[1] == "delete" || input_vec[1] == "rm" || input_vec[1] == "del" {
+                let config_name = ask("Enter a name of config or filename to remove: ");
+                conf.delete(config_name);
+            }
+            if input_vec[1] == "undelete" {
+                S3Config::undelete(None);
+            }
+            if input_vec[1] == "use" || input_vec[1] == "load" || input_vec[1] == "set" {
+                let config_name = ask("Enter a name of config or filename to use: ");
+                use_config(config_name, conf, s3cli);
+            }
+            if input_vec[1] == "default" {
+                match default_profile() {
+                    Some(config_name) => println!("default config: {}", config_name.green()),
+                    None => println!("default config: {} (not set)", "default-config".green()),
+                }
+            }
+        } else if input_vec.len() == 3 {
+            if input_vec[1] == "use" || input_vec[1] == "load" || input_vec[1] == "set" {
+                let config_name = input_vec[2].to_string();
+                use_config(config_name, conf, s3cli);
+            }
+            if input_vec[1] == "default" {
+                match profile_exists(input_vec[2]) {
+                    true => set_default_profile(input_vec[2].trim_end_matches(".toml")),
+                    false => println!("{} {}", "there is no config".yellow(), input_vec[2].yellow()),
+                }
+            }
+            if input_vec[1] == "delete" || input_vec[1] == "rm" || input_vec[1] == "del" {
+                let config_name = input_vec[2].to_string();
+                conf.delete(config_name);
+            }
+            if input_vec[1] == "undelete" {
+                S3Config::undelete(Some(input_vec[2].to_string()));
+            }
+        } else {
+            conf.print();
         }
+        return
+    }
 
-        println!("your input: {} - is not a command...", input);
-        print_help();
+    if input == "q" || input == "exit" || input == "quit" {
+        println!("{}", "buy...".yellow());
+        exit(0);
     }
-}
\ No newline at end of file
+
+    //tests
+
+    if input == "test_crypto" {
+        test_crypto();
+        return
+    }
+
+    println!("your input: {} - is not a command...", input);
+    print_help();
+}
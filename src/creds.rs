@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::{Deserialize, Serialize};
+use crate::config::S3Config;
+
+// environment variables, s3po names first
+const ACCESS_KEY_VARS: [&str; 2] = ["S3PO_ACCESS_KEY", "AWS_ACCESS_KEY_ID"];
+const SECRET_KEY_VARS: [&str; 2] = ["S3PO_SECRET_KEY", "AWS_SECRET_ACCESS_KEY"];
+const SESSION_TOKEN_VARS: [&str; 2] = ["S3PO_SESSION_TOKEN", "AWS_SESSION_TOKEN"];
+// cached process credentials are fetched again this long before they expire
+const EXPIRY_MARGIN_SECS: i64 = 60;
+
+/// Where the s3 keys come from, stored as the `[credentials]` table of a config.
+/// With `source = "auto"` the environment, the keys in the config itself,
+/// `process` and the shared credentials `file` are tried in that order.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub(crate) struct CredentialSource {
+    /// auto, env, config, process or file
+    pub(crate) source: String,
+    /// profile in the shared credentials file, AWS_PROFILE or "default" when empty
+    pub(crate) profile: String,
+    /// shared credentials file, AWS_SHARED_CREDENTIALS_FILE or ~/.aws/credentials when empty
+    pub(crate) file: String,
+    /// command that prints AWS credential_process JSON
+    pub(crate) process: String,
+}
+
+impl ::std::default::Default for CredentialSource {
+    fn default() -> Self { Self { source: "auto".into(), profile: "".into(), file: "".into(), process: "".into() } }
+}
+
+/// Keys found by `resolve` and where they were found.
+#[derive(Clone, Debug)]
+pub(crate) struct Credentials {
+    pub(crate) access_key: String,
+    pub(crate) secret_key: String,
+    pub(crate) session_token: Option<String>,
+    pub(crate) origin: String,
+}
+
+// output of a credential_process command
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProcessOutput {
+    version: u32,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    /// credentials without it never expire
+    expiration: Option<DateTime<Utc>>,
+}
+
+// credentials of every credential process run so far and when they expire
+type ProcessCache = HashMap<String, (Credentials, Option<DateTime<Utc>>)>;
+
+fn process_cache() -> &'static Mutex<ProcessCache> {
+    static CACHE: OnceLock<Mutex<ProcessCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Finds the s3 keys of `config` as its `[credentials]` table says.
+pub(crate) fn resolve(config: &S3Config) -> Result<Credentials, String> {
+    let source = &config.credentials;
+    match source.source.trim() {
+        "" | "auto" => {
+            if let Some(credentials) = from_env() {
+                return Ok(credentials);
+            }
+            if let Some(credentials) = from_config(config) {
+                return Ok(credentials);
+            }
+            if !source.process.trim().is_empty() {
+                return from_process(&source.process);
+            }
+            from_file(source).map_err(|err| format!("no s3 credentials in the environment, the config, a credential process or the credentials file: {}", err))
+        }
+        "env" => from_env().ok_or(format!("{} and {} are not set", ACCESS_KEY_VARS.join("/"), SECRET_KEY_VARS.join("/"))),
+        "config" => from_config(config).ok_or("access_key and secret_key of the config are empty".to_string()),
+        "process" => from_process(&source.process),
+        "file" => from_file(source),
+        other => Err(format!("unknown credentials source \"{}\", expected auto, env, config, process or file", other)),
+    }
+}
+
+/// Sections of an INI file like ~/.aws/credentials, keys are lowercase.
+pub(crate) fn parse_ini(text: &str) -> BTreeMap<String, BTreeMap<String, String>> {
+    let mut sections: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    let mut section = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            section = name.trim().to_string();
+            sections.entry(section.clone()).or_default();
+        } else if let Some((key, value)) = line.split_once('=') {
+            sections.entry(section.clone()).or_default().insert(key.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    sections
+}
+
+/// The home folder, where ~/.aws and other tools keep their files.
+pub(crate) fn home_dir() -> String {
+    env::var("HOME").or_else(|_| env::var("USERPROFILE")).unwrap_or_default()
+}
+
+fn from_env() -> Option<Credentials> {
+    let var = |names: &[&str]| names.iter().find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()).map(|value| (name.to_string(), value)));
+    let (name, access_key) = var(&ACCESS_KEY_VARS)?;
+    let (_, secret_key) = var(&SECRET_KEY_VARS)?;
+    Some(Credentials { access_key, secret_key, session_token: var(&SESSION_TOKEN_VARS).map(|(_, token)| token), origin: format!("environment ({})", name) })
+}
+
+fn from_config(config: &S3Config) -> Option<Credentials> {
+    if config.access_key.trim().is_empty() || config.secret_key.trim().is_empty() {
+        return None;
+    }
+    Some(Credentials { access_key: config.access_key.clone(), secret_key: config.secret_key.clone(), session_token: None, origin: "config".to_string() })
+}
+
+// runs the credential process only when there are no cached credentials that are still valid
+fn from_process(command: &str) -> Result<Credentials, String> {
+    if command.trim().is_empty() {
+        return Err("credentials process is empty".to_string());
+    }
+    let mut cache = process_cache().lock().unwrap();
+    if let Some((credentials, expiration)) = cache.get(command) {
+        if expiration.is_none_or(|expiration| Utc::now() + Duration::seconds(EXPIRY_MARGIN_SECS) < expiration) {
+            return Ok(credentials.clone());
+        }
+    }
+    let (credentials, expiration) = run_process(command)?;
+    cache.insert(command.to_string(), (credentials.clone(), expiration));
+    Ok(credentials)
+}
+
+fn run_process(command: &str) -> Result<(Credentials, Option<DateTime<Utc>>), String> {
+    let output = shell(command).output().map_err(|err| format!("cant run credential process \"{}\": {}", command, err))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(format!("credential process \"{}\" failed with {}{}", command, output.status, if stderr.is_empty() { stderr } else { format!(": {}", stderr) }));
+    }
+    let parsed: ProcessOutput = serde_json::from_slice(&output.stdout).map_err(|err| format!("credential process \"{}\" printed no credentials json: {}", command, err))?;
+    if parsed.version != 1 {
+        return Err(format!("credential process \"{}\" printed version {}, only 1 is known", command, parsed.version));
+    }
+    let credentials = Credentials { access_key: parsed.access_key_id, secret_key: parsed.secret_access_key, session_token: parsed.session_token, origin: format!("credential process \"{}\"", command) };
+    Ok((credentials, parsed.expiration))
+}
+
+fn from_file(source: &CredentialSource) -> Result<Credentials, String> {
+    let path = match source.file.trim() {
+        "" => env::var("AWS_SHARED_CREDENTIALS_FILE").unwrap_or(format!("{}/.aws/credentials", home_dir())),
+        file => match file.strip_prefix('~') {
+            Some(rest) => format!("{}{}", home_dir(), rest),
+            None => file.to_string(),
+        },
+    };
+    let profile = match source.profile.trim() {
+        "" => env::var("AWS_PROFILE").unwrap_or("default".to_string()),
+        profile => profile.to_string(),
+    };
+    let text = fs::read_to_string(&path).map_err(|err| format!("cant read credentials file {}: {}", path, err))?;
+    let sections = parse_ini(&text);
+    let section = sections.get(&profile).ok_or(format!("no profile [{}] in {}", profile, path))?;
+    match (section.get("aws_access_key_id"), section.get("aws_secret_access_key")) {
+        (Some(access_key), Some(secret_key)) => Ok(Credentials {
+            access_key: access_key.clone(),
+            secret_key: secret_key.clone(),
+            session_token: section.get("aws_session_token").cloned(),
+            origin: format!("profile {} of {}", profile, path),
+        }),
+        _ => Err(format!("profile [{}] of {} has no aws_access_key_id and aws_secret_access_key", profile, path)),
+    }
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.args(["/C", command]);
+    cmd
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", command]);
+    cmd
+}
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+
+    // a credential process that counts its runs in `runs`
+    fn counting_process(runs: &str, expiration: &str) -> String {
+        format!(r#"echo run >> {} && echo '{{"Version":1,"AccessKeyId":"key","SecretAccessKey":"secret","Expiration":"{}"}}'"#, runs, expiration)
+    }
+
+    fn run_count(runs: &str) -> usize {
+        fs::read_to_string(runs).unwrap_or_default().lines().count()
+    }
+
+    #[test]
+    fn process_credentials_are_cached_until_they_expire() {
+        let dir = env::temp_dir().join(format!("s3po-creds-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let valid_runs = dir.join("valid").display().to_string();
+        let command = counting_process(&valid_runs, &(Utc::now() + Duration::hours(1)).to_rfc3339());
+        assert_eq!(from_process(&command).unwrap().access_key, "key");
+        assert_eq!(from_process(&command).unwrap().secret_key, "secret");
+        assert_eq!(run_count(&valid_runs), 1);
+
+        let expiring_runs = dir.join("expiring").display().to_string();
+        let command = counting_process(&expiring_runs, &(Utc::now() + Duration::seconds(EXPIRY_MARGIN_SECS / 2)).to_rfc3339());
+        from_process(&command).unwrap();
+        from_process(&command).unwrap();
+        assert_eq!(run_count(&expiring_runs), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,12 +1,15 @@
 use rand::prelude::*;
-use std::{fs, str};
+use std::str;
+use std::collections::BTreeMap;
 use std::ops::Add;
 use ecies::{decrypt, encrypt, utils::generate_keypair};
 use libsecp256k1::{Message, sign, Signature, verify};
 use bs58;
+use chacha20poly1305::{aead::{Aead, KeyInit}, XChaCha20Poly1305, XNonce};
 use colored::Colorize;
 use crate::config::S3Config;
 use crate::console::{ask};
+use crate::envelope::{open, seal};
 
 pub(crate) fn random_bytes() -> Vec<u8> {
     let mut rng = rand::thread_rng();
@@ -38,6 +41,17 @@ pub(crate) fn keys_bs58_to_bytes(sk: String, pk: String) -> ([u8; 32], [u8; 65])
     (<[u8; 32]>::try_from(sk_vec.as_slice()).unwrap(), <[u8; 65]>::try_from(pk_vec.as_slice()).unwrap())
 }
 
+/// Checks that `pk_bs58` is the public key of `sk_bs58`.
+pub(crate) fn check_keypair(sk_bs58: &str, pk_bs58: &str) -> Result<(), String> {
+    let sk_vec = bs58::decode(sk_bs58.trim()).into_vec().map_err(|err| format!("sk_bs58 is not base58: {}", err))?;
+    let pk_vec = bs58::decode(pk_bs58.trim()).into_vec().map_err(|err| format!("pk_bs58 is not base58: {}", err))?;
+    let sk = libsecp256k1::SecretKey::parse_slice(&sk_vec).map_err(|err| format!("sk_bs58 is not a secret key: {:?}", err))?;
+    match libsecp256k1::PublicKey::from_secret_key(&sk).serialize().as_slice() == pk_vec.as_slice() {
+        true => Ok(()),
+        false => Err("pk_bs58 is not the public key of sk_bs58, files encrypted now can't be decrypted with this config".to_string()),
+    }
+}
+
 pub(crate) fn new_keys() -> (String, String) {
     let (sk, pk) = generate_keys();
     keys_bytes_to_bs58(sk, pk)
@@ -57,54 +71,67 @@ pub(crate) fn random_mnemonic() -> String {
     bytes_to_mnemonic(random_bytes())
 }
 
-pub(crate) fn encrypt_config(cfg: S3Config) {
-    encrypt_file(cfg, String::from("/home/inspeere/.config/s3po/default-config.toml"));
+/// Decodes a base58 public key, like the `pk_bs58` of a config.
+pub(crate) fn parse_public_key(pk_bs58: &str) -> Result<Vec<u8>, String> {
+    let pk_vec = bs58::decode(pk_bs58.trim()).into_vec().map_err(|err| format!("public key {} is not base58: {}", pk_bs58, err))?;
+    libsecp256k1::PublicKey::parse_slice(&pk_vec, None).map_err(|err| format!("{} is not a public key: {:?}", pk_bs58, err))?;
+    Ok(pk_vec)
 }
 
-pub(crate) fn encrypt_file(cfg: S3Config, local_path: String) {
-    let file_content = fs::read(local_path.to_string())
-        .expect("can't open file for encryption");
-    let encrypted_content = encrypt_bytes(cfg, file_content);
-    let encrypted_path = local_path.clone() + ".x";
-    fs::write( encrypted_path.clone(), &encrypted_content).expect("error writing encrypted file");
-    println!("File {} encrypted successfully! Check {}.", local_path, encrypted_path);
+/// Encrypts for the config key and the base58 public keys of `recipients`.
+pub(crate) fn encrypt_bytes(cfg: S3Config, file_content: Vec<u8>, sealed_metadata: &BTreeMap<String, String>, recipients: &[String], compression: &str, progress: &dyn Fn(u64)) -> Result<Vec<u8>, String> {
+    let pk_vec = bs58::decode(cfg.pk_bs58).into_vec().map_err(|err| format!("public key of the config is not base58: {}", err))?;
+    let pk_bytes = pk_vec.as_slice();
+    let recipients = recipients.iter().map(|recipient| parse_public_key(recipient)).collect::<Result<Vec<_>, _>>()?;
+    seal(pk_bytes, &recipients, &file_content, sealed_metadata, compression, progress)
 }
 
-pub(crate) fn encrypt_bytes(cfg: S3Config, file_content: Vec<u8>) -> Vec<u8> {
-    let sk_vec = bs58::decode(cfg.sk_bs58).into_vec().unwrap();
-    let sk_bytes = sk_vec.as_slice();
-    let pk_vec = bs58::decode(cfg.pk_bs58).into_vec().unwrap();
-    let pk_bytes = pk_vec.as_slice();
-    encrypt(pk_bytes, &file_content).expect("file encryption error")
+/// Decrypts with the config key, an error when the config is not a recipient or has the wrong key.
+pub(crate) fn decrypt_bytes(cfg: S3Config, file_content: Vec<u8>, progress: &dyn Fn(u64)) -> Result<Vec<u8>, String> {
+    let sk_vec = bs58::decode(cfg.sk_bs58).into_vec().map_err(|err| format!("secret key of the config is not base58: {}", err))?;
+    let pk_vec = bs58::decode(cfg.pk_bs58).into_vec().map_err(|err| format!("public key of the config is not base58: {}", err))?;
+    open(&sk_vec, &pk_vec, &file_content, progress)
 }
 
-pub(crate) fn decrypt_config(cfg: S3Config) {
-    decrypt_file(cfg, String::from("/home/inspeere/.config/s3po/default-config.toml.x"));
+/// Fresh random key for a single shared object.
+pub(crate) fn random_key() -> [u8; 32] {
+    rand::thread_rng().gen()
 }
 
-pub(crate) fn decrypt_file(cfg: S3Config, encrypted_path: String) {
-    //check file extension .x exists or fail
-    if !encrypted_path.ends_with(".x") {
-        println!("{}", "error ... encrypted file must have .x extension in the end of file name".yellow());
-        return;
+/// Argon2id cost of keys derived from a passphrase, as RFC 9106 suggests with little memory.
+pub(crate) const PASSPHRASE_MEM_KIB: u32 = 64 * 1024;
+pub(crate) const PASSPHRASE_PASSES: u32 = 3;
+pub(crate) const PASSPHRASE_LANES: u32 = 4;
+
+/// Derives a symmetric key from a passphrase with Argon2id and the given cost.
+pub(crate) fn passphrase_key(passphrase: &str, salt: &[u8], mem_kib: u32, passes: u32, lanes: u32) -> Result<[u8; 32], String> {
+    let config = argon2::Config {
+        mem_cost: mem_kib,
+        time_cost: passes,
+        lanes,
+        hash_length: 32,
+        ..argon2::Config::rfc9106_low_mem()
+    };
+    let hash = argon2::hash_raw(passphrase.as_bytes(), salt, &config).map_err(|err| format!("cant derive a key from the passphrase: {}", err))?;
+    <[u8; 32]>::try_from(hash.as_slice()).map_err(|_| "argon2 gave a key of the wrong size".to_string())
+}
+
+/// Encrypts with a symmetric key, the random nonce is prepended to the ciphertext.
+pub(crate) fn encrypt_with_key(key: &[u8; 32], content: &[u8]) -> Vec<u8> {
+    let nonce: [u8; 24] = rand::thread_rng().gen();
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let mut sealed = nonce.to_vec();
+    sealed.extend(cipher.encrypt(XNonce::from_slice(&nonce), content).expect("file encryption error"));
+    sealed
+}
+
+/// Reverses `encrypt_with_key`, fails when the key is wrong or the content was changed.
+pub(crate) fn decrypt_with_key(key: &[u8; 32], sealed: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < 24 {
+        return None;
     }
-    let file_content = fs::read(encrypted_path.clone())
-        .expect("can't open file for decryption");
-    let decrypted_content = decrypt_bytes(cfg, file_content);
-    let decrypted_bytes= decrypted_content.as_slice();
-    println!("{}: {:?}", "decrypted file", str::from_utf8(decrypted_bytes).unwrap());
-    let decrypted_path = encrypted_path.strip_suffix(".x").expect("error stripping encrypted extension");
-    fs::write(decrypted_path.clone(), &decrypted_bytes).expect("error writing decrypted file");
-    println!("File {} decrypted to {} successfully!", encrypted_path, decrypted_path);
-}
-
-pub(crate) fn decrypt_bytes(cfg: S3Config, file_content: Vec<u8>) -> Vec<u8> {
-    let sk_vec = bs58::decode(cfg.sk_bs58).into_vec().unwrap();
-    let sk_bytes = sk_vec.as_slice();
-    let pk_vec = bs58::decode(cfg.pk_bs58).into_vec().unwrap();
-    let pk_bytes = pk_vec.as_slice();
-    let decrypted_content = decrypt(sk_bytes, &file_content).expect("error file decryption decryption");
-    decrypted_content.as_slice().to_vec()
+    let (nonce, ciphertext) = sealed.split_at(24);
+    XChaCha20Poly1305::new(key.into()).decrypt(XNonce::from_slice(nonce), ciphertext).ok()
 }
 
 pub(crate) fn test_crypto() {
@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use sha2::{Digest, Sha256};
+
+// metadata key that holds the sha256 of plain uploads
+pub(crate) const SHA256_META: &str = "s3po-sha256";
+
+/// How a local file relates to the remote object of the same name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Change {
+    OnlyLocal,
+    OnlyRemote,
+    Changed,
+    Same,
+    /// same size, but the object has no hash to compare with
+    SameSize,
+}
+
+/// Files under `dir` by their path relative to it, with `/` separators, and their sizes.
+pub(crate) fn local_files(dir: &Path) -> io::Result<BTreeMap<String, u64>> {
+    let mut files = BTreeMap::new();
+    let mut folders = vec![dir.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        for entry in fs::read_dir(&folder)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                folders.push(entry.path());
+            } else if file_type.is_file() {
+                let relative = entry.path().strip_prefix(dir).unwrap_or(&entry.path()).to_path_buf();
+                let name: Vec<String> = relative.components().map(|part| part.as_os_str().to_string_lossy().to_string()).collect();
+                files.insert(name.join("/"), entry.metadata()?.len());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Hex MD5 and SHA-256 of a file, read once.
+pub(crate) fn file_digests(path: &Path) -> io::Result<(String, String)> {
+    let mut file = File::open(path)?;
+    let mut md5 = md5::Context::new();
+    let mut sha256 = Sha256::new();
+    let mut buf = vec![0u8; 1024 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        md5.consume(&buf[..n]);
+        sha256.update(&buf[..n]);
+    }
+    Ok((hex(&md5.compute().0), hex(&sha256.finalize())))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
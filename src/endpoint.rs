@@ -0,0 +1,243 @@
+use std::fs;
+use std::time::Duration;
+use colored::Colorize;
+use minio::s3::error::Error;
+use minio::s3::http::BaseUrl;
+use crate::config::S3Config;
+
+/// The `BaseUrl` of `base_url` with the scheme, port, region and addressing style
+/// settings of the config applied.
+pub(crate) fn base_url(config: &S3Config) -> Result<BaseUrl, String> {
+    let raw = config.base_url.trim();
+    let url = endpoint_url(config)?;
+    let mut base_url = url.as_str().parse::<BaseUrl>().map_err(|err| format!("invalid base url {}: {}", raw, err))?;
+    let region = config.region.trim();
+    if !region.is_empty() {
+        if !base_url.region.is_empty() && base_url.region != region {
+            return Err(format!("region {} differs from region {} of the base url", region, base_url.region));
+        }
+        base_url.region = region.to_string();
+    }
+    match config.addressing_style.trim() {
+        "" | "auto" => {}
+        "path" => base_url.virtual_style = false,
+        "virtual" => base_url.virtual_style = true,
+        other => return Err(format!("unknown addressing_style \"{}\", expected auto, path or virtual", other)),
+    }
+    Ok(base_url)
+}
+
+/// Checks the endpoint settings, including that `ca_bundle` holds a PEM certificate.
+pub(crate) fn validate(config: &S3Config) -> Result<(), String> {
+    let base_url = base_url(config)?;
+    let ca_bundle = config.ca_bundle.trim();
+    if !ca_bundle.is_empty() {
+        if !base_url.https {
+            return Err(format!("ca_bundle {} is set, but the endpoint uses plain http", ca_bundle));
+        }
+        let pem = fs::read(ca_bundle).map_err(|err| format!("cant read ca_bundle {}: {}", ca_bundle, err))?;
+        reqwest::Certificate::from_pem(&pem).map_err(|err| format!("ca_bundle {} is not a PEM certificate: {}", ca_bundle, err))?;
+    }
+    Ok(())
+}
+
+// base_url with use_https and port applied
+fn endpoint_url(config: &S3Config) -> Result<reqwest::Url, String> {
+    let raw = config.base_url.trim();
+    if raw.is_empty() {
+        return Err("base_url of the config is empty".to_string());
+    }
+    // minio takes a bare host as https, the url crate needs the scheme
+    let with_scheme = match raw.contains("://") {
+        true => raw.to_string(),
+        false => format!("https://{}", raw),
+    };
+    let mut url = reqwest::Url::parse(&with_scheme).map_err(|err| format!("cant parse base url {}: {}", raw, err))?;
+    if let Some(use_https) = config.use_https {
+        // switching between the two special schemes always succeeds
+        let _ = url.set_scheme(if use_https { "https" } else { "http" });
+    }
+    if let Some(port) = config.port {
+        url.set_port(Some(port)).map_err(|_| format!("cant set port {} on base url {}", port, raw))?;
+    }
+    Ok(url)
+}
+
+/// Looks up the addresses of the endpoint host.
+pub(crate) async fn lookup(config: &S3Config) -> Result<String, String> {
+    let url = endpoint_url(config)?;
+    let host = url.host_str().ok_or(format!("base url {} has no host", url))?.trim_matches(['[', ']']).to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<String> = tokio::net::lookup_host((host.as_str(), port)).await
+        .map_err(|err| format!("cant resolve {}: {}", host, err))?
+        .map(|addr| addr.ip().to_string())
+        .collect();
+    match addrs.is_empty() {
+        true => Err(format!("{} has no addresses", host)),
+        false => Ok(format!("{} is {}", host, addrs.join(", "))),
+    }
+}
+
+/// Sends a plain GET to the endpoint with the TLS settings of the config. Returns what
+/// answered and the difference of the server clock to the local one, in seconds.
+pub(crate) async fn probe(config: &S3Config) -> Result<(String, Option<i64>), String> {
+    let url = endpoint_url(config)?;
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .danger_accept_invalid_certs(config.insecure_skip_verify);
+    let ca_bundle = config.ca_bundle.trim();
+    if !ca_bundle.is_empty() {
+        let pem = fs::read(ca_bundle).map_err(|err| format!("cant read ca_bundle {}: {}", ca_bundle, err))?;
+        let cert = reqwest::Certificate::from_pem(&pem).map_err(|err| format!("ca_bundle {} is not a PEM certificate: {}", ca_bundle, err))?;
+        builder = builder.add_root_certificate(cert);
+    }
+    let http = builder.build().map_err(|err| error_chain(&err))?;
+    let resp = http.get(url.clone()).send().await.map_err(|err| error_chain(&err))?;
+    let answered = match url.scheme() {
+        "https" => format!("TLS handshake with {} done, HTTP {}", url.host_str().unwrap_or(""), resp.status()),
+        _ => format!("plain http, {} answered HTTP {}", url.host_str().unwrap_or(""), resp.status()),
+    };
+    let skew = resp.headers().get(reqwest::header::DATE)
+        .and_then(|date| date.to_str().ok())
+        .and_then(|date| chrono::DateTime::parse_from_rfc2822(date).ok())
+        .map(|date| date.with_timezone(&chrono::Utc).signed_duration_since(chrono::Utc::now()).num_seconds());
+    Ok((answered, skew))
+}
+
+/// An error with all of its causes, reqwest keeps the interesting part in the sources.
+pub(crate) fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut text = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        // hyper repeats the message of its cause in its own
+        let cause_text = cause.to_string();
+        if !text.contains(&cause_text) {
+            text += &format!(": {}", cause_text);
+        }
+        source = cause.source();
+    }
+    text
+}
+
+/// A minio error with the causes of http errors, which its Display leaves out.
+pub(crate) fn describe_error(err: &Error) -> String {
+    match err {
+        Error::HttpError(err) => error_chain(err),
+        err => err.to_string(),
+    }
+}
+
+// a config with a bad endpoint still loads, so it can be printed and fixed
+pub(crate) fn check_endpoint(config: &S3Config) {
+    if let Err(err) = validate(config) {
+        println!("{}", err.yellow());
+    }
+    if config.insecure_skip_verify {
+        println!("{}", "insecure_skip_verify is on, server certificates are not checked".yellow());
+    }
+}
+
+/// One line about where requests go, for `config print`.
+pub(crate) fn describe_endpoint(config: &S3Config) -> String {
+    let base_url = match base_url(config) {
+        Ok(base_url) => base_url,
+        Err(err) => return err,
+    };
+    let mut parts = vec![
+        match base_url.https {
+            true => "https".to_string(),
+            false => "plain http".to_string(),
+        },
+        match base_url.region.is_empty() {
+            true => "region from the server".to_string(),
+            false => format!("region {}", base_url.region),
+        },
+        match base_url.virtual_style {
+            true => "virtual host style".to_string(),
+            false => "path style".to_string(),
+        },
+    ];
+    if let Some(port) = config.port {
+        parts.push(format!("port {}", port));
+    }
+    if !config.ca_bundle.trim().is_empty() {
+        parts.push(format!("ca bundle {}", config.ca_bundle.trim()));
+    }
+    if config.insecure_skip_verify {
+        parts.push("certificate check off".to_string());
+    }
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use super::*;
+
+    fn config(base_url: &str) -> S3Config {
+        S3Config { base_url: base_url.to_string(), ..S3Config::default() }
+    }
+
+    #[test]
+    fn regions_must_agree() {
+        let mut aws = config("https://s3.eu-west-1.amazonaws.com");
+        assert_eq!(base_url(&aws).unwrap().region, "eu-west-1");
+        aws.region = "eu-west-1".to_string();
+        assert!(base_url(&aws).is_ok());
+        aws.region = "us-east-1".to_string();
+        assert!(base_url(&aws).unwrap_err().contains("differs from region eu-west-1"));
+
+        let mut other = config("https://storage.example.com");
+        other.region = "fra1".to_string();
+        assert_eq!(base_url(&other).unwrap().region, "fra1");
+    }
+
+    #[test]
+    fn addressing_style() {
+        let mut aws = config("https://s3.eu-west-1.amazonaws.com");
+        assert!(base_url(&aws).unwrap().virtual_style);
+        aws.addressing_style = "path".to_string();
+        assert!(!base_url(&aws).unwrap().virtual_style);
+
+        let mut local = config("http://127.0.0.1:9000");
+        assert!(!base_url(&local).unwrap().virtual_style);
+        local.addressing_style = "virtual".to_string();
+        assert!(base_url(&local).unwrap().virtual_style);
+        local.addressing_style = "host".to_string();
+        assert!(base_url(&local).unwrap_err().contains("unknown addressing_style"));
+    }
+
+    #[test]
+    fn scheme_and_port_settings() {
+        let mut bare = config("storage.example.com");
+        assert!(base_url(&bare).unwrap().https);
+        bare.use_https = Some(false);
+        bare.port = Some(8080);
+        let url = endpoint_url(&bare).unwrap();
+        assert_eq!(url.as_str(), "http://storage.example.com:8080/");
+        assert!(base_url(&config("")).unwrap_err().contains("empty"));
+    }
+
+    #[test]
+    fn ca_bundles_must_be_pem() {
+        let path = env::temp_dir().join(format!("s3po-ca-{}.pem", std::process::id()));
+        fs::write(&path, "not a certificate").unwrap();
+        let mut https = config("https://storage.example.com");
+        https.ca_bundle = path.to_string_lossy().to_string();
+        let err = validate(&https).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(err.contains("is not a PEM certificate"), "{}", err);
+        assert!(validate(&https).unwrap_err().contains("cant read ca_bundle"));
+
+        let mut http = config("http://127.0.0.1:9000");
+        http.ca_bundle = https.ca_bundle.clone();
+        assert!(validate(&http).unwrap_err().contains("plain http"));
+    }
+
+    #[tokio::test]
+    async fn lookup_resolves_the_host() {
+        let found = lookup(&config("http://localhost:9000")).await.unwrap();
+        assert!(found.starts_with("localhost is "), "{}", found);
+        assert!(lookup(&config("https://no-such-host.invalid")).await.unwrap_err().contains("cant resolve"));
+    }
+}
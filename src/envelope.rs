@@ -0,0 +1,404 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use chacha20poly1305::{aead::{Aead, KeyInit, Payload}, XChaCha20Poly1305, XNonce};
+use ecies::{decrypt, encrypt};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// every enveloped object starts with MAGIC, the format version and the header length
+pub(crate) const MAGIC: &[u8; 4] = b"S3PO";
+pub(crate) const VERSION: u8 = 1;
+pub(crate) const PREFIX_LEN: usize = 4 + 1 + 4;
+const CIPHER: &str = "xchacha20poly1305";
+const CHUNK_SIZE: usize = 1024 * 1024;
+const TAG_LEN: usize = 16;
+/// Headers are a few hundred bytes per recipient, anything bigger is not ours.
+pub(crate) const MAX_HEADER_LEN: usize = 1024 * 1024;
+// nonce indexes of the sealed metadata and plaintext hash, past any chunk index
+const METADATA_INDEX: u64 = u64::MAX;
+const SHA256_INDEX: u64 = u64::MAX - 1;
+/// Compressions the header may name, the content is compressed before it is split in chunks.
+pub(crate) const COMPRESSIONS: [&str; 2] = ["none", "deflate"];
+// raw ECIES output from before the envelope: ephemeral public key, nonce and tag
+pub(crate) const LEGACY_OVERHEAD: u64 = 65 + 24 + 16;
+
+/// A data key wrapped for one public key with ECIES.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Recipient {
+    pub(crate) fingerprint: String,
+    pub(crate) wrapped_key: String,
+}
+
+/// Clear text header of an encrypted object. It is authenticated together with
+/// every chunk, so changing it breaks decryption.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Header {
+    pub(crate) version: u8,
+    pub(crate) cipher: String,
+    pub(crate) fingerprint: String,
+    pub(crate) recipients: Vec<Recipient>,
+    pub(crate) compression: String,
+    pub(crate) chunk_size: usize,
+    pub(crate) plaintext_size: u64,
+    pub(crate) nonce_prefix: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) sealed_metadata: Option<String>,
+    // lets diff compare with local files without downloading, encrypted with the data
+    // key so the hash can't confirm a guessed file, missing in older objects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) sealed_sha256: Option<String>,
+    // the hash in clear text, only read from objects written before sealed_sha256
+    #[serde(default, skip_serializing)]
+    pub(crate) plaintext_sha256: Option<String>,
+}
+
+/// Short id of a public key: hex of the first 8 bytes of its SHA-256.
+pub(crate) fn fingerprint(pk: &[u8]) -> String {
+    Sha256::digest(pk)[..8].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Encrypts `content` with a random data key in chunks and wraps the key for `pk` and
+/// every key of `recipients`. Non empty `metadata` is encrypted with the same key and
+/// kept in the header. `compression` is one of `COMPRESSIONS`. `progress` gets the
+/// plaintext bytes done after every chunk.
+pub(crate) fn seal(pk: &[u8], recipients: &[Vec<u8>], content: &[u8], metadata: &BTreeMap<String, String>, compression: &str, progress: &dyn Fn(u64)) -> Result<Vec<u8>, String> {
+    let data_key: [u8; 32] = rand::thread_rng().gen();
+    let nonce_prefix: [u8; 16] = rand::thread_rng().gen();
+    let mut wrapped = vec![wrap_key(pk, &data_key)?];
+    for recipient in recipients {
+        if wrapped.iter().all(|known: &Recipient| known.fingerprint != fingerprint(recipient)) {
+            wrapped.push(wrap_key(recipient, &data_key)?);
+        }
+    }
+    let cipher = XChaCha20Poly1305::new(&data_key.into());
+    let seal_field = |index: u64, field: &[u8]| {
+        let nonce = chunk_nonce(&nonce_prefix, index);
+        bs58::encode(cipher.encrypt(XNonce::from_slice(&nonce), field).expect("header field encryption error")).into_string()
+    };
+    let sealed_metadata = match metadata.is_empty() {
+        true => None,
+        false => Some(seal_field(METADATA_INDEX, &serde_json::to_vec(metadata).expect("error writing sealed metadata"))),
+    };
+    let sha256: String = Sha256::digest(content).iter().map(|byte| format!("{:02x}", byte)).collect();
+    let header = Header {
+        version: VERSION,
+        cipher: CIPHER.to_string(),
+        fingerprint: fingerprint(pk),
+        recipients: wrapped,
+        compression: compression.to_string(),
+        chunk_size: CHUNK_SIZE,
+        plaintext_size: content.len() as u64,
+        nonce_prefix: bs58::encode(nonce_prefix).into_string(),
+        sealed_metadata,
+        sealed_sha256: Some(seal_field(SHA256_INDEX, sha256.as_bytes())),
+        plaintext_sha256: None,
+    };
+    let header_bytes = serde_json::to_vec(&header).expect("error writing encryption header");
+    let compressed = compress(compression, content)?;
+    let plaintext_size = content.len() as u64;
+    let content = compressed.as_slice();
+
+    let mut sealed = Vec::with_capacity(PREFIX_LEN + header_bytes.len() + content.len() + (content.len() / CHUNK_SIZE + 1) * TAG_LEN);
+    sealed.extend_from_slice(MAGIC);
+    sealed.push(VERSION);
+    sealed.extend_from_slice(&(header_bytes.len() as u32).to_be_bytes());
+    sealed.extend_from_slice(&header_bytes);
+
+    let chunks: Vec<&[u8]> = match content.is_empty() {
+        true => vec![&[]],
+        false => content.chunks(CHUNK_SIZE).collect(),
+    };
+    for (index, chunk) in chunks.iter().enumerate() {
+        let aad = chunk_aad(&header_bytes, index + 1 == chunks.len());
+        let nonce = chunk_nonce(&nonce_prefix, index as u64);
+        sealed.extend(cipher.encrypt(XNonce::from_slice(&nonce), Payload { msg: chunk, aad: &aad }).expect("file encryption error"));
+        // compressed chunks stand for a share of the plaintext
+        progress(chunk.len() as u64 * plaintext_size / content.len().max(1) as u64);
+    }
+    Ok(sealed)
+}
+
+fn wrap_key(pk: &[u8], data_key: &[u8; 32]) -> Result<Recipient, String> {
+    let wrapped_key = encrypt(pk, data_key).map_err(|err| format!("cant encrypt the data key for key {}: {}", fingerprint(pk), err))?;
+    Ok(Recipient { fingerprint: fingerprint(pk), wrapped_key: bs58::encode(wrapped_key).into_string() })
+}
+
+fn compress(compression: &str, content: &[u8]) -> Result<Vec<u8>, String> {
+    match compression {
+        "none" => Ok(content.to_vec()),
+        "deflate" => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(content).and_then(|_| encoder.finish()).map_err(|err| format!("compression error: {}", err))
+        }
+        other => Err(format!("unknown compression \"{}\", expected {}", other, COMPRESSIONS.join(" or "))),
+    }
+}
+
+fn decompress(compression: &str, content: Vec<u8>, size: u64) -> Result<Vec<u8>, String> {
+    match compression {
+        "none" => Ok(content),
+        "deflate" => {
+            let mut plain = Vec::with_capacity(content.len());
+            // one byte past the size is enough to tell that the content is wrong
+            DeflateDecoder::new(content.as_slice()).take(size.saturating_add(1)).read_to_end(&mut plain).map_err(|err| format!("decompression error: {}", err))?;
+            Ok(plain)
+        }
+        other => Err(format!("unsupported compression {}, update s3po", other)),
+    }
+}
+
+/// Length of the header that follows the prefix, `None` when `prefix` is not an envelope.
+pub(crate) fn header_len(prefix: &[u8]) -> Option<usize> {
+    if prefix.len() < PREFIX_LEN || &prefix[..4] != MAGIC {
+        return None;
+    }
+    Some(u32::from_be_bytes(prefix[5..9].try_into().ok()?) as usize)
+}
+
+pub(crate) fn parse_header(header_bytes: &[u8]) -> Result<Header, String> {
+    serde_json::from_slice(header_bytes).map_err(|err| format!("broken encryption header: {}", err))
+}
+
+/// Decrypts an envelope with the secret key, objects from before the envelope are
+/// decrypted as plain ECIES. `progress` gets the sealed bytes done after every chunk.
+pub(crate) fn open(sk: &[u8], pk: &[u8], sealed: &[u8], progress: &dyn Fn(u64)) -> Result<Vec<u8>, String> {
+    let Some(len) = header_len(sealed) else {
+        return decrypt(sk, sealed).map_err(|err| format!("decryption error: {}", err));
+    };
+    if len > MAX_HEADER_LEN {
+        return Err(format!("broken encryption header: {} bytes long", len));
+    }
+    let header_bytes = sealed.get(PREFIX_LEN..PREFIX_LEN + len).ok_or("encrypted file is truncated")?;
+    let header = parse_header(header_bytes)?;
+    if sealed[4] > VERSION || header.cipher != CIPHER {
+        return Err(format!("unsupported encryption format {} ({}), update s3po", sealed[4], header.cipher));
+    }
+    if !COMPRESSIONS.contains(&header.compression.as_str()) {
+        return Err(format!("unsupported compression {}, update s3po", header.compression));
+    }
+    // nothing in the header is authenticated yet, so sizes are only trusted as far as the body goes
+    let body = &sealed[PREFIX_LEN + len..];
+    let chunk_len = header.chunk_size.checked_add(TAG_LEN)
+        .filter(|_| header.chunk_size > 0)
+        .ok_or("broken encryption header: bad chunk size")?;
+    let data_key = unwrap_key(sk, pk, &header)?;
+    let nonce_prefix = nonce_prefix(&header)?;
+
+    let cipher = XChaCha20Poly1305::new(&data_key.into());
+    // seal writes at least one chunk, and only chunks authenticate the header
+    if body.is_empty() {
+        return Err("decryption error: file is damaged or was changed".to_string());
+    }
+    let chunks: Vec<&[u8]> = body.chunks(chunk_len).collect();
+    let mut content = Vec::with_capacity(body.len());
+    for (index, chunk) in chunks.iter().enumerate() {
+        let aad = chunk_aad(header_bytes, index + 1 == chunks.len());
+        let nonce = chunk_nonce(&nonce_prefix, index as u64);
+        let plain = cipher.decrypt(XNonce::from_slice(&nonce), Payload { msg: chunk, aad: &aad })
+            .map_err(|_| "decryption error: file is damaged or was changed".to_string())?;
+        content.extend(plain);
+        progress(chunk.len() as u64);
+    }
+    let content = decompress(&header.compression, content, header.plaintext_size)?;
+    if content.len() as u64 != header.plaintext_size {
+        return Err("decryption error: file is truncated".to_string());
+    }
+    Ok(content)
+}
+
+/// Decrypts the metadata sealed in `header`, empty when there is none.
+pub(crate) fn open_metadata(sk: &[u8], pk: &[u8], header: &Header) -> Result<BTreeMap<String, String>, String> {
+    let Some(sealed_metadata) = &header.sealed_metadata else {
+        return Ok(BTreeMap::new());
+    };
+    let json = open_field(sk, pk, header, sealed_metadata, METADATA_INDEX, "sealed metadata")?;
+    serde_json::from_slice(&json).map_err(|err| format!("broken sealed metadata: {}", err))
+}
+
+/// The hex SHA-256 of the plaintext, None for objects written without one.
+pub(crate) fn open_sha256(sk: &[u8], pk: &[u8], header: &Header) -> Result<Option<String>, String> {
+    let Some(sealed_sha256) = &header.sealed_sha256 else {
+        return Ok(header.plaintext_sha256.clone());
+    };
+    let sha256 = open_field(sk, pk, header, sealed_sha256, SHA256_INDEX, "sealed hash")?;
+    String::from_utf8(sha256).map(Some).map_err(|_| "broken encryption header: bad sealed hash".to_string())
+}
+
+fn open_field(sk: &[u8], pk: &[u8], header: &Header, field: &str, index: u64, what: &str) -> Result<Vec<u8>, String> {
+    let data_key = unwrap_key(sk, pk, header)?;
+    let nonce = chunk_nonce(&nonce_prefix(header)?, index);
+    let sealed = bs58::decode(field).into_vec().map_err(|_| format!("broken encryption header: bad {}", what))?;
+    XChaCha20Poly1305::new(&data_key.into()).decrypt(XNonce::from_slice(&nonce), sealed.as_slice())
+        .map_err(|_| format!("decryption error: {} was changed", what))
+}
+
+fn nonce_prefix(header: &Header) -> Result<[u8; 16], String> {
+    bs58::decode(&header.nonce_prefix).into_vec().ok()
+        .and_then(|prefix| prefix.try_into().ok())
+        .ok_or("broken encryption header: bad nonce".to_string())
+}
+
+fn unwrap_key(sk: &[u8], pk: &[u8], header: &Header) -> Result<[u8; 32], String> {
+    let own = fingerprint(pk);
+    let recipient = header.recipients.iter().find(|recipient| recipient.fingerprint == own).ok_or_else(|| {
+        let fingerprints: Vec<&str> = header.recipients.iter().map(|recipient| recipient.fingerprint.as_str()).collect();
+        format!("not a recipient: file is encrypted for key(s) {}, this config has key {}", fingerprints.join(", "), own)
+    })?;
+    let wrapped_key = bs58::decode(&recipient.wrapped_key).into_vec().map_err(|_| "broken encryption header: bad wrapped key")?;
+    decrypt(sk, &wrapped_key).ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or("wrong key: this config's secret key can't unwrap the data key".to_string())
+}
+
+// the header and whether it is the last chunk are authenticated, so chunks
+// can't be cut off or moved between files
+fn chunk_aad(header_bytes: &[u8], last: bool) -> Vec<u8> {
+    let mut aad = header_bytes.to_vec();
+    aad.push(last as u8);
+    aad
+}
+
+fn chunk_nonce(prefix: &[u8; 16], index: u64) -> [u8; 24] {
+    let mut nonce = [0u8; 24];
+    nonce[..16].copy_from_slice(prefix);
+    nonce[16..].copy_from_slice(&index.to_be_bytes());
+    nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use ecies::utils::generate_keypair;
+    use super::*;
+
+    fn keypair() -> (Vec<u8>, Vec<u8>) {
+        let (sk, pk) = generate_keypair();
+        (sk.serialize().to_vec(), pk.serialize().to_vec())
+    }
+
+    fn header_of(sealed: &[u8]) -> Header {
+        let len = header_len(sealed).unwrap();
+        parse_header(&sealed[PREFIX_LEN..PREFIX_LEN + len]).unwrap()
+    }
+
+    // the same envelope with its header changed by `change`
+    fn with_header(sealed: &[u8], change: impl Fn(&mut serde_json::Value)) -> Vec<u8> {
+        let len = header_len(sealed).unwrap();
+        let mut header: serde_json::Value = serde_json::from_slice(&sealed[PREFIX_LEN..PREFIX_LEN + len]).unwrap();
+        change(&mut header);
+        let header_bytes = serde_json::to_vec(&header).unwrap();
+        let mut changed = sealed[..5].to_vec();
+        changed.extend((header_bytes.len() as u32).to_be_bytes());
+        changed.extend(header_bytes);
+        changed.extend(&sealed[PREFIX_LEN + len..]);
+        changed
+    }
+
+    #[test]
+    fn round_trip() {
+        let (sk, pk) = keypair();
+        let content: Vec<u8> = (0..CHUNK_SIZE * 2 + 100).map(|i| (i % 251) as u8).collect();
+        for compression in COMPRESSIONS {
+            let sealed_bytes = std::cell::Cell::new(0);
+            let sealed = seal(&pk, &[], &content, &BTreeMap::new(), compression, &|bytes| sealed_bytes.set(sealed_bytes.get() + bytes)).unwrap();
+            assert!(sealed_bytes.get() <= content.len() as u64, "{}", compression);
+            let opened_bytes = std::cell::Cell::new(0);
+            assert_eq!(open(&sk, &pk, &sealed, &|bytes| opened_bytes.set(opened_bytes.get() + bytes)).unwrap(), content, "{}", compression);
+            assert!(opened_bytes.get() > 0, "{}", compression);
+        }
+        let reported = std::cell::Cell::new(0);
+        seal(&pk, &[], &content, &BTreeMap::new(), "none", &|bytes| reported.set(reported.get() + bytes)).unwrap();
+        assert_eq!(reported.get(), content.len() as u64);
+        let empty = seal(&pk, &[], &[], &BTreeMap::new(), "none", &|_| {}).unwrap();
+        assert_eq!(open(&sk, &pk, &empty, &|_| {}).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn changed_bytes_are_refused() {
+        let (sk, pk) = keypair();
+        let sealed = seal(&pk, &[], b"some content", &BTreeMap::new(), "none", &|_| {}).unwrap();
+        let mut flipped = sealed.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert!(open(&sk, &pk, &flipped, &|_| {}).unwrap_err().contains("damaged or was changed"));
+        let resized = with_header(&sealed, |header| header["plaintext_size"] = 5.into());
+        assert!(open(&sk, &pk, &resized, &|_| {}).is_err());
+    }
+
+    #[test]
+    fn truncated_files_are_refused() {
+        let (sk, pk) = keypair();
+        let content = vec![7u8; CHUNK_SIZE * 2 + 100];
+        let sealed = seal(&pk, &[], &content, &BTreeMap::new(), "none", &|_| {}).unwrap();
+        // cut at a chunk boundary, the last chunk left is not marked as the last one
+        let whole_chunks = sealed.len() - 100 - TAG_LEN;
+        assert!(open(&sk, &pk, &sealed[..whole_chunks], &|_| {}).is_err());
+        assert!(open(&sk, &pk, &sealed[..sealed.len() - 1], &|_| {}).is_err());
+        assert!(open(&sk, &pk, &sealed[..PREFIX_LEN + 3], &|_| {}).unwrap_err().contains("truncated"));
+    }
+
+    #[test]
+    fn empty_bodies_are_refused() {
+        let (sk, pk) = keypair();
+        let sealed = seal(&pk, &[], b"", &BTreeMap::new(), "none", &|_| {}).unwrap();
+        assert_eq!(open(&sk, &pk, &sealed, &|_| {}), Ok(vec![]));
+        // only the header left, a changed header would go unnoticed
+        let header_only = &sealed[..PREFIX_LEN + header_len(&sealed).unwrap()];
+        assert!(open(&sk, &pk, header_only, &|_| {}).unwrap_err().contains("damaged or was changed"));
+        let changed = with_header(header_only, |header| header["compression"] = "deflate".into());
+        assert!(open(&sk, &pk, &changed, &|_| {}).is_err());
+    }
+
+    #[test]
+    fn other_keys_are_refused() {
+        let (sk, pk) = keypair();
+        let (other_sk, other_pk) = keypair();
+        let sealed = seal(&pk, &[], b"some content", &BTreeMap::new(), "none", &|_| {}).unwrap();
+        assert!(open(&other_sk, &other_pk, &sealed, &|_| {}).unwrap_err().starts_with("not a recipient"));
+        // a header claiming the other key holds the data key
+        let claimed = with_header(&sealed, |header| header["recipients"][0]["fingerprint"] = fingerprint(&other_pk).into());
+        assert!(open(&other_sk, &other_pk, &claimed, &|_| {}).unwrap_err().starts_with("wrong key"));
+        let shared = seal(&pk, std::slice::from_ref(&other_pk), b"some content", &BTreeMap::new(), "none", &|_| {}).unwrap();
+        assert_eq!(open(&other_sk, &other_pk, &shared, &|_| {}).unwrap(), b"some content");
+        assert_eq!(open(&sk, &pk, &shared, &|_| {}).unwrap(), b"some content");
+    }
+
+    #[test]
+    fn header_sizes_are_not_trusted() {
+        let (sk, pk) = keypair();
+        let sealed = seal(&pk, &[], b"some content", &BTreeMap::new(), "deflate", &|_| {}).unwrap();
+        let huge = with_header(&sealed, |header| {
+            header["chunk_size"] = u64::MAX.into();
+            header["plaintext_size"] = u64::MAX.into();
+        });
+        assert!(open(&sk, &pk, &huge, &|_| {}).is_err());
+        let zero = with_header(&sealed, |header| header["chunk_size"] = 0.into());
+        assert!(open(&sk, &pk, &zero, &|_| {}).unwrap_err().contains("bad chunk size"));
+        let mut long_header = sealed.clone();
+        long_header[5..9].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(open(&sk, &pk, &long_header, &|_| {}).unwrap_err().contains("broken encryption header"));
+    }
+
+    #[test]
+    fn plaintext_hash_is_not_in_clear() {
+        let (sk, pk) = keypair();
+        let content = b"a file someone could guess".to_vec();
+        let sealed = seal(&pk, &[], &content, &BTreeMap::new(), "none", &|_| {}).unwrap();
+        let hex: String = Sha256::digest(&content).iter().map(|byte| format!("{:02x}", byte)).collect();
+        let len = header_len(&sealed).unwrap();
+        let header_text = String::from_utf8_lossy(&sealed[PREFIX_LEN..PREFIX_LEN + len]).to_string();
+        assert!(!header_text.contains(&hex));
+        assert!(!header_text.contains("plaintext_sha256"));
+        assert_eq!(open_sha256(&sk, &pk, &header_of(&sealed)), Ok(Some(hex)));
+        let (other_sk, other_pk) = keypair();
+        assert!(open_sha256(&other_sk, &other_pk, &header_of(&sealed)).is_err());
+    }
+
+    #[test]
+    fn old_headers_keep_their_clear_hash() {
+        let header: Header = serde_json::from_str(r#"{"version":1,"cipher":"xchacha20poly1305","fingerprint":"00","recipients":[],
+            "compression":"none","chunk_size":1048576,"plaintext_size":0,"nonce_prefix":"1","plaintext_sha256":"abc"}"#).unwrap();
+        assert_eq!(open_sha256(&[], &[], &header), Ok(Some("abc".to_string())));
+    }
+}
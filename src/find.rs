@@ -0,0 +1,184 @@
+use chrono::Duration;
+use minio::s3::types::Item;
+use minio::s3::utils::UtcTime;
+use crate::command::{take_flag, take_option};
+use crate::units::{parse_duration, parse_size};
+
+/// What `find` does with the objects that match.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Action {
+    Print,
+    Print0,
+    Delete,
+    Get,
+}
+
+/// Tests of `find`, an object has to pass all of them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Filter {
+    name: Option<String>,
+    path: Option<String>,
+    larger: Option<u64>,
+    smaller: Option<u64>,
+    older: Option<i64>,
+    newer: Option<i64>,
+    encrypted: Option<bool>,
+    storage_class: Option<String>,
+}
+
+impl Filter {
+    /// Removes the tests from `args`, e.g. `-name '*.sql.x' -larger 1G -older 30d -encrypted`.
+    pub(crate) fn take(args: &mut Vec<String>) -> Result<Filter, String> {
+        let mut filter = Filter {
+            name: take_option(args, "-name").map(|name| unquote(&name)),
+            path: take_option(args, "-path").map(|path| unquote(&path)),
+            storage_class: take_option(args, "-storage-class").map(|class| class.to_uppercase()),
+            ..Filter::default()
+        };
+        if let Some(size) = take_option(args, "-larger") {
+            filter.larger = Some(parse_size(&size).ok_or(format!("-larger takes a size like 100M or 1G, got {}", size))?);
+        }
+        if let Some(size) = take_option(args, "-smaller") {
+            filter.smaller = Some(parse_size(&size).ok_or(format!("-smaller takes a size like 100M or 1G, got {}", size))?);
+        }
+        if let Some(age) = take_option(args, "-older") {
+            filter.older = Some(parse_duration(&age).ok_or(format!("-older takes an age like 12h or 30d, got {}", age))? as i64);
+        }
+        if let Some(age) = take_option(args, "-newer") {
+            filter.newer = Some(parse_duration(&age).ok_or(format!("-newer takes an age like 12h or 30d, got {}", age))? as i64);
+        }
+        match (take_flag(args, &["-encrypted"]), take_flag(args, &["-plain"])) {
+            (true, true) => return Err("-encrypted and -plain exclude each other".to_string()),
+            (true, false) => filter.encrypted = Some(true),
+            (false, true) => filter.encrypted = Some(false),
+            (false, false) => {}
+        }
+        Ok(filter)
+    }
+
+    pub(crate) fn matches(&self, item: &Item, now: UtcTime) -> bool {
+        let size = item.size.unwrap_or(0) as u64;
+        let age = item.last_modified.map(|last_modified| now.signed_duration_since(last_modified));
+        let base_name = item.name.rsplit('/').next().unwrap_or(&item.name);
+        self.name.as_ref().is_none_or(|name| glob(name, base_name))
+            && self.path.as_ref().is_none_or(|path| glob(path, &item.name))
+            && self.larger.is_none_or(|larger| size > larger)
+            && self.smaller.is_none_or(|smaller| size < smaller)
+            && self.older.is_none_or(|secs| age.is_some_and(|age| age > Duration::seconds(secs)))
+            && self.newer.is_none_or(|secs| age.is_some_and(|age| age < Duration::seconds(secs)))
+            && self.encrypted.is_none_or(|encrypted| item.name.ends_with(".x") == encrypted)
+            && self.storage_class.as_ref().is_none_or(|class| item.storage_class.as_deref().unwrap_or("STANDARD") == class)
+    }
+}
+
+/// Removes the action from `args`, -print when none is given.
+pub(crate) fn take_action(args: &mut Vec<String>) -> Result<Action, String> {
+    let actions: Vec<Action> = [("-print", Action::Print), ("-print0", Action::Print0), ("-delete", Action::Delete), ("-get", Action::Get)]
+        .into_iter()
+        .filter(|(name, _)| take_flag(args, &[name]))
+        .map(|(_, action)| action)
+        .collect();
+    match actions[..] {
+        [] => Ok(Action::Print),
+        [action] => Ok(action),
+        _ => Err("give only one of -print, -print0, -delete and -get".to_string()),
+    }
+}
+
+// '*' matches any run of characters, '?' a single one
+pub(crate) fn glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // position after the last '*' and the text position it was tried at
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if let Some((after_star, tried)) = star {
+            p = after_star;
+            t = tried + 1;
+            star = Some((after_star, tried + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+// the console keeps the quotes a shell would remove
+fn unquote(value: &str) -> String {
+    value.trim_matches(|c| c == '\'' || c == '"').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn item(name: &str, size: usize, days_old: i64, now: UtcTime) -> Item {
+        Item {
+            name: name.to_string(),
+            size: Some(size),
+            last_modified: Some(now - Duration::days(days_old)),
+            ..Item::default()
+        }
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob("*.sql.x", "dump.sql.x"));
+        assert!(glob("*", ""));
+        assert!(glob("a*b*c", "aXXbYYc"));
+        assert!(glob("a*b", "abab"));
+        assert!(glob("log-????.txt", "log-2024.txt"));
+        assert!(!glob("log-????.txt", "log-24.txt"));
+        assert!(!glob("*.sql", "dump.sql.x"));
+        assert!(!glob("", "a"));
+        assert!(glob("logs/*/app.log", "logs/2024/app.log"));
+    }
+
+    #[test]
+    fn filters_are_taken_from_the_args() {
+        let mut line = args("bucket/logs -name '*.log' -larger 1K -older 30d -encrypted -storage-class glacier -delete");
+        let filter = Filter::take(&mut line).unwrap();
+        assert_eq!(take_action(&mut line), Ok(Action::Delete));
+        assert_eq!(line, args("bucket/logs"));
+        assert_eq!(filter.name.as_deref(), Some("*.log"));
+        assert_eq!(filter.larger, Some(1024));
+        assert_eq!(filter.older, Some(30 * 24 * 60 * 60));
+        assert_eq!(filter.encrypted, Some(true));
+        assert_eq!(filter.storage_class.as_deref(), Some("GLACIER"));
+
+        assert!(Filter::take(&mut args("-larger big")).unwrap_err().contains("-larger"));
+        assert!(Filter::take(&mut args("-newer soon")).unwrap_err().contains("-newer"));
+        assert!(Filter::take(&mut args("-encrypted -plain")).is_err());
+        assert_eq!(take_action(&mut args("b")), Ok(Action::Print));
+        assert!(take_action(&mut args("-print -get")).is_err());
+    }
+
+    #[test]
+    fn filters_match_items() {
+        let now = Utc.with_ymd_and_hms(2026, 6, 1, 0, 0, 0).unwrap();
+        let filter = Filter::take(&mut args("-name *.log.x -larger 1K -older 30d -encrypted")).unwrap();
+        assert!(filter.matches(&item("logs/app.log.x", 2048, 40, now), now));
+        assert!(!filter.matches(&item("logs/app.log.x", 512, 40, now), now));
+        assert!(!filter.matches(&item("logs/app.log.x", 2048, 10, now), now));
+        assert!(!filter.matches(&item("logs/app.log", 2048, 40, now), now));
+        // -name looks at the last segment, -path at the whole key
+        assert!(!Filter::take(&mut args("-name logs*")).unwrap().matches(&item("logs/app.log", 1, 1, now), now));
+        assert!(Filter::take(&mut args("-path logs/*")).unwrap().matches(&item("logs/app.log", 1, 1, now), now));
+        let glacier = Item { storage_class: Some("GLACIER".to_string()), ..item("a", 1, 1, now) };
+        let filter = Filter::take(&mut args("-storage-class standard")).unwrap();
+        assert!(filter.matches(&item("a", 1, 1, now), now));
+        assert!(!filter.matches(&glacier, now));
+    }
+}
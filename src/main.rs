@@ -3,6 +3,22 @@ mod config;
 mod s3;
 mod crypto;
 mod command;
+mod progress;
+mod retry;
+mod throttle;
+mod units;
+mod envelope;
+mod bucket;
+mod sse;
+mod usage;
+mod find;
+mod diff;
+mod creds;
+mod profiles;
+mod endpoint;
+mod migrate;
+mod bundle;
+mod rules;
 
 use crate::command::parse_args;
 use crate::console::{print_intro, console_loop};
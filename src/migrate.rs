@@ -0,0 +1,138 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use colored::Colorize;
+use toml_edit::{value, Document, Item};
+
+/// Schema version written into new configs. Files without `version` are version 0.
+pub(crate) const CONFIG_VERSION: u32 = 1;
+
+// MIGRATIONS[n] upgrades a config from version n to n + 1, comments and key order stay
+const MIGRATIONS: [fn(&mut Document, &str); CONFIG_VERSION as usize] = [to_v1];
+
+/// Upgrades the config file at `path` to `CONFIG_VERSION` in place, after copying it to
+/// a timestamped backup. A file from a newer s3po is refused and left untouched.
+pub(crate) fn migrate(path: &Path, config_name: &str) -> Result<(), String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        // confy creates missing files itself
+        Err(_) if !path.exists() => return Ok(()),
+        Err(err) => return Err(format!("cant read config {}: {}", path.display(), err)),
+    };
+    let mut doc: Document = text.parse().map_err(|err| format!("config {} is not valid toml: {}", path.display(), err))?;
+    let version = match doc.get("version") {
+        None => 0,
+        Some(item) => match item.as_integer() {
+            Some(version) if version >= 0 => version as u32,
+            _ => return Err(format!("config {} has an invalid version {}", path.display(), item.to_string().trim())),
+        },
+    };
+    if version > CONFIG_VERSION {
+        return Err(format!("config {} has version {}, this s3po knows up to version {}; update s3po, the file was not changed", path.display(), version, CONFIG_VERSION));
+    }
+    if version == CONFIG_VERSION {
+        return Ok(());
+    }
+
+    let backup_path = backup(path)?;
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut doc, config_name);
+    }
+    doc["version"] = value(CONFIG_VERSION as i64);
+    fs::write(path, doc.to_string()).map_err(|err| format!("cant write migrated config {}: {}", path.display(), err))?;
+    println!("{}", format!("config {} upgraded from version {} to {}, the old file is {}", config_name, version, CONFIG_VERSION, backup_path.display()).yellow());
+    Ok(())
+}
+
+/// Copies `path` to `<path>.<timestamp>.bak` next to it.
+pub(crate) fn backup(path: &Path) -> Result<PathBuf, String> {
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let backup_path = PathBuf::from(format!("{}.{}.bak", path.display(), stamp));
+    fs::copy(path, &backup_path).map_err(|err| format!("cant back up config {} to {}: {}", path.display(), backup_path.display(), err))?;
+    Ok(backup_path)
+}
+
+// configs before versioning: all settings besides the keys were added with defaults,
+// so only the fields they always had can be missing or empty
+fn to_v1(doc: &mut Document, config_name: &str) {
+    if doc.get("name").and_then(Item::as_str).is_none_or(|name| name.trim().is_empty()) {
+        doc["name"] = value(config_name);
+    }
+    for key in ["base_url", "sk_bs58", "pk_bs58"] {
+        if doc.get(key).is_none() {
+            doc[key] = value("");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use super::*;
+
+    // a fresh folder for the config files of one test
+    fn folder(test: &str) -> PathBuf {
+        let folder = env::temp_dir().join(format!("s3po-migrate-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    fn backups(folder: &Path) -> Vec<PathBuf> {
+        fs::read_dir(folder).unwrap().flatten().map(|entry| entry.path()).filter(|path| path.extension().is_some_and(|ext| ext == "bak")).collect()
+    }
+
+    #[test]
+    fn upgrades_version_0_configs() {
+        // config before, config after
+        let cases = [
+            ("base_url = \"http://s3\"\nsk_bs58 = \"sk\"\npk_bs58 = \"pk\"\n",
+             "base_url = \"http://s3\"\nsk_bs58 = \"sk\"\npk_bs58 = \"pk\"\nname = \"work\"\nversion = 1\n"),
+            ("# my s3 config\nname = \"\"\nbase_url = \"http://s3\"\n",
+             "# my s3 config\nname = \"work\"\nbase_url = \"http://s3\"\nsk_bs58 = \"\"\npk_bs58 = \"\"\nversion = 1\n"),
+            ("name = \"kept\"\n[retry]\nmax_attempts = 3\n",
+             "name = \"kept\"\nbase_url = \"\"\nsk_bs58 = \"\"\npk_bs58 = \"\"\nversion = 1\n[retry]\nmax_attempts = 3\n"),
+        ];
+        let folder = folder("v0");
+        for (before, after) in cases {
+            let path = folder.join("work.toml");
+            fs::write(&path, before).unwrap();
+            migrate(&path, "work").unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), after);
+            // the file is current now, a second run changes nothing
+            migrate(&path, "work").unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), after);
+        }
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn refuses_configs_from_a_newer_s3po() {
+        let folder = folder("future");
+        let path = folder.join("work.toml");
+        let text = format!("version = {}\nname = \"work\"\nsome_new_setting = true\n", CONFIG_VERSION + 1);
+        fs::write(&path, &text).unwrap();
+        assert!(migrate(&path, "work").unwrap_err().contains("update s3po"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        assert!(backups(&folder).is_empty());
+        fs::write(&path, "version = -1\n").unwrap();
+        assert!(migrate(&path, "work").unwrap_err().contains("invalid version"));
+        fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn backs_up_the_file_before_migrating() {
+        let folder = folder("backup");
+        let path = folder.join("work.toml");
+        let text = "base_url = \"http://s3\"\n";
+        fs::write(&path, text).unwrap();
+        migrate(&path, "work").unwrap();
+        let backed_up = backups(&folder);
+        assert_eq!(backed_up.len(), 1);
+        assert_eq!(fs::read_to_string(&backed_up[0]).unwrap(), text);
+        assert_ne!(fs::read_to_string(&path).unwrap(), text);
+        // current configs are not backed up again
+        migrate(&path, "work").unwrap();
+        assert_eq!(backups(&folder).len(), 1);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
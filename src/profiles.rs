@@ -0,0 +1,318 @@
+use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use colored::Colorize;
+use crate::bundle::key_fingerprint;
+use crate::config::S3Config;
+use crate::console::y_or_n;
+use crate::creds::{home_dir, parse_ini, resolve};
+use crate::crypto::gen_new_keys;
+
+/// An s3 profile read from the config of another tool.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Profile {
+    pub(crate) name: String,
+    pub(crate) base_url: String,
+    pub(crate) region: String,
+    pub(crate) access_key: String,
+    pub(crate) secret_key: String,
+}
+
+/// Reads the profiles of the AWS CLI, rclone or s3cmd and stores each one as an s3po
+/// config named `<format>-<profile>`. Configs get the keypair of `current`, a new one
+/// is generated only when there is none, and updated configs keep their own. The import
+/// says so, since a shared keypair decrypts the files of every config that has it.
+pub(crate) fn import(format: &str, path: Option<String>, current: &S3Config) {
+    let profiles = match format {
+        "aws" => read_aws(path),
+        "rclone" => read_rclone(path),
+        "s3cmd" => read_s3cmd(path),
+        _ => Err(format!("can't import from {}, known formats are aws, rclone and s3cmd", format)),
+    };
+    let profiles = match profiles {
+        Ok(profiles) if profiles.is_empty() => {
+            println!("{}", format!("no s3 profiles found in the {} config", format).yellow());
+            return;
+        }
+        Ok(profiles) => profiles,
+        Err(err) => {
+            println!("{}", err.red());
+            return;
+        }
+    };
+
+    let mut keypair = match current.sk_bs58.trim().is_empty() || current.pk_bs58.trim().is_empty() {
+        true => None,
+        false => Some((current.sk_bs58.clone(), current.pk_bs58.clone())),
+    };
+    match &keypair {
+        Some((_, pk_bs58)) => println!("{}", format!("new configs get the keypair of config {} (key {}), each of them decrypts what the others encrypt",
+                                                     current.name, key_fingerprint(pk_bs58)).yellow()),
+        None => println!("{}", "new configs share one new keypair, each of them decrypts what the others encrypt".yellow()),
+    }
+    for profile in profiles {
+        let name = format!("{}-{}", format, profile.name);
+        let exists = confy::get_configuration_file_path("s3po", Some(name.as_str())).map(|path| path.exists()).unwrap_or(false);
+        let mut cfg = match exists {
+            true => {
+                if !y_or_n(&format!("config {} exists, update its endpoint and keys?", name)) {
+                    continue;
+                }
+                match S3Config::load(name.clone()) {
+                    Ok(cfg) => cfg,
+                    Err(err) => {
+                        println!("cant load config {}, skipped: {}", name, err);
+                        continue;
+                    }
+                }
+            }
+            false => S3Config::default(),
+        };
+        cfg.name = name.clone();
+        cfg.base_url = profile.base_url.clone();
+        cfg.region = profile.region.clone();
+        cfg.access_key = profile.access_key;
+        cfg.secret_key = profile.secret_key;
+        if cfg.sk_bs58.trim().is_empty() || cfg.pk_bs58.trim().is_empty() {
+            match &keypair {
+                Some((sk_bs58, pk_bs58)) => {
+                    cfg.sk_bs58 = sk_bs58.clone();
+                    cfg.pk_bs58 = pk_bs58.clone();
+                }
+                None => {
+                    cfg = gen_new_keys(cfg);
+                    keypair = Some((cfg.sk_bs58.clone(), cfg.pk_bs58.clone()));
+                }
+            }
+        }
+        let pk_bs58 = cfg.pk_bs58.clone();
+        match confy::store("s3po", Some(name.as_str()), cfg) {
+            Ok(_) => println!("config {} imported, endpoint {}, key {}", name.green(), profile_endpoint(&profile.base_url, &profile.region), key_fingerprint(&pk_bs58)),
+            Err(err) => println!("cant store config {}: {}", name, err),
+        }
+    }
+}
+
+/// Writes `cfg` as a profile of the AWS CLI into `credentials` and `config` in `dir`,
+/// replacing a profile of the same name and keeping everything else. Without `dir`
+/// both parts are printed.
+pub(crate) fn export_aws(cfg: &S3Config, dir: Option<String>) {
+    let credentials = match resolve(cfg) {
+        Ok(credentials) => credentials,
+        Err(err) => {
+            println!("{}", err.red());
+            return;
+        }
+    };
+    let mut credentials_body = format!("aws_access_key_id = {}\naws_secret_access_key = {}\n", credentials.access_key, credentials.secret_key);
+    if let Some(session_token) = &credentials.session_token {
+        credentials_body += &format!("aws_session_token = {}\n", session_token);
+    }
+    let mut config_body = String::new();
+    let region = match cfg.region.trim() {
+        "" => url_region(&cfg.base_url),
+        region => region.to_string(),
+    };
+    if !region.is_empty() {
+        config_body += &format!("region = {}\n", region);
+    }
+    if !cfg.base_url.contains(".amazonaws.com") {
+        config_body += &format!("endpoint_url = {}\n", cfg.base_url);
+    }
+    let config_header = match cfg.name.as_str() {
+        "default" => "[default]".to_string(),
+        name => format!("[profile {}]", name),
+    };
+
+    let Some(dir) = dir else {
+        println!("# credentials\n[{}]\n{}", cfg.name, credentials_body);
+        println!("# config\n{}\n{}", config_header, config_body);
+        return;
+    };
+    let dir = match dir.strip_prefix('~') {
+        Some(rest) => format!("{}{}", home_dir(), rest),
+        None => dir,
+    };
+    if let Err(err) = fs::create_dir_all(&dir) {
+        println!("cant create folder {}: {}", dir, err);
+        return;
+    }
+    for (file, header, body) in [("credentials", format!("[{}]", cfg.name), credentials_body), ("config", config_header, config_body)] {
+        let path = Path::new(&dir).join(file);
+        let text = fs::read_to_string(&path).unwrap_or_default();
+        match write_private(&path, &replace_section(&text, &header, &body)) {
+            Ok(_) => println!("profile {} written to {}", cfg.name.green(), path.display()),
+            Err(err) => println!("cant write {}: {}", path.display(), err),
+        }
+    }
+}
+
+// writes a file only its owner can read, the credentials file holds secret keys.
+// the mode of OpenOptions only applies to new files, so an existing one is fixed too
+fn write_private(path: &Path, text: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(text.as_bytes())
+}
+
+// ~/.aws/credentials with the endpoints and regions of ~/.aws/config
+fn read_aws(path: Option<String>) -> Result<Vec<Profile>, String> {
+    let credentials_path = path
+        .or(env::var("AWS_SHARED_CREDENTIALS_FILE").ok())
+        .unwrap_or(format!("{}/.aws/credentials", home_dir()));
+    let config_path = match Path::new(&credentials_path).parent() {
+        Some(dir) if env::var("AWS_CONFIG_FILE").is_err() => dir.join("config").to_string_lossy().to_string(),
+        _ => env::var("AWS_CONFIG_FILE").unwrap_or(format!("{}/.aws/config", home_dir())),
+    };
+    let credentials = parse_ini(&fs::read_to_string(&credentials_path).map_err(|err| format!("cant read {}: {}", credentials_path, err))?);
+    let config = parse_ini(&fs::read_to_string(&config_path).unwrap_or_default());
+
+    let mut profiles = Vec::new();
+    for (name, section) in credentials {
+        let (Some(access_key), Some(secret_key)) = (section.get("aws_access_key_id"), section.get("aws_secret_access_key")) else {
+            continue;
+        };
+        let settings = config.get(&format!("profile {}", name)).or(config.get(&name));
+        let setting = |key: &str| settings.and_then(|settings| settings.get(key)).cloned().unwrap_or_default();
+        let region = setting("region");
+        let base_url = match setting("endpoint_url") {
+            endpoint if endpoint.is_empty() => aws_url(&region),
+            endpoint => endpoint,
+        };
+        profiles.push(Profile { name, base_url, region, access_key: access_key.clone(), secret_key: secret_key.clone() });
+    }
+    Ok(profiles)
+}
+
+// remotes of type s3 in rclone.conf
+fn read_rclone(path: Option<String>) -> Result<Vec<Profile>, String> {
+    let path = path
+        .or(env::var("RCLONE_CONFIG").ok())
+        .unwrap_or(format!("{}/.config/rclone/rclone.conf", home_dir()));
+    let remotes = parse_ini(&fs::read_to_string(&path).map_err(|err| format!("cant read {}: {}", path, err))?);
+
+    let mut profiles = Vec::new();
+    for (name, remote) in remotes {
+        if remote.get("type").map(String::as_str) != Some("s3") {
+            continue;
+        }
+        let setting = |key: &str| remote.get(key).cloned().unwrap_or_default();
+        if setting("access_key_id").is_empty() {
+            println!("{}", format!("rclone remote {} takes its keys from the environment, import skipped it", name).yellow());
+            continue;
+        }
+        let region = setting("region");
+        let base_url = match setting("endpoint") {
+            endpoint if endpoint.is_empty() => aws_url(&region),
+            endpoint if endpoint.contains("://") => endpoint,
+            endpoint => format!("https://{}", endpoint),
+        };
+        profiles.push(Profile { name, base_url, region, access_key: setting("access_key_id"), secret_key: setting("secret_access_key") });
+    }
+    Ok(profiles)
+}
+
+// every section of .s3cfg with keys, usually only [default]
+fn read_s3cmd(path: Option<String>) -> Result<Vec<Profile>, String> {
+    let path = path.unwrap_or(format!("{}/.s3cfg", home_dir()));
+    let sections = parse_ini(&fs::read_to_string(&path).map_err(|err| format!("cant read {}: {}", path, err))?);
+
+    let mut profiles = Vec::new();
+    for (name, section) in sections {
+        let setting = |key: &str| section.get(key).cloned().unwrap_or_default();
+        if setting("access_key").is_empty() {
+            continue;
+        }
+        let scheme = match setting("use_https").to_lowercase().as_str() {
+            "false" | "no" | "0" => "http",
+            _ => "https",
+        };
+        let host = match setting("host_base") {
+            host if host.is_empty() => "s3.amazonaws.com".to_string(),
+            host => host,
+        };
+        let region = match setting("bucket_location") {
+            location if location == "US" => "us-east-1".to_string(),
+            location => location,
+        };
+        let base_url = match host == "s3.amazonaws.com" {
+            true => aws_url(&region),
+            false => format!("{}://{}", scheme, host),
+        };
+        profiles.push(Profile { name, base_url, region, access_key: setting("access_key"), secret_key: setting("secret_key") });
+    }
+    Ok(profiles)
+}
+
+fn aws_url(region: &str) -> String {
+    match region {
+        "" | "us-east-1" => "https://s3.amazonaws.com".to_string(),
+        region => format!("https://s3.{}.amazonaws.com", region),
+    }
+}
+
+// region of an aws url like https://s3.eu-west-1.amazonaws.com
+fn url_region(base_url: &str) -> String {
+    let host = base_url.split("://").last().unwrap_or(base_url).split(['/', ':']).next().unwrap_or("");
+    match host.strip_suffix(".amazonaws.com").and_then(|rest| rest.strip_prefix("s3.")) {
+        Some(region) if !region.is_empty() => region.to_string(),
+        _ if host.ends_with(".amazonaws.com") => "us-east-1".to_string(),
+        _ => String::new(),
+    }
+}
+
+fn profile_endpoint(base_url: &str, region: &str) -> String {
+    match region.is_empty() {
+        true => base_url.to_string(),
+        false => format!("{} ({})", base_url, region),
+    }
+}
+
+// swaps the lines of section `header` in an INI text for `body`, or appends it
+fn replace_section(text: &str, header: &str, body: &str) -> String {
+    let mut out = String::new();
+    let mut skipping = false;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            skipping = trimmed == header;
+        }
+        if !skipping {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    if !out.is_empty() && !out.ends_with("\n\n") {
+        out.push('\n');
+    }
+    out.push_str(header);
+    out.push('\n');
+    out.push_str(body);
+    out
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use super::*;
+
+    #[test]
+    fn credentials_are_only_readable_by_the_owner() {
+        let path = env::temp_dir().join(format!("s3po-credentials-{}", std::process::id()));
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        write_private(&path, "[default]\n").unwrap();
+        assert_eq!(mode(&path), 0o600);
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&path, "[other]\n").unwrap();
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[other]\n");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+use std::io::{stdout, IsTerminal};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use colored::Colorize;
+use indicatif::{HumanBytes, HumanDuration, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+const BAR_TEMPLATE: &str = "  {prefix:>8} {msg:30!} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} eta {eta}";
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Stage {
+    Upload,
+    Download,
+    Copy,
+    Encrypt,
+    Decrypt,
+}
+
+impl Stage {
+    fn name(&self) -> &'static str {
+        match self {
+            Stage::Upload => "upload",
+            Stage::Download => "download",
+            Stage::Copy => "copy",
+            Stage::Encrypt => "encrypt",
+            Stage::Decrypt => "decrypt",
+        }
+    }
+}
+
+// all bars share one MultiProgress so parallel jobs are drawn one per line
+fn multi() -> &'static MultiProgress {
+    static MULTI: OnceLock<MultiProgress> = OnceLock::new();
+    MULTI.get_or_init(|| MultiProgress::with_draw_target(ProgressDrawTarget::stdout()))
+}
+
+pub(crate) fn is_interactive() -> bool {
+    stdout().is_terminal()
+}
+
+/// Progress of a single transfer stage. Draws a bar when stdout is a terminal,
+/// otherwise prints a log line to stderr every few seconds, so piped output stays clean.
+#[derive(Clone)]
+pub(crate) struct Transfer {
+    bar: ProgressBar,
+    stage: Stage,
+    name: String,
+    interactive: bool,
+    last_log: Arc<Mutex<Instant>>,
+}
+
+impl Transfer {
+    pub(crate) fn start(stage: Stage, name: &str, total: u64) -> Transfer {
+        let interactive = is_interactive();
+        let bar = if interactive {
+            let bar = multi().add(ProgressBar::new(total));
+            bar.set_style(ProgressStyle::with_template(BAR_TEMPLATE).unwrap().progress_chars("=> "));
+            bar
+        } else {
+            eprintln!("{} {}: {}", stage.name(), name, HumanBytes(total));
+            ProgressBar::with_draw_target(Some(total), ProgressDrawTarget::hidden())
+        };
+        bar.set_prefix(stage.name());
+        bar.set_message(name.to_string());
+        Transfer { bar, stage, name: name.to_string(), interactive, last_log: Arc::new(Mutex::new(Instant::now())) }
+    }
+
+    pub(crate) fn inc(&self, bytes: u64) {
+        self.bar.inc(bytes);
+        if !self.interactive {
+            let mut last_log = self.last_log.lock().unwrap();
+            if last_log.elapsed() >= LOG_INTERVAL {
+                *last_log = Instant::now();
+                self.log_line();
+            }
+        }
+    }
+
+    /// Starts the bar over, for a transfer that has to begin again.
+    pub(crate) fn restart(&self, total: u64) {
+        self.bar.reset();
+        self.bar.set_length(total);
+        if !self.interactive {
+            eprintln!("{} {}: starting over, {}", self.stage.name(), self.name, HumanBytes(total));
+        }
+    }
+
+    pub(crate) fn finish(&self) {
+        if let Some(total) = self.bar.length() {
+            self.bar.set_position(total);
+        }
+        if self.interactive {
+            self.bar.finish();
+        } else {
+            eprintln!("{} {}: done {} in {}", self.stage.name(), self.name, HumanBytes(self.bar.position()), HumanDuration(self.bar.elapsed()));
+        }
+    }
+
+    pub(crate) fn fail(&self) {
+        if self.interactive {
+            self.bar.abandon_with_message(format!("{} {}", self.name, "failed".red()));
+        } else {
+            eprintln!("{} {}: failed after {}", self.stage.name(), self.name, HumanBytes(self.bar.position()));
+        }
+    }
+
+    fn log_line(&self) {
+        let total = self.bar.length().unwrap_or(0);
+        eprintln!("{} {}: {}/{} ({}/s, eta {})",
+                 self.stage.name(), self.name,
+                 HumanBytes(self.bar.position()), HumanBytes(total),
+                 HumanBytes(self.bar.per_sec() as u64), HumanDuration(self.bar.eta()));
+    }
+}
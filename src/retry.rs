@@ -0,0 +1,194 @@
+use std::future::Future;
+use std::io;
+use std::time::{Duration, Instant};
+use colored::Colorize;
+use minio::s3::error::Error;
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+
+// error codes S3 compatible servers send back for throttling and temporary failures
+const RETRYABLE_CODES: [&str; 7] = [
+    "SlowDown", "Throttling", "ThrottlingException", "RequestTimeout",
+    "InternalError", "ServiceUnavailable", "OperationAborted",
+];
+// of those, the codes that mean the request was turned away before it was processed
+const NOT_APPLIED_CODES: [&str; 3] = ["SlowDown", "Throttling", "ThrottlingException"];
+
+/// How S3 requests are retried, stored as the `[retry]` table of a config.
+/// Timeouts of 0 mean "no timeout".
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub(crate) struct RetryPolicy {
+    pub(crate) max_attempts: u32,
+    pub(crate) base_delay_ms: u64,
+    pub(crate) max_delay_ms: u64,
+    pub(crate) request_timeout_secs: u64,
+    pub(crate) overall_timeout_secs: u64,
+}
+
+impl ::std::default::Default for RetryPolicy {
+    fn default() -> Self { Self { max_attempts: 5, base_delay_ms: 200, max_delay_ms: 20_000, request_timeout_secs: 120, overall_timeout_secs: 900 } }
+}
+
+impl RetryPolicy {
+    pub(crate) fn request_timeout(&self) -> Option<Duration> {
+        match self.request_timeout_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    fn overall_timeout(&self) -> Option<Duration> {
+        match self.overall_timeout_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    // "full jitter": a random delay between zero and the exponential backoff cap
+    fn backoff(&self, attempt: u32) -> Duration {
+        let cap = self.base_delay_ms.saturating_mul(1u64 << attempt.min(20)).min(self.max_delay_ms);
+        Duration::from_millis(rand::thread_rng().gen_range(0..=cap))
+    }
+}
+
+/// Runs `op` until it succeeds, fails with a permanent error or the policy gives up.
+/// Operations that are not `idempotent` are only retried when the server surely did
+/// not apply them (it was unreachable or throttled the request), so a retry can't
+/// apply them twice.
+pub(crate) async fn retry<T, F, Fut>(policy: &RetryPolicy, what: &str, idempotent: bool, mut op: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let started = Instant::now();
+    let mut attempt: u32 = 1;
+    loop {
+        let err = match with_timeout(policy.request_timeout(), op()).await {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        let retryable = match idempotent {
+            true => is_transient(&err),
+            false => is_not_applied(&err),
+        };
+        if !retryable || attempt >= policy.max_attempts.max(1) {
+            return Err(err);
+        }
+        let delay = policy.backoff(attempt);
+        if let Some(overall) = policy.overall_timeout() {
+            if started.elapsed() + delay >= overall {
+                return Err(timed_out(format!("{} gave up after {} attempts", what, attempt)));
+            }
+        }
+        println!("{} {} ({}), retrying in {:.1}s, attempt {}/{}", what.yellow(), "failed".yellow(), err, delay.as_secs_f64(), attempt + 1, policy.max_attempts);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Awaits `fut`, failing with a timeout error after `timeout`.
+pub(crate) async fn with_timeout<T, Fut>(timeout: Option<Duration>, fut: Fut) -> Result<T, Error>
+where
+    Fut: Future<Output = Result<T, Error>>,
+{
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, fut).await {
+            Ok(res) => res,
+            Err(_) => Err(timed_out(format!("request timed out after {}s", timeout.as_secs()))),
+        },
+        None => fut.await,
+    }
+}
+
+fn timed_out(msg: String) -> Error {
+    Error::IOError(io::Error::new(io::ErrorKind::TimedOut, msg))
+}
+
+/// Whether `err` is worth another attempt: throttling, 5xx, timeouts and dropped connections.
+pub(crate) fn is_transient(err: &Error) -> bool {
+    match err {
+        Error::S3Error(resp) => RETRYABLE_CODES.contains(&resp.code.as_str()),
+        Error::ServerError(status) | Error::InvalidResponse(status, _) => *status == 429 || *status >= 500,
+        Error::HttpError(err) => err.is_timeout() || err.is_connect() || err.is_request() || err.is_body(),
+        Error::IOError(err) => matches!(err.kind(),
+            io::ErrorKind::TimedOut | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted |
+            io::ErrorKind::ConnectionRefused | io::ErrorKind::BrokenPipe | io::ErrorKind::UnexpectedEof |
+            io::ErrorKind::Interrupted),
+        _ => false,
+    }
+}
+
+// the request never reached the server, or the server throttled it before doing anything.
+// an InternalError, a 5xx or a timeout can come after the request was applied
+fn is_not_applied(err: &Error) -> bool {
+    match err {
+        Error::S3Error(resp) => NOT_APPLIED_CODES.contains(&resp.code.as_str()),
+        Error::ServerError(status) | Error::InvalidResponse(status, _) => *status == 429,
+        Error::HttpError(err) => err.is_connect(),
+        Error::IOError(err) => err.kind() == io::ErrorKind::ConnectionRefused,
+        _ => false,
+    }
+}
+
+/// Whether a conditional request failed because the object is not the one it was made for.
+pub(crate) fn is_precondition_failed(err: &Error) -> bool {
+    match err {
+        Error::S3Error(resp) => resp.code == "PreconditionFailed",
+        Error::ServerError(status) | Error::InvalidResponse(status, _) => *status == 412,
+        _ => false,
+    }
+}
+
+/// Whether a read was refused for its SSE-C headers: the object needs a key that was not
+/// sent, or a key was sent for an object without SSE-C. S3 answers both with a 400.
+pub(crate) fn is_sse_mismatch(err: &Error) -> bool {
+    match err {
+        Error::S3Error(resp) => ["BadRequest", "InvalidRequest", "InvalidArgument"].contains(&resp.code.as_str()),
+        Error::ServerError(status) | Error::InvalidResponse(status, _) => *status == 400,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use minio::s3::error::ErrorResponse;
+    use super::*;
+
+    fn s3_error(code: &str) -> Error {
+        Error::S3Error(ErrorResponse { code: code.to_string(), ..ErrorResponse::default() })
+    }
+
+    #[test]
+    fn only_throttling_and_unreachable_servers_are_not_applied() {
+        for code in ["SlowDown", "Throttling", "ThrottlingException"] {
+            assert!(is_not_applied(&s3_error(code)), "{}", code);
+        }
+        for code in ["InternalError", "ServiceUnavailable", "RequestTimeout", "OperationAborted", "NoSuchKey"] {
+            assert!(!is_not_applied(&s3_error(code)), "{}", code);
+        }
+        assert!(is_not_applied(&Error::ServerError(429)));
+        assert!(!is_not_applied(&Error::ServerError(500)));
+        assert!(!is_not_applied(&Error::InvalidResponse(503, String::new())));
+        assert!(is_not_applied(&Error::IOError(io::Error::new(io::ErrorKind::ConnectionRefused, "refused"))));
+        assert!(!is_not_applied(&timed_out("slow".to_string())));
+        assert!(is_transient(&s3_error("InternalError")));
+        assert!(is_transient(&timed_out("slow".to_string())));
+    }
+
+    #[test]
+    fn detects_failed_preconditions() {
+        assert!(is_precondition_failed(&s3_error("PreconditionFailed")));
+        assert!(is_precondition_failed(&Error::ServerError(412)));
+        assert!(!is_precondition_failed(&s3_error("NoSuchKey")));
+    }
+
+    #[test]
+    fn detects_sse_mismatches() {
+        assert!(is_sse_mismatch(&s3_error("BadRequest")));
+        assert!(is_sse_mismatch(&s3_error("InvalidRequest")));
+        assert!(is_sse_mismatch(&Error::InvalidResponse(400, String::new())));
+        assert!(!is_sse_mismatch(&s3_error("AccessDenied")));
+        assert!(!is_sse_mismatch(&Error::ServerError(500)));
+    }
+}
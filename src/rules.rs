@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+use colored::Colorize;
+use serde_derive::{Deserialize, Serialize};
+use crate::config::S3Config;
+use crate::crypto::parse_public_key;
+use crate::envelope::COMPRESSIONS;
+use crate::find::glob;
+use crate::s3::PutOptions;
+use crate::sse::Sse;
+use crate::units::parse_duration;
+
+/// Upload defaults for a bucket or a prefix, stored as `[[rules]]` tables of a config.
+/// Every matching rule applies in order, a later rule wins for the settings it sets.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub(crate) struct Rule {
+    /// glob of bucket names, like "prod-*"
+    pub(crate) bucket: String,
+    /// glob of the start of object names, empty for the whole bucket
+    pub(crate) prefix: String,
+    /// "required" refuses plain uploads, "forbidden" refuses encrypted ones
+    pub(crate) encryption: String,
+    /// base58 public keys that can decrypt too, the config key always can
+    pub(crate) recipients: Vec<String>,
+    /// none or deflate, for encrypted uploads
+    pub(crate) compression: String,
+    pub(crate) storage_class: String,
+    pub(crate) tags: BTreeMap<String, String>,
+    /// server side encryption of uploads like `sse` of the config, reads don't depend on it
+    pub(crate) sse: String,
+    /// object lock retention counted from the upload, like "90d"
+    pub(crate) retention: String,
+    /// governance or compliance, governance when empty
+    pub(crate) retention_mode: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum Encryption {
+    #[default]
+    Any,
+    Required,
+    Forbidden,
+}
+
+/// What the rules of a config say about one object.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Policy {
+    pub(crate) encryption: Encryption,
+    pub(crate) recipients: Vec<String>,
+    pub(crate) compression: Option<String>,
+    pub(crate) storage_class: Option<String>,
+    pub(crate) tags: BTreeMap<String, String>,
+    pub(crate) sse: Option<String>,
+    /// mode and seconds
+    pub(crate) retention: Option<(String, u64)>,
+}
+
+impl Rule {
+    fn matches(&self, bucket_name: &str, object_name: &str) -> bool {
+        glob(self.bucket.trim(), bucket_name) && (self.prefix.is_empty() || glob(&format!("{}*", self.prefix), object_name))
+    }
+
+    fn validate(&self, config: &S3Config) -> Result<(), String> {
+        let place = self.place();
+        if self.bucket.trim().is_empty() {
+            return Err("a rule has no bucket, use bucket = \"*\" for all buckets".to_string());
+        }
+        parse_encryption(&self.encryption).map_err(|err| format!("rule {}: {}", place, err))?;
+        for recipient in &self.recipients {
+            parse_public_key(recipient).map_err(|err| format!("rule {}: recipient {}", place, err))?;
+        }
+        if !self.compression.is_empty() && !COMPRESSIONS.contains(&self.compression.as_str()) {
+            return Err(format!("rule {}: unknown compression \"{}\", expected {}", place, self.compression, COMPRESSIONS.join(" or ")));
+        }
+        if !self.sse.is_empty() {
+            Sse::parse(&self.sse, config).map_err(|err| format!("rule {}: {}", place, err))?;
+        }
+        if !self.retention.is_empty() && parse_duration(&self.retention).is_none_or(|secs| secs == 0) {
+            return Err(format!("rule {}: can't parse retention \"{}\", expected a duration like 90d", place, self.retention));
+        }
+        if !["", "governance", "compliance"].contains(&self.retention_mode.to_lowercase().as_str()) {
+            return Err(format!("rule {}: retention_mode is governance or compliance, got {}", place, self.retention_mode));
+        }
+        Ok(())
+    }
+
+    fn place(&self) -> String {
+        format!("{}/{}", self.bucket.trim(), self.prefix)
+    }
+
+    /// One line for `config print`.
+    pub(crate) fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        match parse_encryption(&self.encryption) {
+            Ok(Encryption::Required) => parts.push("encrypted only".to_string()),
+            Ok(Encryption::Forbidden) => parts.push("plain only".to_string()),
+            _ => {}
+        }
+        if !self.recipients.is_empty() {
+            parts.push(format!("{} more recipients", self.recipients.len()));
+        }
+        if !self.compression.is_empty() {
+            parts.push(format!("compression {}", self.compression));
+        }
+        if !self.storage_class.is_empty() {
+            parts.push(format!("storage class {}", self.storage_class.to_uppercase()));
+        }
+        if !self.tags.is_empty() {
+            let tags: Vec<String> = self.tags.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+            parts.push(format!("tags {}", tags.join(",")));
+        }
+        if !self.sse.is_empty() {
+            parts.push(format!("sse {}", self.sse));
+        }
+        if !self.retention.is_empty() {
+            parts.push(format!("retention {} {}", self.retention, if self.retention_mode.is_empty() { "governance" } else { &self.retention_mode }));
+        }
+        if parts.is_empty() {
+            parts.push("no settings".to_string());
+        }
+        format!("{}: {}", self.place(), parts.join(", "))
+    }
+}
+
+impl Policy {
+    /// Refuses an upload that breaks `encryption`, unless it is forced.
+    pub(crate) fn check_upload(&self, encrypted: bool, force: bool) -> Result<(), String> {
+        let (refusal, hint) = match (self.encryption, encrypted) {
+            (Encryption::Required, false) => ("takes only encrypted uploads", "put"),
+            (Encryption::Forbidden, true) => ("takes only plain uploads", "upload"),
+            _ => return Ok(()),
+        };
+        match force {
+            true => {
+                println!("{}", format!("a rule of the config {}, uploading anyway", refusal).yellow());
+                Ok(())
+            }
+            false => Err(format!("a rule of the config {}, use {} or --force", refusal, hint)),
+        }
+    }
+
+    /// Fills the put options the command line left out.
+    pub(crate) fn apply(&self, options: &mut PutOptions) {
+        if options.storage_class.is_none() {
+            options.storage_class = self.storage_class.clone();
+        }
+        for (key, value) in &self.tags {
+            if options.tags.iter().all(|(given, _)| given != key) {
+                options.tags.push((key.clone(), value.clone()));
+            }
+        }
+        if options.retention.is_none() {
+            if let Some((mode, secs)) = &self.retention {
+                options.retention = Some((mode.clone(), chrono::Utc::now() + chrono::Duration::seconds(*secs as i64)));
+            }
+        }
+        if options.compression.is_none() {
+            options.compression = self.compression.clone();
+        }
+        options.recipients = self.recipients.clone();
+    }
+}
+
+/// Merges the rules of the config that match `object_name` in `bucket_name`. A broken
+/// matching rule is an error, skipping it could let a plain upload through.
+pub(crate) fn policy(config: &S3Config, bucket_name: &str, object_name: &str) -> Result<Policy, String> {
+    let mut policy = Policy::default();
+    for rule in config.rules.iter().filter(|rule| rule.matches(bucket_name, object_name)) {
+        rule.validate(config)?;
+        let encryption = parse_encryption(&rule.encryption)?;
+        if encryption != Encryption::Any {
+            policy.encryption = encryption;
+        }
+        if !rule.recipients.is_empty() {
+            policy.recipients = rule.recipients.clone();
+        }
+        if !rule.compression.is_empty() {
+            policy.compression = Some(rule.compression.clone());
+        }
+        if !rule.storage_class.is_empty() {
+            policy.storage_class = Some(rule.storage_class.clone());
+        }
+        policy.tags.extend(rule.tags.clone());
+        if !rule.sse.is_empty() {
+            policy.sse = Some(rule.sse.clone());
+        }
+        if !rule.retention.is_empty() {
+            let mode = if rule.retention_mode.is_empty() { "governance".to_string() } else { rule.retention_mode.to_lowercase() };
+            policy.retention = Some((mode, parse_duration(&rule.retention).unwrap_or_default()));
+        }
+    }
+    Ok(policy)
+}
+
+/// Prints a warning for every rule of the config that can't be used.
+pub(crate) fn check_rules(config: &S3Config) {
+    for rule in &config.rules {
+        if let Err(err) = rule.validate(config) {
+            println!("{}", err.yellow());
+        }
+    }
+}
+
+fn parse_encryption(encryption: &str) -> Result<Encryption, String> {
+    match encryption.trim().to_lowercase().as_str() {
+        "" | "any" => Ok(Encryption::Any),
+        "required" => Ok(Encryption::Required),
+        "forbidden" => Ok(Encryption::Forbidden),
+        other => Err(format!("encryption is required, forbidden or any, got {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::new_keys;
+    use super::*;
+
+    // a config with the rules of `rules_toml`, keyed so sse c can be used
+    fn config(rules_toml: &str) -> S3Config {
+        let text = format!("name = \"test\"\nbase_url = \"http://s3\"\nsk_bs58 = \"{}\"\npk_bs58 = \"\"\n{}",
+                           bs58::encode([7u8; 32]).into_string(), rules_toml);
+        toml::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn parses_rules_of_a_config() {
+        let config = config(r#"
+            [[rules]]
+            bucket = "prod-*"
+            prefix = "db/"
+            encryption = "required"
+            compression = "deflate"
+            tags = { team = "ops" }
+            retention = "90d"
+            retention_mode = "compliance"
+
+            [[rules]]
+            bucket = "public-assets"
+            encryption = "forbidden"
+        "#);
+        assert_eq!(config.rules.len(), 2);
+        let rule = &config.rules[0];
+        assert_eq!((rule.bucket.as_str(), rule.prefix.as_str(), rule.compression.as_str()), ("prod-*", "db/", "deflate"));
+        assert_eq!(rule.tags.get("team").map(String::as_str), Some("ops"));
+        assert!(config.rules[1].prefix.is_empty() && config.rules[1].recipients.is_empty());
+        assert_eq!(rule.describe(), "prod-*/db/: encrypted only, compression deflate, tags team=ops, retention 90d compliance");
+        for rule in &config.rules {
+            assert_eq!(rule.validate(&config), Ok(()));
+        }
+    }
+
+    #[test]
+    fn merges_the_matching_rules_in_order() {
+        let config = config(r#"
+            [[rules]]
+            bucket = "*"
+            storage_class = "STANDARD_IA"
+            tags = { team = "ops", env = "any" }
+
+            [[rules]]
+            bucket = "prod-*"
+            prefix = "db/"
+            encryption = "required"
+            storage_class = "GLACIER"
+            tags = { env = "prod" }
+            sse = "c"
+            retention = "1d"
+        "#);
+        let merged = policy(&config, "prod-eu", "db/dump.sql").unwrap();
+        assert_eq!(merged.encryption, Encryption::Required);
+        assert_eq!(merged.storage_class.as_deref(), Some("GLACIER"));
+        assert_eq!(merged.tags.get("team").map(String::as_str), Some("ops"));
+        assert_eq!(merged.tags.get("env").map(String::as_str), Some("prod"));
+        assert_eq!(merged.sse.as_deref(), Some("c"));
+        assert_eq!(merged.retention, Some(("governance".to_string(), 86400)));
+
+        let other = policy(&config, "prod-eu", "logs/today").unwrap();
+        assert_eq!(other.encryption, Encryption::Any);
+        assert_eq!(other.storage_class.as_deref(), Some("STANDARD_IA"));
+        assert!(other.sse.is_none() && other.retention.is_none());
+    }
+
+    #[test]
+    fn refuses_broken_rules() {
+        let (_, pk_bs58) = new_keys();
+        let base = config("");
+        let broken = [
+            (Rule { bucket: " ".into(), ..Rule::default() }, "has no bucket"),
+            (Rule { bucket: "b".into(), encryption: "maybe".into(), ..Rule::default() }, "encryption is required, forbidden or any"),
+            (Rule { bucket: "b".into(), recipients: vec![pk_bs58.clone(), "not-a-key".into()], ..Rule::default() }, "recipient"),
+            (Rule { bucket: "b".into(), compression: "zstd".into(), ..Rule::default() }, "unknown compression"),
+            (Rule { bucket: "b".into(), sse: "rot13".into(), ..Rule::default() }, "sse"),
+            (Rule { bucket: "b".into(), retention: "0d".into(), ..Rule::default() }, "can't parse retention"),
+            (Rule { bucket: "b".into(), retention: "soon".into(), ..Rule::default() }, "can't parse retention"),
+            (Rule { bucket: "b".into(), retention_mode: "forever".into(), ..Rule::default() }, "governance or compliance"),
+        ];
+        for (rule, error) in broken {
+            let err = rule.validate(&base).unwrap_err();
+            assert!(err.contains(error), "{} should contain {}", err, error);
+            // a broken rule that matches stops the upload, one that doesn't is left alone
+            let config = S3Config { rules: vec![rule], ..base.clone() };
+            if !config.rules[0].bucket.trim().is_empty() {
+                assert!(policy(&config, "b", "file").is_err());
+            }
+            assert!(policy(&config, "other", "file").is_ok());
+        }
+        let fine = Rule { bucket: "b".into(), recipients: vec![pk_bs58], encryption: "Any".into(), retention_mode: "Compliance".into(), ..Rule::default() };
+        assert_eq!(fine.validate(&base), Ok(()));
+    }
+
+    #[test]
+    fn checks_uploads_against_the_encryption() {
+        let required = Policy { encryption: Encryption::Required, ..Policy::default() };
+        assert!(required.check_upload(true, false).is_ok());
+        assert!(required.check_upload(false, false).unwrap_err().contains("only encrypted"));
+        assert!(required.check_upload(false, true).is_ok());
+        let forbidden = Policy { encryption: Encryption::Forbidden, ..Policy::default() };
+        assert!(forbidden.check_upload(true, false).unwrap_err().contains("only plain"));
+        assert!(forbidden.check_upload(false, false).is_ok());
+        assert!(Policy::default().check_upload(true, false).is_ok());
+    }
+}
@@ -1,15 +1,102 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::fs::File;
-use std::io::{Cursor, Read};
+use std::io::{Cursor, Read, Seek, Write};
+use std::path::{Component, Path};
 use colored::Colorize;
-use minio::s3::args::{BucketExistsArgs, DownloadObjectArgs, GetObjectArgs, ListBucketsArgs, ListObjectsV2Args, MakeBucketArgs, PutObjectApiArgs, PutObjectArgs, RemoveBucketArgs, RemoveObjectArgs, UploadObjectArgs};
+use minio::s3::args::{AbortMultipartUploadArgs, BucketExistsArgs, CompleteMultipartUploadArgs, CopyObjectArgs, CopySource, CreateMultipartUploadArgs, GetObjectArgs, GetBucketVersioningArgs, GetPresignedObjectUrlArgs, ListBucketsArgs, ListObjectVersionsArgs, ListObjectsV2Args, MakeBucketArgs, StatObjectArgs, PutObjectApiArgs, RemoveBucketArgs, RemoveObjectArgs, RemoveObjectsApiArgs, SetBucketVersioningArgs, SetObjectTagsArgs, GetObjectTagsArgs, DeleteObjectTagsArgs, EnableObjectLegalHoldArgs, DisableObjectLegalHoldArgs, UploadObjectArgs, UploadPartArgs, UploadPartCopyArgs};
 use minio::s3::client::Client;
 use minio::s3::creds::StaticProvider;
+use minio::s3::error::Error;
 use minio::s3::http::BaseUrl;
-use minio::s3::response::PutObjectApiResponse;
-use minio::s3::types::Bucket;
+use minio::s3::response::{PutObjectApiResponse, StatObjectResponse};
+use minio::s3::types::{DeleteObject, Item, Part, RetentionMode};
+use minio::s3::utils::{md5sum_hash, merge, to_iso8601utc, urlencode, Multimap, UtcTime};
+use indicatif::HumanBytes;
+use reqwest::Method;
+use crate::bundle::{new_passphrase, passphrase, record_key_backup, Bundle, CONFIG_BACKUP_PREFIX};
+use crate::bucket::{load, load_policy, render, Cors, Lifecycle};
 use crate::config::S3Config;
-use crate::crypto::{decrypt_bytes, encrypt_bytes};
+use crate::creds::resolve;
+use crate::endpoint::{base_url, describe_endpoint, describe_error, lookup, probe, validate};
+use crate::console::y_or_n;
+use crate::envelope::{fingerprint, header_len, open_metadata, open_sha256, parse_header, Header, LEGACY_OVERHEAD, MAX_HEADER_LEN, PREFIX_LEN};
+use crate::crypto::{check_keypair, decrypt_bytes, decrypt_with_key, encrypt_bytes, encrypt_with_key, random_key};
+use crate::progress::{Stage, Transfer};
+use crate::rules::{policy, Policy};
+use crate::sse::Sse;
+use crate::usage::Usage;
+use crate::find::{Action, Filter};
+use crate::diff::{file_digests, local_files, Change, SHA256_META};
+use crate::retry::{is_precondition_failed, is_sse_mismatch, is_transient, retry, with_timeout, RetryPolicy};
+use crate::throttle::{consume, current_rate};
+
+// objects bigger than this are sent as multipart uploads, one part at a time
+const PART_SIZE: usize = 16 * 1024 * 1024;
+// most keys a single DeleteObjects request may carry
+const DELETE_BATCH: usize = 1000;
+// longest validity SigV4 allows for a presigned url
+pub(crate) const MAX_PRESIGN_SECS: u64 = 7 * 24 * 60 * 60;
+// smallest part S3 accepts, used under a bandwidth limit to keep bursts short
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+// SigV4 requests signed further off the server clock are refused
+const MAX_CLOCK_SKEW_SECS: i64 = 15 * 60;
+// largest source a single CopyObject accepts
+const MAX_COPY_SIZE: usize = 5 * 1024 * 1024 * 1024;
+// range of each UploadPartCopy, 5 TiB sources stay under the 10000 parts limit
+const COPY_PART_SIZE: usize = 1024 * 1024 * 1024;
+
+/// Content type, storage class, metadata and tags sent with an upload. With
+/// `seal_metadata` encrypted uploads keep the metadata inside the envelope instead.
+/// `sse` replaces the server side encryption of the config for this upload.
+/// `recipients` and `compression` shape encrypted uploads, `force` lets an upload
+/// through that a rule of the config refuses.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PutOptions {
+    pub(crate) metadata: Vec<(String, String)>,
+    pub(crate) tags: Vec<(String, String)>,
+    pub(crate) content_type: Option<String>,
+    pub(crate) storage_class: Option<String>,
+    pub(crate) seal_metadata: bool,
+    pub(crate) retention: Option<(String, UtcTime)>,
+    pub(crate) sse: Option<Sse>,
+    pub(crate) recipients: Vec<String>,
+    pub(crate) compression: Option<String>,
+    pub(crate) force: bool,
+}
+
+impl PutOptions {
+    fn headers(&self) -> Multimap {
+        let mut headers = Multimap::new();
+        if let Some(content_type) = &self.content_type {
+            headers.insert("Content-Type".to_string(), content_type.clone());
+        }
+        if let Some(storage_class) = &self.storage_class {
+            headers.insert("x-amz-storage-class".to_string(), storage_class.to_uppercase());
+        }
+        if !self.seal_metadata {
+            for (key, value) in &self.metadata {
+                headers.insert(format!("x-amz-meta-{}", key), value.clone());
+            }
+        }
+        if let Some((mode, retain_until)) = &self.retention {
+            headers.insert("x-amz-object-lock-mode".to_string(), mode.to_uppercase());
+            headers.insert("x-amz-object-lock-retain-until-date".to_string(), to_iso8601utc(*retain_until));
+        }
+        if !self.tags.is_empty() {
+            let tagging: Vec<String> = self.tags.iter().map(|(key, value)| format!("{}={}", urlencode(key), urlencode(value))).collect();
+            headers.insert("x-amz-tagging".to_string(), tagging.join("&"));
+        }
+        headers
+    }
+
+    fn sealed_metadata(&self) -> BTreeMap<String, String> {
+        match self.seal_metadata {
+            true => self.metadata.iter().cloned().collect(),
+            false => BTreeMap::new(),
+        }
+    }
+}
 
 pub(crate) struct S3Client {
     pub(crate) config: S3Config,
@@ -17,24 +104,97 @@ pub(crate) struct S3Client {
 }
 
 impl S3Client {
-    async fn list_buckets(&self) {
-        let base_url: BaseUrl = self.config.base_url.parse::<BaseUrl>().expect("error parsing base url...");
+    fn client(&self) -> Result<Client, String> {
+        let base_url: BaseUrl = base_url(&self.config)?;
+        let ca_bundle = self.config.ca_bundle.trim();
 
+        let credentials = resolve(&self.config)?;
         let static_provider = StaticProvider::new(
-            &*self.config.access_key,
-            &*self.config.secret_key,
-            None,
+            &credentials.access_key,
+            &credentials.secret_key,
+            credentials.session_token.as_deref(),
         );
 
-        let client = Client::new(
-            base_url.clone(),
+        Client::new(
+            base_url,
             Some(Box::new(static_provider)),
-            None,
-            None,
+            if ca_bundle.is_empty() { None } else { Some(Path::new(ca_bundle)) },
+            Some(self.config.insecure_skip_verify),
         )
-            .unwrap();
+            .map_err(|err| format!("cant create s3 client: {}", err))
+    }
+
+    // the client, None after printing why there is none
+    fn connect(&self) -> Option<Client> {
+        self.client().map_err(|err| println!("{}", err.red())).ok()
+    }
+
+    // server side encryption of the config, or of --sse for the running command,
+    // unless a rule of the config sets one for the object. Uploads use it, reads use read_sse
+    fn sse(&self, bucket_name: &str, object_name: &str) -> Result<Sse, String> {
+        let sse = match policy(&self.config, bucket_name, object_name) {
+            Ok(Policy { sse: Some(sse), .. }) => sse,
+            _ => self.config.sse.clone(),
+        };
+        Sse::parse(&sse, &self.config)
+    }
+
+    // HeadObject with the SSE-C key the object needs. Which one that is does not depend on
+    // the rules or `sse` of the upload: the key comes from the config secret key alone, so
+    // when the server asks for a key or refuses one, the read is tried the other way
+    async fn stat_with_sse(&self, client: &Client, bucket_name: &str, object_name: &str, version_id: Option<&str>) -> Result<(StatObjectResponse, Sse), Error> {
+        let mut sse = self.sse(bucket_name, object_name).map_err(config_error)?;
+        let mut other = match sse {
+            Sse::C(_) => Some(Sse::Off),
+            _ => Sse::parse("c", &self.config).ok(),
+        };
+        loop {
+            let sse_headers = sse.read_headers();
+            let mut args = StatObjectArgs::new(bucket_name, object_name)?;
+            args.version_id = version_id;
+            args.extra_headers = Some(&sse_headers);
+            match retry(&self.config.retry, "stat object", true, || client.stat_object(&args)).await {
+                Ok(resp) => return Ok((resp, sse)),
+                Err(err) if is_sse_mismatch(&err) => match other.take() {
+                    Some(next) => sse = next,
+                    None => return Err(err),
+                },
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // server side encryption to read an object with, see stat_with_sse
+    async fn read_sse(&self, client: &Client, bucket_name: &str, object_name: &str, version_id: Option<&str>) -> Result<Sse, Error> {
+        self.stat_with_sse(client, bucket_name, object_name, version_id).await.map(|(_, sse)| sse)
+    }
+
+    // the options with the rules of the config for the object filled in, None when
+    // a rule refuses the upload
+    fn rule_options(&self, bucket_name: &str, object_name: &str, encrypted: bool, options: &PutOptions) -> Option<PutOptions> {
+        match policy(&self.config, bucket_name, object_name).and_then(|policy| policy.check_upload(encrypted, options.force).map(|_| policy)) {
+            Ok(policy) => {
+                let mut options = options.clone();
+                policy.apply(&mut options);
+                Some(options)
+            }
+            Err(err) => {
+                println!("{}", err.red());
+                None
+            }
+        }
+    }
+
+    async fn exists(&self, client: &Client, bucket_name: &str) -> Result<bool, Error> {
+        let args = BucketExistsArgs::new(bucket_name)?;
+        retry(&self.config.retry, "bucket exists", true, || client.bucket_exists(&args)).await
+    }
+
+    async fn list_buckets(&self) {
+        let Some(client) = self.connect() else { return };
 
-        let buckets = client.list_buckets(&ListBucketsArgs::new()).await;
+        let args = ListBucketsArgs::new();
+        let buckets = retry(&self.config.retry, "list buckets", true, || client.list_buckets(&args)).await;
         match buckets {
             Ok(buckets) => {
                 for bucket in buckets.buckets {
@@ -61,26 +221,12 @@ impl S3Client {
     }
 
     async fn list_objects(&self, bucket_name: String) {
-        let base_url: BaseUrl = self.config.base_url.parse::<BaseUrl>().expect("error parsing base url...");
-
-        let static_provider = StaticProvider::new(
-            &*self.config.access_key,
-            &*self.config.secret_key,
-            None,
-        );
+        let Some(client) = self.connect() else { return };
 
-        let client = Client::new(
-            base_url.clone(),
-            Some(Box::new(static_provider)),
-            None,
-            None,
-        )
-            .unwrap();
-
-        let objects = client.list_objects_v2(&ListObjectsV2Args::new(&*bucket_name.clone()).unwrap()).await;
+        let objects = self.list_all(&client, &bucket_name, "").await;
         match objects {
             Ok(objects) => {
-                for object in objects.contents {
+                for object in objects {
                     println!("  {} {} {}", object.name, object.size.unwrap(), object.last_modified.unwrap())
                 }
             }
@@ -89,6 +235,91 @@ impl S3Client {
             }
         }
     }
+    /// Lists every version and delete marker under `prefix`, newest first per key.
+    pub(crate) async fn ls_versions(&self, bucket_name: String, prefix: String) {
+        let Some(client) = self.connect() else { return };
+
+        match self.list_versions(&client, &bucket_name, &prefix).await {
+            Ok(versions) => {
+                for version in versions {
+                    let latest = if version.is_latest { " (latest)" } else { "" };
+                    let version_id = version.version_id.unwrap_or("null".to_string());
+                    match version.is_delete_marker {
+                        true => println!("  {} {} {} {}{}", version.name, version_id, "delete marker".yellow(), version.last_modified.unwrap(), latest),
+                        false => println!("  {} {} {} {}{}", version.name, version_id, version.size.unwrap_or(0), version.last_modified.unwrap(), latest),
+                    }
+                }
+            }
+            Err(err) => {
+                println!("{}", err)
+            }
+        }
+    }
+
+    /// Turns versioning of a bucket on or off, or prints its state.
+    pub(crate) async fn versioning(&self, bucket_name: String, action: String) {
+        let Some(client) = self.connect() else { return };
+
+        let status = match action.as_str() {
+            "on" => true,
+            "off" => false,
+            _ => {
+                let args = GetBucketVersioningArgs::new(&bucket_name).unwrap();
+                match retry(&self.config.retry, "get bucket versioning", true, || client.get_bucket_versioning(&args)).await {
+                    Ok(resp) => {
+                        let status = match resp.status {
+                            Some(true) => "enabled",
+                            Some(false) => "suspended",
+                            None => "never enabled",
+                        };
+                        println!("versioning of bucket {}: {}", bucket_name, status);
+                    }
+                    Err(err) => println!("cant get versioning of bucket {}: {}", bucket_name, err),
+                }
+                return;
+            }
+        };
+        let args = SetBucketVersioningArgs::new(&bucket_name, status).unwrap();
+        match retry(&self.config.retry, "set bucket versioning", true, || client.set_bucket_versioning(&args)).await {
+            Ok(_) => println!("versioning of bucket {} {}", bucket_name, if status { "enabled" } else { "suspended" }),
+            Err(err) => println!("cant set versioning of bucket {}: {}", bucket_name, err),
+        }
+    }
+
+    /// Makes the newest version of `object_name` that existed at `as_of` current again
+    /// by copying it over the latest one. Older versions stay as they are.
+    pub(crate) async fn restore(&self, bucket_name: String, object_name: String, as_of: UtcTime) {
+        let Some(client) = self.connect() else { return };
+
+        let versions = match self.list_versions(&client, &bucket_name, &object_name).await {
+            Ok(versions) => versions,
+            Err(err) => {
+                println!("cant list versions of {}/{}: {}", bucket_name, object_name, err);
+                return;
+            }
+        };
+        let versions: Vec<Item> = versions.into_iter().filter(|version| version.name == object_name).collect();
+        let Some(at_time) = versions.iter()
+            .filter(|version| version.last_modified.is_some_and(|modified| modified <= as_of))
+            .max_by_key(|version| version.last_modified) else {
+            println!("{}/{} has no version from {} or before", bucket_name, object_name, as_of);
+            return;
+        };
+        if at_time.is_delete_marker {
+            println!("{}/{} was deleted at {}, nothing to restore", bucket_name, object_name, as_of);
+            return;
+        }
+        let version_id = at_time.version_id.clone().unwrap_or("null".to_string());
+        if at_time.is_latest {
+            println!("{}/{} version {} is already current", bucket_name, object_name, version_id);
+            return;
+        }
+        match self.copy_object(&client, (&bucket_name, &object_name, Some(&version_id)), (&bucket_name, &object_name)).await {
+            Ok(_) => println!("{}/{} restored from version {} of {}", bucket_name, object_name, version_id, at_time.last_modified.unwrap()),
+            Err(err) => println!("cant restore {}/{} from version {}: {}", bucket_name, object_name, version_id, err),
+        }
+    }
+
     pub(crate) async fn ls(&self, bucket_name: String) {
         if bucket_name.trim() == "" || bucket_name.trim() == "/" {
             self.list_buckets().await;
@@ -98,26 +329,10 @@ impl S3Client {
         self.list_objects(bucket_name.clone()).await;
     }
 
-    pub(crate) async fn mkdir(&self, bucket_name: String) {
-        let base_url: BaseUrl = self.config.base_url.parse::<BaseUrl>().expect("error parsing base url...");
-
-        let static_provider = StaticProvider::new(
-            &*self.config.access_key,
-            &*self.config.secret_key,
-            None,
-        );
-
-        let client = Client::new(
-            base_url.clone(),
-            Some(Box::new(static_provider)),
-            None,
-            None,
-        )
-            .expect("error creating s3 client");
+    pub(crate) async fn mkdir(&self, bucket_name: String, object_lock: bool) {
+        let Some(client) = self.connect() else { return };
 
-        let exists = client
-            .bucket_exists(&BucketExistsArgs::new(&*bucket_name.clone()).unwrap())
-            .await;
+        let exists = self.exists(&client, &bucket_name).await;
         match exists {
             Ok(exist) => {
                 if exist {
@@ -126,52 +341,46 @@ impl S3Client {
                 }
             }
             Err(err) => {
-                println!("cant check existence of bucket with name: {}", bucket_name);
+                println!("cant check existence of bucket with name: {}: {}", bucket_name, err);
                 return;
             }
         }
 
-        let resp = client.make_bucket(&MakeBucketArgs::new(&*bucket_name.clone()).unwrap()).await;
+        // not idempotent: a repeated CreateBucket fails once the first one went through
+        let mut args = MakeBucketArgs::new(&bucket_name).unwrap();
+        args.object_lock = object_lock;
+        let resp = retry(&self.config.retry, "create bucket", false, || client.make_bucket(&args)).await;
         match resp {
+            Ok(resp) if object_lock => {
+                println!("bucket {} successfully created with object lock, versioning is on and can't be turned off", resp.bucket_name);
+            }
             Ok(resp) => {
                 println!("bucket {} successfully created", resp.bucket_name);
             }
             Err(err) => {
-                println!("cant create bucket with name: {}", bucket_name);
+                println!("cant create bucket with name: {}: {}", bucket_name, err);
             }
         }
     }
 
     pub(crate) async fn rm(&self, bucket_name: String) {
-        let base_url: BaseUrl = self.config.base_url.parse::<BaseUrl>().expect("error parsing base url...");
+        let Some(client) = self.connect() else { return };
 
-        let static_provider = StaticProvider::new(
-            &*self.config.access_key,
-            &*self.config.secret_key,
-            None,
-        );
-
-        let client = Client::new(
-            base_url.clone(),
-            Some(Box::new(static_provider)),
-            None,
-            None,
-        )
-            .expect("error creating s3 client");
-
-        let exists = client
-            .bucket_exists(&BucketExistsArgs::new(&*bucket_name.clone()).unwrap())
-            .await;
+        let exists = self.exists(&client, &bucket_name).await;
         match exists {
             Ok(exist) => {
                 if exist {
-                    let resp = client.remove_bucket(&RemoveBucketArgs::new(&*bucket_name.clone()).unwrap()).await;
+                    let args = RemoveBucketArgs::new(&bucket_name).unwrap();
+                    let resp = retry(&self.config.retry, "remove bucket", true, || client.remove_bucket(&args)).await;
                     match resp {
                         Ok(resp) => {
                             println!("bucket {} successfully deleted", resp.bucket_name);
                         }
+                        Err(Error::S3Error(err)) if err.code == "BucketNotEmpty" => {
+                            println!("bucket {} is not empty, use {} to delete it with all objects", bucket_name, format!("rmdir --force {}", bucket_name).yellow());
+                        }
                         Err(err) => {
-                            println!("cant delete/remove bucket with name: {}", bucket_name);
+                            println!("cant delete/remove bucket with name: {}: {}", bucket_name, err);
                         }
                     }
                     return;
@@ -181,43 +390,49 @@ impl S3Client {
                 }
             }
             Err(err) => {
-                println!("cant check existence of bucket with name: {}", bucket_name);
+                println!("cant check existence of bucket with name: {}: {}", bucket_name, err);
                 return;
             }
         }
     }
 
-    pub(crate) async fn rm_obj(&self, bucket_name: String, object_name: String) {
-        let base_url: BaseUrl = self.config.base_url.parse::<BaseUrl>().expect("error parsing base url...");
-
-        let static_provider = StaticProvider::new(
-            &*self.config.access_key,
-            &*self.config.secret_key,
-            None,
-        );
-
-        let client = Client::new(
-            base_url.clone(),
-            Some(Box::new(static_provider)),
-            None,
-            None,
-        )
-            .expect("error creating s3 client");
+    pub(crate) async fn rm_obj(&self, bucket_name: String, object_name: String, version_id: Option<String>, bypass_governance: bool) {
+        let Some(client) = self.connect() else { return };
 
-        let exists = client
-            .bucket_exists(&BucketExistsArgs::new(&*bucket_name.clone()).unwrap())
-            .await;
+        let exists = self.exists(&client, &bucket_name).await;
         match exists {
             Ok(exist) => {
                 if exist {
-                    let resp = client.remove_object(
-                        &RemoveObjectArgs::new(&*bucket_name.clone(), &*object_name.clone()).unwrap()).await;
+                    let mut bypass = Multimap::new();
+                    bypass.insert("x-amz-bypass-governance-retention".to_string(), "true".to_string());
+                    let mut args = RemoveObjectArgs::new(&bucket_name, &object_name).unwrap();
+                    args.version_id = version_id.as_deref();
+                    if bypass_governance {
+                        args.extra_headers = Some(&bypass);
+                    }
+                    let resp = retry(&self.config.retry, "remove object", true, || client.remove_object(&args)).await;
                     match resp {
+                        // in a versioned bucket a plain delete only hides the object behind a marker
+                        Ok(resp) if resp.headers.get("x-amz-delete-marker").is_some_and(|marker| marker == "true") && version_id.is_none() => {
+                            let marker = resp.headers.get("x-amz-version-id").and_then(|marker| marker.to_str().ok()).unwrap_or_default();
+                            println!("object {} in bucket {} deleted, delete marker {} created", resp.object_name, resp.bucket_name, marker);
+                            println!("{}", "older versions are kept, see ls --versions, bring it back with restore or rm --version-id <marker>".blue());
+                        }
+                        Ok(resp) if version_id.is_some() => {
+                            println!("version {} of object {} in bucket {} permanently deleted", version_id.unwrap_or_default(), resp.object_name, resp.bucket_name);
+                        }
                         Ok(resp) => {
                             println!("object {} in bucket {} successfully deleted", resp.object_name, resp.bucket_name);
                         }
                         Err(err) => {
-                            println!("cant delete/remove object {} from bucket {}", object_name, bucket_name);
+                            let code = match &err {
+                                Error::S3Error(resp) => resp.code.clone(),
+                                _ => String::new(),
+                            };
+                            match self.lock_reason(&client, &bucket_name, &object_name, version_id.as_deref(), &code).await {
+                                Some(reason) => println!("cant delete object {} from bucket {}: {}", object_name, bucket_name, reason.yellow()),
+                                None => println!("cant delete/remove object {} from bucket {}: {}", object_name, bucket_name, err),
+                            }
                         }
                     }
                     return;
@@ -227,42 +442,289 @@ impl S3Client {
                 }
             }
             Err(err) => {
-                println!("cant check existence of bucket with name: {}", bucket_name);
+                println!("cant check existence of bucket with name: {}: {}", bucket_name, err);
                 return;
             }
         }
     }
 
-    pub(crate) async fn get(&self, bucket_name: String, remote_file_name: String, local_file_path: String) {
-        let base_url: BaseUrl = self.config.base_url.parse::<BaseUrl>().expect("error parsing base url...");
+    /// Server side copy of one object, or with `recursive` of every object under a prefix.
+    /// Encrypted `.x` objects keep the suffix so `get` still decrypts them. With
+    /// `remove_source` the copied objects are deleted afterwards, which makes it a move.
+    pub(crate) async fn cp(&self, src: (String, String), dst: (String, String), recursive: bool, remove_source: bool) {
+        let (src_bucket, src_key) = src;
+        let (dst_bucket, dst_key) = dst;
+        let Some(client) = self.connect() else { return };
 
-        let static_provider = StaticProvider::new(
-            &*self.config.access_key,
-            &*self.config.secret_key,
-            None,
-        );
+        let pairs: Vec<(String, String)> = if recursive {
+            // "foo" copies the object foo and what is under foo/, not foobar
+            let src_prefix = as_folder(&src_key);
+            let dst_prefix = as_folder(&dst_key);
+            match self.list_all(&client, &src_bucket, &src_key).await {
+                Ok(objects) => objects.into_iter()
+                    .filter_map(|object| {
+                        let dst_name = match object.name == src_key {
+                            true => single_copy_name(&object.name, &dst_key),
+                            false => dst_prefix.clone() + object.name.strip_prefix(&src_prefix)?,
+                        };
+                        Some((object.name, dst_name))
+                    })
+                    .collect(),
+                Err(err) => {
+                    println!("cant list objects in {}/{}: {}", src_bucket, src_key, err);
+                    return;
+                }
+            }
+        } else {
+            if src_key.is_empty() {
+                println!("{}", "specify source as <bucket>/<object>, or use -r to copy a prefix".yellow());
+                return;
+            }
+            vec![(src_key.clone(), single_copy_name(&src_key, &dst_key))]
+        };
+        if pairs.is_empty() {
+            println!("nothing to copy in {}/{}", src_bucket, src_key);
+            return;
+        }
 
-        let client = Client::new(
-            base_url.clone(),
-            Some(Box::new(static_provider)),
-            None,
-            None,
-        )
-            .unwrap();
+        let verb = if remove_source { "moved" } else { "copied" };
+        for (src_name, dst_name) in pairs {
+            let dst_name = match encrypted_name(&src_name, &dst_name) {
+                Some(dst_name) => dst_name,
+                None => {
+                    println!("{} {} {}", "refusing to give plain object".yellow(), src_name, "an encrypted .x name".yellow());
+                    continue;
+                }
+            };
+            if src_bucket == dst_bucket && src_name == dst_name {
+                println!("{}/{} is the same as the destination, skipped", src_bucket, src_name);
+                continue;
+            }
+            if let Err(err) = self.copy_object(&client, (&src_bucket, &src_name, None), (&dst_bucket, &dst_name)).await {
+                println!("cant copy {}/{} to {}/{}: {}", src_bucket, src_name, dst_bucket, dst_name, err);
+                continue;
+            }
+            if remove_source {
+                let args = RemoveObjectArgs::new(&src_bucket, &src_name).unwrap();
+                if let Err(err) = retry(&self.config.retry, "remove object", true, || client.remove_object(&args)).await {
+                    println!("copied {}/{} to {}/{} but cant remove the source: {}", src_bucket, src_name, dst_bucket, dst_name, err);
+                    continue;
+                }
+            }
+            println!("{}/{} {} to {}/{}", src_bucket, src_name, verb, dst_bucket, dst_name);
+        }
+    }
+
+    // CopyObject, or a multipart copy for sources bigger than 5 GiB. The source is
+    // opened with the key it needs and the copy written with the server side encryption of
+    // the destination.
+    async fn copy_object(&self, client: &Client, src: (&str, &str, Option<&str>), dst: (&str, &str)) -> Result<(), Error> {
+        let (stat, source_sse) = self.stat_with_sse(client, src.0, src.1, src.2).await?;
+        if stat.size > MAX_COPY_SIZE {
+            return self.copy_multipart(client, src, dst, &stat, &source_sse).await;
+        }
+        let read_headers = source_sse.read_headers();
+        let mut write_headers = self.sse(dst.0, dst.1).map_err(config_error)?.write_headers();
+        merge(&mut write_headers, &source_sse.copy_source_headers());
+        let mut source = CopySource::new(src.0, src.1)?;
+        source.version_id = src.2;
+        source.extra_headers = Some(&read_headers);
+        let mut args = CopyObjectArgs::new(dst.0, dst.1, source)?;
+        args.extra_headers = Some(&write_headers);
+        retry(&self.config.retry, "copy object", true, || client.copy_object(&args)).await?;
+        Ok(())
+    }
+
+    // CreateMultipartUpload with the content type, metadata and tags of the source, then
+    // ranged UploadPartCopy calls. Every part names the ETag of the stat, so a source
+    // replaced halfway fails the copy instead of mixing two objects
+    async fn copy_multipart(&self, client: &Client, src: (&str, &str, Option<&str>), dst: (&str, &str), stat: &StatObjectResponse, source_sse: &Sse) -> Result<(), Error> {
+        let write_sse = self.sse(dst.0, dst.1).map_err(config_error)?;
+        let mut options = PutOptions {
+            content_type: stat.headers.get("content-type").and_then(|value| value.to_str().ok()).map(String::from),
+            metadata: stat.user_metadata.iter().map(|(key, value)| (key.clone(), value.clone())).collect(),
+            ..Default::default()
+        };
+        let mut args = GetObjectTagsArgs::new(src.0, src.1)?;
+        args.version_id = src.2;
+        options.tags = retry(&self.config.retry, "get object tags", true, || client.get_object_tags(&args)).await?.tags.into_iter().collect();
+        let mut headers = options.headers();
+        merge(&mut headers, &write_sse.write_headers());
+
+        let mut args = CreateMultipartUploadArgs::new(dst.0, dst.1)?;
+        args.extra_headers = Some(&headers);
+        let upload_id = retry(&self.config.retry, "create multipart upload", false, || client.create_multipart_upload(&args)).await?.upload_id;
+        let res = self.copy_parts(client, src, (dst.0, dst.1, &upload_id), stat, source_sse, &write_sse).await;
+        if res.is_err() {
+            if let Ok(args) = AbortMultipartUploadArgs::new(dst.0, dst.1, &upload_id) {
+                let _ = retry(&self.config.retry, "abort multipart upload", true, || client.abort_multipart_upload(&args)).await;
+            }
+        }
+        res
+    }
+
+    // parts carry the key of an SSE-C source and the SSE-C key of the destination, like
+    // the parts of an upload
+    async fn copy_parts(&self, client: &Client, src: (&str, &str, Option<&str>), upload: (&str, &str, &str), stat: &StatObjectResponse, source_sse: &Sse, write_sse: &Sse) -> Result<(), Error> {
+        let (bucket_name, object_name, upload_id) = upload;
+        let mut source = CopySource::new(src.0, src.1)?;
+        source.version_id = src.2;
+        source.match_etag = Some(&stat.etag);
+        let mut copy_headers = source.get_copy_headers();
+        merge(&mut copy_headers, &source_sse.copy_source_headers());
+        merge(&mut copy_headers, &write_sse.read_headers());
+
+        let transfer = Transfer::start(Stage::Copy, object_name, stat.size as u64);
+        let mut parts: Vec<Part> = Vec::new();
+        let mut offset = 0;
+        while offset < stat.size {
+            let end = (offset + COPY_PART_SIZE).min(stat.size);
+            let part_number = parts.len() as u16 + 1;
+            let mut headers = copy_headers.clone();
+            headers.insert("x-amz-copy-source-range".to_string(), format!("bytes={}-{}", offset, end - 1));
+            let args = UploadPartCopyArgs::new(bucket_name, object_name, upload_id, part_number, headers)?;
+            let what = format!("copy part {}", part_number);
+            let resp = match retry(&self.config.retry, &what, true, || client.upload_part_copy(&args)).await {
+                Ok(resp) => resp,
+                Err(err) => {
+                    transfer.fail();
+                    return Err(err);
+                }
+            };
+            parts.push(Part { number: part_number, etag: resp.etag });
+            transfer.inc((end - offset) as u64);
+            offset = end;
+        }
+        let args = CompleteMultipartUploadArgs::new(bucket_name, object_name, upload_id, &parts)?;
+        match retry(&self.config.retry, "complete multipart upload", false, || client.complete_multipart_upload(&args)).await {
+            Ok(_) => transfer.finish(),
+            Err(err) => {
+                transfer.fail();
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes every object under `prefix` with batched DeleteObjects requests, after
+    /// showing how many objects and bytes go away. With `all_versions` old versions and
+    /// delete markers go too. Returns whether all were deleted.
+    pub(crate) async fn rm_recursive(&self, bucket_name: String, prefix: String, yes: bool, all_versions: bool) -> bool {
+        let Some(client) = self.connect() else { return false };
+
+        let listing = match all_versions {
+            true => self.list_versions(&client, &bucket_name, &prefix).await,
+            false => self.list_all(&client, &bucket_name, &prefix).await,
+        };
+        let objects = match listing {
+            Ok(objects) => objects,
+            Err(err) => {
+                println!("cant list objects in {}/{}: {}", bucket_name, prefix, err);
+                return false;
+            }
+        };
+        if objects.is_empty() {
+            println!("no objects in {}/{}", bucket_name, prefix);
+            return true;
+        }
+
+        let total: u64 = objects.iter().map(|object| object.size.unwrap_or(0) as u64).sum();
+        let question = format!("Delete {} objects ({}) from {}/{}?", objects.len(), HumanBytes(total), bucket_name, prefix);
+        if !yes && !y_or_n(&question) {
+            println!("nothing deleted");
+            return false;
+        }
+
+        let (deleted, failed) = self.delete_items(&client, &bucket_name, &prefix, &objects, all_versions).await;
+        println!("{} objects deleted from {}/{}, {} failed", deleted, bucket_name, prefix, failed);
+        failed == 0
+    }
+
+    // batched DeleteObjects of `objects` listed under `prefix`, gives the deleted and failed counts
+    async fn delete_items(&self, client: &Client, bucket_name: &str, prefix: &str, objects: &[Item], all_versions: bool) -> (usize, usize) {
+        let mut deleted = 0;
+        let mut failed = 0;
+        // keys of batches whose response could not be parsed, checked with a listing below
+        let mut unconfirmed: Vec<&str> = vec![];
+        for batch in objects.chunks(DELETE_BATCH) {
+            let keys: Vec<DeleteObject> = batch.iter().map(|object| DeleteObject { name: &object.name, version_id: object.version_id.as_deref() }).collect();
+            let mut args = RemoveObjectsApiArgs::new(bucket_name, &keys).unwrap();
+            args.quiet = true;
+            match retry(&self.config.retry, "delete objects", true, || client.remove_objects_api(&args)).await {
+                Ok(resp) => {
+                    for err in &resp.errors {
+                        let reason = self.lock_reason(client, bucket_name, &err.object_name, err.version_id.as_deref(), &err.code).await;
+                        match reason {
+                            Some(reason) => println!("cant delete object {} from bucket {}: {}", err.object_name, bucket_name, reason.yellow()),
+                            None => println!("cant delete/remove object {} from bucket {}: {} {}", err.object_name, bucket_name, err.code, err.message),
+                        }
+                    }
+                    failed += resp.errors.len();
+                    deleted += batch.len() - resp.errors.len();
+                }
+                // some servers answer quiet deletes with entries minio can't parse
+                Err(Error::XmlError(_)) => unconfirmed.extend(batch.iter().map(|object| object.name.as_str())),
+                Err(err) => {
+                    println!("cant delete batch of {} objects from bucket {}: {}", batch.len(), bucket_name, err);
+                    failed += batch.len();
+                }
+            }
+        }
+        if !unconfirmed.is_empty() {
+            let left = match all_versions {
+                true => self.list_versions(client, bucket_name, prefix).await,
+                false => self.list_all(client, bucket_name, prefix).await,
+            };
+            let left: Vec<String> = left
+                .map(|objects| objects.into_iter().map(|object| object.name).collect())
+                .unwrap_or_default();
+            for name in unconfirmed {
+                match left.iter().any(|key| key == name) {
+                    true => {
+                        println!("cant delete/remove object {} from bucket {}", name, bucket_name);
+                        failed += 1;
+                    }
+                    false => deleted += 1,
+                }
+            }
+        }
+        (deleted, failed)
+    }
+
+    /// Deletes a bucket. With `force` it is emptied first, including old versions.
+    pub(crate) async fn rmdir(&self, bucket_name: String, force: bool, yes: bool) {
+        if force && !self.rm_recursive(bucket_name.clone(), "".to_string(), yes, true).await {
+            println!("bucket {} is not empty, not deleted", bucket_name);
+            return;
+        }
+        self.rm(bucket_name).await;
+    }
+
+    pub(crate) async fn get(&self, bucket_name: String, remote_file_name: String, local_file_path: String, version_id: Option<String>) {
+        let Some(client) = self.connect() else { return };
 
-        let exists = client
-            .bucket_exists(&BucketExistsArgs::new(&*bucket_name.clone()).unwrap())
-            .await;
+        let exists = self.exists(&client, &bucket_name).await;
         match exists {
             Ok(exist) => {
                 if exist {
-                    let resp = client.download_object(&DownloadObjectArgs::new(&*bucket_name.clone(), &*remote_file_name.clone(), &*local_file_path.clone()).unwrap()).await;
-                    match resp {
-                        Ok(resp) => {
-                            println!("file: {} downloaded from bucket: {} successfully ", resp.object_name, resp.bucket_name);
+                    let part_path = part_path(&local_file_path);
+                    let mut file = match File::create(&part_path) {
+                        Ok(file) => file,
+                        Err(err) => {
+                            println!("cant create file {}: {}", part_path, err);
+                            return;
                         }
+                    };
+                    let resp = self.download(&client, &bucket_name, &remote_file_name, version_id.as_deref(), &mut file).await;
+                    drop(file);
+                    match resp {
+                        Ok(_) => match fs::rename(&part_path, &local_file_path) {
+                            Ok(_) => println!("file: {} downloaded from bucket: {} successfully ", remote_file_name, bucket_name),
+                            Err(err) => println!("cant move {} to {}: {}", part_path, local_file_path, err),
+                        },
                         Err(err) => {
-                            println!("cant load file: {} from bucket: {}", remote_file_name, bucket_name);
+                            let _ = fs::remove_file(&part_path);
+                            println!("cant load file: {} from bucket: {}: {}", remote_file_name, bucket_name, err);
                         }
                     }
                     return;
@@ -271,85 +733,88 @@ impl S3Client {
                 }
             }
             Err(err) => {
-                println!("cant check existence of bucket with name: {}", bucket_name);
+                println!("cant check existence of bucket with name: {}: {}", bucket_name, err);
                 return;
             }
         }
     }
-    pub(crate) async fn put(&self, bucket_name: String, remote_file_name: String, local_file_path: String) {
-        let base_url: BaseUrl = self.config.base_url.parse::<BaseUrl>().expect("error parsing base url...");
+    pub(crate) async fn put(&self, bucket_name: String, remote_file_name: String, local_file_path: String, options: &PutOptions) {
+        let Some(options) = self.rule_options(&bucket_name, &remote_file_name, false, options) else { return };
+        let Some(client) = self.connect() else { return };
 
-        let static_provider = StaticProvider::new(
-            &*self.config.access_key,
-            &*self.config.secret_key,
-            None,
-        );
-
-        let client = Client::new(
-            base_url.clone(),
-            Some(Box::new(static_provider)),
-            None,
-            None,
-        )
-            .unwrap();
-
-        let exists = client
-            .bucket_exists(&BucketExistsArgs::new(&*bucket_name.clone()).unwrap())
-            .await;
+        let exists = self.exists(&client, &bucket_name).await;
         match exists {
             Ok(exist) => {
                 if exist {
-                    let resp = client.upload_object(&UploadObjectArgs::new(&*bucket_name.clone(), &*remote_file_name.clone(), &*local_file_path.clone()).unwrap()).await;
+                    let opened = File::open(&local_file_path).and_then(|file| file.metadata().map(|metadata| (file, metadata.len())));
+                    let (mut file, size) = match opened {
+                        Ok(opened) => opened,
+                        Err(err) => {
+                            println!("cant open file {} for upload: {}", local_file_path, err);
+                            return;
+                        }
+                    };
+                    let mut options = options.clone();
+                    if options.content_type.is_none() {
+                        options.content_type = Some(content_type(&local_file_path).to_string());
+                    }
+                    // diff compares with this, the ETag of multipart uploads is no file hash
+                    match file_digests(Path::new(&local_file_path)) {
+                        Ok((_, sha256)) => options.metadata.push((SHA256_META.to_string(), sha256)),
+                        Err(err) => println!("cant hash file {}: {}", local_file_path, err),
+                    }
+                    let resp = self.upload(&client, &bucket_name, &remote_file_name, &mut file, size, &options).await;
                     match resp {
                         Ok(resp) => {
                             println!("file: {} uploaded to bucket: {} successfully ", resp.object_name, resp.bucket_name);
                         }
                         Err(err) => {
-                            println!("cant put file: {} to bucket: {}", remote_file_name, bucket_name);
+                            println!("cant put file: {} to bucket: {}: {}", remote_file_name, bucket_name, err);
                         }
                     }
                 }
             }
             Err(err) => {
-                println!("cant check existence of bucket with name: {}", bucket_name);
+                println!("cant check existence of bucket with name: {}: {}", bucket_name, err);
                 return;
             }
         }
     }
 
-    pub(crate) async fn put_file_encrypted(&self, bucket_name: String, remote_file_name: String, local_file_path: String) {
-        let file_bytes = fs::read(local_file_path.to_string())
-            .expect("can't open file for encryption and upload");
-        self.put_bytes_encrypted(bucket_name, remote_file_name, file_bytes).await;
+    pub(crate) async fn put_file_encrypted(&self, bucket_name: String, remote_file_name: String, local_file_path: String, options: &PutOptions) {
+        let file_bytes = match fs::read(&local_file_path) {
+            Ok(file_bytes) => file_bytes,
+            Err(err) => {
+                println!("cant read file {} for encryption: {}", local_file_path, err);
+                return;
+            }
+        };
+        self.put_bytes_encrypted(bucket_name, remote_file_name, file_bytes, options).await;
     }
 
-    pub(crate) async fn put_bytes_encrypted(&self, bucket_name: String, remote_file_name: String, file_bytes: Vec<u8>) {
-        let base_url: BaseUrl = self.config.base_url.parse::<BaseUrl>().expect("error parsing base url...");
-
-        let static_provider = StaticProvider::new(
-            &*self.config.access_key,
-            &*self.config.secret_key,
-            None,
-        );
-
-        let client = Client::new(
-            base_url.clone(),
-            Some(Box::new(static_provider)),
-            None,
-            None,
-        )
-            .unwrap();
+    pub(crate) async fn put_bytes_encrypted(&self, bucket_name: String, remote_file_name: String, file_bytes: Vec<u8>, options: &PutOptions) {
+        let Some(options) = self.rule_options(&bucket_name, &remote_file_name, true, options) else { return };
+        let Some(client) = self.connect() else { return };
 
-        let exists = client
-            .bucket_exists(&BucketExistsArgs::new(&*bucket_name.clone()).unwrap())
-            .await;
+        let exists = self.exists(&client, &bucket_name).await;
         match exists {
             Ok(exist) => {
                 if exist {
                     let remote_file_name = remote_file_name + ".x";
                     let conf = self.config.clone();
-                    let encrypted_bytes = encrypt_bytes(conf, file_bytes);
-                    let resp = client.put_object_api(&PutObjectApiArgs::new(&*bucket_name, &*remote_file_name, &*encrypted_bytes).unwrap()).await;
+                    let encryption = Transfer::start(Stage::Encrypt, &remote_file_name, file_bytes.len() as u64);
+                    let compression = options.compression.as_deref().unwrap_or("none");
+                    let encrypted_bytes = match encrypt_bytes(conf, file_bytes, &options.sealed_metadata(), &options.recipients, compression, &|bytes| encryption.inc(bytes)) {
+                        Ok(encrypted_bytes) => encrypted_bytes,
+                        Err(err) => {
+                            encryption.fail();
+                            println!("cant encrypt {}: {}", remote_file_name, err.red());
+                            return;
+                        }
+                    };
+                    encryption.finish();
+                    let size = encrypted_bytes.len() as u64;
+                    let resp = self.upload(&client, &bucket_name, &remote_file_name, &mut Cursor::new(encrypted_bytes), size, &options).await;
                     match resp {
                         Ok(resp) => {
                             println!("file: {} successfully saved to bucket: {}", resp.object_name, resp.bucket_name);
@@ -363,39 +828,22 @@ impl S3Client {
                 }
             }
             Err(err) => {
-                println!("error while checking existence of bucket: {}", bucket_name);
+                println!("error while checking existence of bucket: {}: {}", bucket_name, err);
                 return;
             }
         }
     }
 
     pub(crate) async fn put2(&self, bucket_name: String, remote_file_name: String, local_file_path: String) {
-        let base_url: BaseUrl = self.config.base_url.parse::<BaseUrl>().expect("error parsing base url...");
-
-        let static_provider = StaticProvider::new(
-            &*self.config.access_key,
-            &*self.config.secret_key,
-            None,
-        );
+        let Some(client) = self.connect() else { return };
 
-        let client = Client::new(
-            base_url.clone(),
-            Some(Box::new(static_provider)),
-            None,
-            None,
-        )
-            .unwrap();
-
-        let exists = client
-            .bucket_exists(&BucketExistsArgs::new(&*bucket_name.clone()).unwrap())
-            .await;
+        let exists = self.exists(&client, &bucket_name).await;
         match exists {
             Ok(exist) => {
                 if exist {
                     let remote_file_name = remote_file_name + ".x";
-                    let conf = self.config.clone();
-                    let args = UploadObjectArgs::new(&*bucket_name, &*remote_file_name, &local_file_path).unwrap();
-                    let resp = client.upload_object(&args).await;
+                    let args = UploadObjectArgs::new(&bucket_name, &remote_file_name, &local_file_path).unwrap();
+                    let resp = retry(&self.config.retry, "upload object", true, || client.upload_object(&args)).await;
                     match resp {
                         Ok(resp) => {
                             println!("file: {} successfully saved to bucket: {}", resp.object_name, resp.bucket_name);
@@ -409,79 +857,65 @@ impl S3Client {
                 }
             }
             Err(err) => {
-                println!("error while checking existence of bucket: {}", bucket_name);
+                println!("error while checking existence of bucket: {}: {}", bucket_name, err);
                 return;
             }
         }
     }
 
-    pub(crate) async fn get_file_encrypted(&self, bucket_name: String, remote_file_name: String, local_file_path: String) {
-        let file_bytes = self.get_bytes_encrypted(bucket_name, remote_file_name.clone()).await;
+    pub(crate) async fn get_file_encrypted(&self, bucket_name: String, remote_file_name: String, local_file_path: String, version_id: Option<String>) {
+        let file_bytes = self.get_bytes_encrypted(bucket_name, remote_file_name.clone(), version_id).await;
         let empty_vec: Vec<u8> = vec![];
         if file_bytes != empty_vec {
-            fs::write(local_file_path.strip_suffix(".x").unwrap(), file_bytes).expect("error writing decrypted file");
-            println!("file {} successfully downloaded and decrypted to {}", remote_file_name, local_file_path.strip_suffix(".x").unwrap());
+            let local_file_path = local_file_path.strip_suffix(".x").unwrap_or(&local_file_path);
+            let part_path = part_path(local_file_path);
+            match fs::write(&part_path, file_bytes).and_then(|_| fs::rename(&part_path, local_file_path)) {
+                Ok(_) => println!("file {} successfully downloaded and decrypted to {}", remote_file_name, local_file_path),
+                Err(err) => {
+                    let _ = fs::remove_file(&part_path);
+                    println!("cant write decrypted file {}: {}", local_file_path, err);
+                }
+            }
         }
     }
 
     pub(crate) async fn bucket_exists(&self, bucket_name: String) -> bool {
-        let base_url: BaseUrl = self.config.base_url.parse::<BaseUrl>().expect("error parsing base url...");
-
-        let static_provider = StaticProvider::new(
-            &*self.config.access_key,
-            &*self.config.secret_key,
-            None,
-        );
-
-        let client = Client::new(
-            base_url.clone(),
-            Some(Box::new(static_provider)),
-            None,
-            None,
-        )
-            .unwrap();
+        let Some(client) = self.connect() else { return false };
 
-        let exists = client
-            .bucket_exists(&BucketExistsArgs::new(&*bucket_name.clone()).unwrap())
-            .await;
+        let exists = self.exists(&client, &bucket_name).await;
         return exists.unwrap_or_else(|err| {
             println!("{}", err);
             return false
         })
     }
 
-    pub(crate) async fn get_bytes_encrypted(&self, bucket_name: String, remote_file_name: String) -> Vec<u8> {
-        let base_url: BaseUrl = self.config.base_url.parse::<BaseUrl>().expect("error parsing base url...");
-
-        let static_provider = StaticProvider::new(
-            &*self.config.access_key,
-            &*self.config.secret_key,
-            None,
-        );
-
-        let client = Client::new(
-            base_url.clone(),
-            Some(Box::new(static_provider)),
-            None,
-            None,
-        )
-            .unwrap();
+    pub(crate) async fn get_bytes_encrypted(&self, bucket_name: String, remote_file_name: String, version_id: Option<String>) -> Vec<u8> {
+        let Some(client) = self.connect() else { return vec![] };
 
-        let exists = client
-            .bucket_exists(&BucketExistsArgs::new(&*bucket_name.clone()).unwrap())
-            .await;
+        let exists = self.exists(&client, &bucket_name).await;
         return match exists {
             Ok(exist) => {
                 if exist {
-                    let resp = client.get_object(&GetObjectArgs::new(&*bucket_name, &*remote_file_name).unwrap()).await;
+                    let mut encrypted_bytes: Vec<u8> = Vec::new();
+                    let resp = self.download(&client, &bucket_name, &remote_file_name, version_id.as_deref(), &mut encrypted_bytes).await;
                     match resp {
-                        Ok(resp) => {
+                        Ok(_) => {
                             let conf = self.config.clone();
-                            let decrypted_bytes = decrypt_bytes(conf, resp.bytes().await.unwrap().to_vec());
-                            decrypted_bytes
+                            let decryption = Transfer::start(Stage::Decrypt, &remote_file_name, encrypted_bytes.len() as u64);
+                            match decrypt_bytes(conf, encrypted_bytes, &|bytes| decryption.inc(bytes)) {
+                                Ok(decrypted_bytes) => {
+                                    decryption.finish();
+                                    decrypted_bytes
+                                }
+                                Err(err) => {
+                                    decryption.fail();
+                                    println!("cant decrypt {} in bucket {}: {}", remote_file_name, bucket_name, err);
+                                    vec![]
+                                }
+                            }
                         }
                         Err(err) => {
-                            println!("error getting bytes from file {} in bucket {}", remote_file_name, bucket_name);
+                            println!("error getting bytes from file {} in bucket {}: {}", remote_file_name, bucket_name, err);
                             vec![]
                         }
                     }
@@ -491,13 +925,1379 @@ impl S3Client {
                 }
             }
             Err(err) => {
-                println!("error while checking existence of bucket: {}", bucket_name);
+                println!("error while checking existence of bucket: {}: {}", bucket_name, err);
                 vec![]
             }
         }
     }
-}
 
-impl ::std::default::Default for S3Client {
-    fn default() -> Self { Self { config: S3Config::default(), bucket: "".to_string() } }
+    /// Lists the objects under `prefix` that pass `filter` and prints, deletes or
+    /// downloads them. Downloads keep the folders below `prefix` and decrypt `.x` objects.
+    pub(crate) async fn find(&self, bucket_name: String, prefix: String, filter: &Filter, action: Action, yes: bool) {
+        let Some(client) = self.connect() else { return };
+
+        let objects = match self.list_all(&client, &bucket_name, &prefix).await {
+            Ok(objects) => objects,
+            Err(err) => {
+                println!("cant list objects in {}/{}: {}", bucket_name, prefix, err);
+                return;
+            }
+        };
+        let now = chrono::Utc::now();
+        let found: Vec<Item> = objects.into_iter().filter(|object| filter.matches(object, now)).collect();
+        match action {
+            Action::Print => {
+                for object in &found {
+                    println!("{}/{}", bucket_name, object.name);
+                }
+            }
+            Action::Print0 => {
+                for object in &found {
+                    print!("{}/{}\0", bucket_name, object.name);
+                }
+                let _ = std::io::stdout().flush();
+            }
+            Action::Delete => {
+                if found.is_empty() {
+                    println!("nothing found in {}/{}", bucket_name, prefix);
+                    return;
+                }
+                let total: u64 = found.iter().map(|object| object.size.unwrap_or(0) as u64).sum();
+                let question = format!("Delete {} found objects ({}) from {}/{}?", found.len(), HumanBytes(total), bucket_name, prefix);
+                if !yes && !y_or_n(&question) {
+                    println!("nothing deleted");
+                    return;
+                }
+                let (deleted, failed) = self.delete_items(&client, &bucket_name, &prefix, &found, false).await;
+                println!("{} objects deleted from {}/{}, {} failed", deleted, bucket_name, prefix, failed);
+            }
+            Action::Get => {
+                for object in found {
+                    let Some(local_file_path) = find_local_path(&object.name, &prefix) else {
+                        println!("{} would be written outside the working folder, skipped", object.name.yellow());
+                        continue;
+                    };
+                    if let Some(parent) = Path::new(&local_file_path).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                        if let Err(err) = fs::create_dir_all(parent) {
+                            println!("cant create folder {}: {}", parent.display(), err);
+                            continue;
+                        }
+                    }
+                    match object.name.ends_with(".x") {
+                        true => self.get_file_encrypted(bucket_name.clone(), object.name, local_file_path, None).await,
+                        false => self.get(bucket_name.clone(), object.name, local_file_path, None).await,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Compares the files under `local_dir` with the objects under `prefix`. Encrypted
+    /// objects are compared by the plaintext size and hash in their envelope header,
+    /// plain ones by size, then by their sha256 metadata or single part ETag. With
+    /// `upload_list` only the local paths that are missing or changed remotely are printed.
+    pub(crate) async fn diff(&self, local_dir: String, bucket_name: String, prefix: String, upload_list: bool) {
+        let Some(client) = self.connect() else { return };
+
+        let local = match local_files(Path::new(&local_dir)) {
+            Ok(local) => local,
+            Err(err) => {
+                println!("cant read folder {}: {}", local_dir, err);
+                return;
+            }
+        };
+        let prefix = match prefix.is_empty() || prefix.ends_with('/') {
+            true => prefix,
+            false => prefix + "/",
+        };
+        let objects = match self.list_all(&client, &bucket_name, &prefix).await {
+            Ok(objects) => objects,
+            Err(err) => {
+                println!("cant list objects in {}/{}: {}", bucket_name, prefix, err);
+                return;
+            }
+        };
+        // remote objects by local name, an encrypted one wins over a plain one
+        let mut remote: BTreeMap<String, Item> = BTreeMap::new();
+        for object in objects {
+            let relative = &object.name[prefix.len()..];
+            let name = relative.strip_suffix(".x").unwrap_or(relative).to_string();
+            if relative.ends_with(".x") || !remote.contains_key(&name) {
+                remote.insert(name, object);
+            }
+        }
+
+        let mut names: Vec<&String> = local.keys().chain(remote.keys()).collect();
+        names.sort();
+        names.dedup();
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for name in names {
+            let local_path = Path::new(&local_dir).join(name);
+            let change = match (local.get(name), remote.get(name)) {
+                (Some(_), None) => Change::OnlyLocal,
+                (None, Some(_)) => Change::OnlyRemote,
+                (Some(size), Some(object)) => self.compare(&client, &bucket_name, object, &local_path, *size).await,
+                (None, None) => continue,
+            };
+            let (label, mark) = match change {
+                Change::OnlyLocal => ("only local", "+"),
+                Change::OnlyRemote => ("only remote", "-"),
+                Change::Changed => ("changed", "~"),
+                Change::Same => ("same", "="),
+                Change::SameSize => ("same size, no hash to compare", "="),
+            };
+            *counts.entry(label).or_default() += 1;
+            match (change, upload_list) {
+                (Change::OnlyLocal | Change::Changed, true) => println!("{}", local_path.display()),
+                (Change::OnlyLocal | Change::Changed, false) => println!("{} {}", mark, local_path.display()),
+                (Change::OnlyRemote, false) => println!("{} {}/{}", mark, bucket_name, remote[name].name),
+                _ => {}
+            }
+        }
+        if !upload_list {
+            let summary: Vec<String> = counts.iter().map(|(label, count)| format!("{} {}", count, label)).collect();
+            println!("{} {}", "diff:".blue(), if summary.is_empty() { "nothing to compare".to_string() } else { summary.join(", ") });
+        }
+    }
+
+    // compares one local file with its remote object, hashing the file only when the sizes match
+    async fn compare(&self, client: &Client, bucket_name: &str, object: &Item, local_path: &Path, local_size: u64) -> Change {
+        let stored_size = object.size.unwrap_or(0) as u64;
+        let (remote_size, remote_sha256) = match object.name.ends_with(".x") {
+            true => match self.read_header(client, bucket_name, &object.name).await {
+                Ok(Some(header)) => {
+                    let sk = bs58::decode(&self.config.sk_bs58).into_vec().unwrap_or_default();
+                    let pk = bs58::decode(&self.config.pk_bs58).into_vec().unwrap_or_default();
+                    // another key's object is compared by size only
+                    (header.plaintext_size, open_sha256(&sk, &pk, &header).unwrap_or(None))
+                }
+                Ok(None) => (stored_size.saturating_sub(LEGACY_OVERHEAD), None),
+                Err(err) => {
+                    println!("cant read encryption header of {}: {}", object.name, err);
+                    return Change::Changed;
+                }
+            },
+            false => (stored_size, None),
+        };
+        if remote_size != local_size {
+            return Change::Changed;
+        }
+        let (md5, sha256) = match file_digests(local_path) {
+            Ok(digests) => digests,
+            Err(err) => {
+                println!("cant hash file {}: {}", local_path.display(), err);
+                return Change::Changed;
+            }
+        };
+        if object.name.ends_with(".x") {
+            return match remote_sha256 {
+                Some(remote_sha256) if remote_sha256 == sha256 => Change::Same,
+                Some(_) => Change::Changed,
+                None => Change::SameSize,
+            };
+        }
+        // the ETag of a single part upload is the MD5 of the content, unless SSE-KMS or SSE-C was used
+        if object.etag.as_deref().map(|etag| etag.trim_matches('"')) == Some(md5.as_str()) {
+            return Change::Same;
+        }
+        let resp = match self.stat_with_sse(client, bucket_name, &object.name, None).await {
+            Ok((resp, _)) => resp,
+            Err(err) => {
+                println!("cant stat {}/{}: {}", bucket_name, object.name, err);
+                return Change::SameSize;
+            }
+        };
+        let header = |name: &str| resp.headers.get(name).and_then(|value| value.to_str().ok()).map(String::from);
+        let etag_is_md5 = !resp.etag.contains('-')
+            && header("x-amz-server-side-encryption").as_deref() != Some("aws:kms")
+            && header("x-amz-server-side-encryption-customer-algorithm").is_none();
+        match resp.user_metadata.get(SHA256_META) {
+            Some(remote_sha256) if *remote_sha256 == sha256 => Change::Same,
+            Some(_) => Change::Changed,
+            None if etag_is_md5 => Change::Changed,
+            None => Change::SameSize,
+        }
+    }
+
+    /// Checks the config one step after the other: endpoint settings, name resolution,
+    /// connection and TLS, server clock, credentials, writing, reading and deleting a
+    /// scratch object in `bucket_name` (the first bucket when None) and the keypair.
+    pub(crate) async fn test_config(&self, bucket_name: Option<String>) {
+        println!("{} {}", "testing config".blue(), self.config.name);
+
+        let mut reachable = false;
+        if let Err(err) = validate(&self.config) {
+            step("endpoint", Err(err));
+            for name in ["dns", "connection", "clock"] {
+                skip(name, "endpoint settings are broken");
+            }
+        } else {
+            step("endpoint", Ok(format!("{}, {}", self.config.base_url, describe_endpoint(&self.config))));
+            let resolved = lookup(&self.config).await;
+            let resolved_ok = resolved.is_ok();
+            step("dns", resolved);
+            match resolved_ok {
+                true => match probe(&self.config).await {
+                    Ok((answered, skew)) => {
+                        reachable = true;
+                        step("connection", Ok(answered));
+                        match skew {
+                            Some(secs) if secs.abs() > MAX_CLOCK_SKEW_SECS => step("clock", Err(format!("server clock is {} s off, servers refuse requests signed more than {} s off", secs, MAX_CLOCK_SKEW_SECS))),
+                            Some(secs) => step("clock", Ok(format!("server clock is {} s off", secs))),
+                            None => skip("clock", "the server sent no Date header"),
+                        }
+                    }
+                    Err(err) => {
+                        step("connection", Err(err));
+                        skip("clock", "no connection");
+                    }
+                },
+                false => {
+                    skip("connection", "host not found");
+                    skip("clock", "host not found");
+                }
+            }
+        }
+
+        let mut bucket_name = bucket_name;
+        let mut signed_in = false;
+        match (reachable, resolve(&self.config)) {
+            (false, _) => skip("credentials", "no connection"),
+            (true, Err(err)) => step("credentials", Err(err)),
+            (true, Ok(credentials)) => {
+                let listed = match self.client() {
+                    Ok(client) => client.list_buckets(&ListBucketsArgs::new()).await.map_err(|err| describe_error(&err)),
+                    Err(err) => Err(err),
+                };
+                match listed {
+                    Ok(resp) => {
+                        signed_in = true;
+                        step("credentials", Ok(format!("key {} from {} lists {} buckets", credentials.access_key, credentials.origin, resp.buckets.len())));
+                        if bucket_name.is_none() {
+                            bucket_name = resp.buckets.first().map(|bucket| bucket.name.clone());
+                        }
+                    }
+                    Err(err) => step("credentials", Err(format!("key {} from {}: {}", credentials.access_key, credentials.origin, err))),
+                }
+            }
+        }
+
+        match (signed_in, bucket_name) {
+            (false, _) => {
+                for name in ["write", "read", "delete"] {
+                    skip(name, "not signed in");
+                }
+            }
+            (true, None) => {
+                for name in ["write", "read", "delete"] {
+                    skip(name, "no buckets, give one with --bucket <name>");
+                }
+            }
+            (true, Some(bucket_name)) => self.test_scratch(&bucket_name).await,
+        }
+
+        step("keypair", check_keypair(&self.config.sk_bs58, &self.config.pk_bs58).map(|_| "pk_bs58 belongs to sk_bs58".to_string()));
+    }
+
+    // writes, reads back and deletes a small object with the server side encryption of the config
+    async fn test_scratch(&self, bucket_name: &str) {
+        let Some(client) = self.connect() else { return };
+        let object_name = format!(".s3po-test-{}", bs58::encode(random_key()).into_string());
+        let data = format!("s3po config test {}\n", object_name).into_bytes();
+        let sse = Sse::parse(&self.config.sse, &self.config).unwrap_or(Sse::Off);
+        let write_headers = sse.write_headers();
+        let read_headers = sse.read_headers();
+        let place = format!("{}/{}", bucket_name, object_name);
+
+        let written = match PutObjectApiArgs::new(bucket_name, &object_name, &data) {
+            Ok(mut args) => {
+                args.headers = Some(&write_headers);
+                client.put_object_api(&args).await.map(|_| ()).map_err(|err| describe_error(&err))
+            }
+            Err(err) => Err(describe_error(&err)),
+        };
+        if let Err(err) = written {
+            step("write", Err(format!("{}: {}", place, err)));
+            skip("read", "nothing written");
+            skip("delete", "nothing written");
+            return;
+        }
+        step("write", Ok(format!("{} bytes to {}", data.len(), place)));
+
+        let read = async {
+            let mut args = GetObjectArgs::new(bucket_name, &object_name)?;
+            args.extra_headers = Some(&read_headers);
+            Ok::<_, Error>(client.get_object(&args).await?.bytes().await?.to_vec())
+        };
+        match read.await {
+            Ok(content) if content == data => step("read", Ok(format!("{} bytes back from {}", content.len(), place))),
+            Ok(content) => step("read", Err(format!("{} gave {} different bytes back", place, content.len()))),
+            Err(err) => step("read", Err(format!("{}: {}", place, describe_error(&err)))),
+        }
+
+        match RemoveObjectArgs::new(bucket_name, &object_name) {
+            Ok(args) => match client.remove_object(&args).await {
+                Ok(_) => step("delete", Ok(place)),
+                Err(err) => step("delete", Err(format!("{}: {}, remove it by hand", place, describe_error(&err)))),
+            },
+            Err(err) => step("delete", Err(describe_error(&err))),
+        }
+    }
+
+    /// Seals all configs with a passphrase and stores the bundle in `bucket_name`
+    /// under `s3po-config/<time>.s3po`.
+    pub(crate) async fn backup_config(&self, bucket_name: String) {
+        let bundle = match Bundle::collect() {
+            Ok(bundle) => bundle,
+            Err(err) => {
+                println!("{}", err.red());
+                return;
+            }
+        };
+        let Some(passphrase) = new_passphrase() else { return };
+        let armored = match bundle.seal(&passphrase) {
+            Ok(armored) => armored,
+            Err(err) => {
+                println!("{}", err.red());
+                return;
+            }
+        };
+        let object_name = format!("{}{}.s3po", CONFIG_BACKUP_PREFIX, chrono::Utc::now().format("%Y%m%d-%H%M%S"));
+        // sse c is keyed by the config secret key, which a new machine doesn't have yet
+        let options = PutOptions {
+            content_type: Some("text/plain".to_string()),
+            sse: Some(match self.sse(&bucket_name, &object_name) {
+                Ok(Sse::C(_)) => Sse::Off,
+                Ok(sse) => sse,
+                Err(err) => {
+                    println!("{}", err.red());
+                    return;
+                }
+            }),
+            ..PutOptions::default()
+        };
+        let Some(client) = self.connect() else { return };
+        let size = armored.len() as u64;
+        match self.upload(&client, &bucket_name, &object_name, &mut Cursor::new(armored.into_bytes()), size, &options).await {
+            Ok(_) => {
+                record_key_backup(&bundle, &format!("{}/{}", bucket_name, object_name));
+                println!("{} configs backed up to {}/{}", bundle.files.len(), bucket_name, object_name.green());
+            }
+            Err(err) => println!("cant back up the configs to {}/{}: {}", bucket_name, object_name, err),
+        }
+    }
+
+    /// Fetches the newest config bundle from `bucket_name`, or `object_name` when given,
+    /// and merges its configs after asking for the passphrase.
+    pub(crate) async fn restore_config(&self, bucket_name: String, object_name: Option<String>) {
+        let Some(client) = self.connect() else { return };
+        let object_name = match object_name {
+            Some(object_name) => object_name,
+            None => match self.list_all(&client, &bucket_name, CONFIG_BACKUP_PREFIX).await {
+                Ok(items) => match items.into_iter().map(|item| item.name).filter(|name| name.ends_with(".s3po")).max() {
+                    Some(object_name) => object_name,
+                    None => {
+                        println!("{} {}/{}", "there is no config backup in".yellow(), bucket_name.yellow(), CONFIG_BACKUP_PREFIX.yellow());
+                        return;
+                    }
+                },
+                Err(err) => {
+                    println!("cant list {}/{}: {}", bucket_name, CONFIG_BACKUP_PREFIX, err);
+                    return;
+                }
+            },
+        };
+        // bundles are stored without sse c, see backup_config
+        let plain = S3Client { config: S3Config { sse: "off".to_string(), rules: vec![], ..self.config.clone() }, bucket: String::new() };
+        let mut armored: Vec<u8> = Vec::new();
+        if let Err(err) = plain.download(&client, &bucket_name, &object_name, None, &mut armored).await {
+            println!("cant fetch {}/{}: {}", bucket_name, object_name, err);
+            return;
+        }
+        match Bundle::open(&String::from_utf8_lossy(&armored), &passphrase()) {
+            Ok(bundle) => {
+                println!("bundle {} from {} with {} configs", object_name, bundle.created, bundle.files.len());
+                bundle.merge();
+                record_key_backup(&bundle, &format!("{}/{}", bucket_name, object_name));
+            }
+            Err(err) => println!("{}", err.red()),
+        }
+    }
+
+    /// Sums the objects under `prefix` by folder, storage class, encryption and age. With
+    /// `plaintext` the sizes before encryption are read from the envelope headers, one
+    /// ranged GET per `.x` object.
+    pub(crate) async fn du(&self, bucket_name: String, prefix: String, depth: usize, plaintext: bool, json: bool) {
+        let Some(client) = self.connect() else { return };
+
+        let objects = match self.list_all(&client, &bucket_name, &prefix).await {
+            Ok(objects) => objects,
+            Err(err) => {
+                println!("cant list objects in {}/{}: {}", bucket_name, prefix, err);
+                return;
+            }
+        };
+        let mut usage = Usage::new(&bucket_name, &prefix, depth, plaintext);
+        for object in objects {
+            let size = object.size.unwrap_or(0) as u64;
+            let plaintext_size = match plaintext && object.name.ends_with(".x") {
+                true => match self.read_header(&client, &bucket_name, &object.name).await {
+                    Ok(Some(header)) => Some(header.plaintext_size),
+                    Ok(None) => Some(size.saturating_sub(LEGACY_OVERHEAD)),
+                    Err(err) => {
+                        println!("cant read encryption header of {}, counting its stored size: {}", object.name, err);
+                        None
+                    }
+                },
+                false => None,
+            };
+            usage.add(&object.name, size, object.storage_class.as_deref(), object.last_modified, plaintext_size);
+        }
+        match json {
+            true => println!("{}", serde_json::to_string_pretty(&usage).expect("error writing usage as json")),
+            false => usage.print(),
+        }
+    }
+
+    /// Lists objects with their retention and legal hold, one HeadObject per object.
+    pub(crate) async fn ls_long(&self, bucket_name: String) {
+        let Some(client) = self.connect() else { return };
+
+        let objects = match self.list_all(&client, &bucket_name, "").await {
+            Ok(objects) => objects,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        for object in objects {
+            let lock = match self.stat_with_sse(&client, &bucket_name, &object.name, None).await {
+                Ok((resp, _)) => describe_lock(&resp),
+                Err(err) => format!("lock state unknown: {}", err),
+            };
+            println!("  {} {} {} {}", object.name, object.size.unwrap_or(0), object.last_modified.unwrap(), lock);
+        }
+    }
+
+    /// Puts an object under legal hold or releases it.
+    pub(crate) async fn legal_hold(&self, bucket_name: String, object_name: String, version_id: Option<String>, on: bool) {
+        let Some(client) = self.connect() else { return };
+
+        let res = match on {
+            true => {
+                let mut args = EnableObjectLegalHoldArgs::new(&bucket_name, &object_name).unwrap();
+                args.version_id = version_id.as_deref();
+                retry(&self.config.retry, "enable legal hold", true, || client.enable_object_legal_hold(&args)).await.map(|_| ())
+            }
+            false => {
+                let mut args = DisableObjectLegalHoldArgs::new(&bucket_name, &object_name).unwrap();
+                args.version_id = version_id.as_deref();
+                retry(&self.config.retry, "disable legal hold", true, || client.disable_object_legal_hold(&args)).await.map(|_| ())
+            }
+        };
+        match res {
+            Ok(_) => println!("legal hold of {}/{} is {}", bucket_name, object_name, if on { "on" } else { "off" }),
+            Err(Error::S3Error(err)) if err.code == "InvalidRequest" => {
+                println!("cant change legal hold of {}/{}: {}", bucket_name, object_name, "the bucket was created without object lock (mkdir --object-lock)".yellow());
+            }
+            Err(err) => println!("cant change legal hold of {}/{}: {}", bucket_name, object_name, err),
+        }
+    }
+
+    // explains a refused delete when object lock is the cause, None for other errors
+    async fn lock_reason(&self, client: &Client, bucket_name: &str, object_name: &str, version_id: Option<&str>, code: &str) -> Option<String> {
+        if code != "AccessDenied" && code != "ObjectLocked" {
+            return None;
+        }
+        let (resp, _) = self.stat_with_sse(client, bucket_name, object_name, version_id).await.ok()?;
+        let mut reasons = vec![];
+        if resp.legal_hold == Some(true) {
+            reasons.push("it is under legal hold, release it with legal-hold off".to_string());
+        }
+        if let (Some(mode), Some(until)) = (&resp.retention_mode, resp.retention_retain_until_date) {
+            if until > chrono::Utc::now() {
+                match mode {
+                    RetentionMode::COMPLIANCE => reasons.push(format!("it is retained in COMPLIANCE mode until {}, nobody can delete it before that", until)),
+                    RetentionMode::GOVERNANCE => reasons.push(format!("it is retained in GOVERNANCE mode until {}, users allowed to bypass it can use rm --bypass-governance", until)),
+                }
+            }
+        }
+        match reasons.is_empty() {
+            true => None,
+            false => Some(format!("object lock protects this version: {}", reasons.join("; "))),
+        }
+    }
+
+    /// Prints what HeadObject knows about an object and, for `.x` objects, the
+    /// encryption header read with a ranged GET instead of the whole body.
+    pub(crate) async fn stat(&self, bucket_name: String, object_name: String) {
+        let Some(client) = self.connect() else { return };
+
+        let resp = match self.stat_with_sse(&client, &bucket_name, &object_name, None).await {
+            Ok((resp, _)) => resp,
+            Err(err) => {
+                println!("cant stat {}/{}: {}", bucket_name, object_name, err);
+                return;
+            }
+        };
+        let header = |name: &str| resp.headers.get(name).and_then(|value| value.to_str().ok()).map(String::from);
+
+        println!("{}: {}/{}", "object".blue(), resp.bucket_name, resp.object_name);
+        println!("{}: {} ({})", "size".blue(), resp.size, HumanBytes(resp.size as u64));
+        if let Some(last_modified) = resp.last_modified {
+            println!("{}: {}", "last modified".blue(), last_modified);
+        }
+        println!("{}: {}", "etag".blue(), resp.etag);
+        println!("{}: {}", "content type".blue(), header("content-type").unwrap_or("-".to_string()));
+        println!("{}: {}", "storage class".blue(), header("x-amz-storage-class").unwrap_or("STANDARD".to_string()));
+        println!("{}: {}", "version id".blue(), resp.version_id.clone().unwrap_or("-".to_string()));
+        println!("{}: {}", "object lock".blue(), describe_lock(&resp));
+        let server_side = match (header("x-amz-server-side-encryption"), header("x-amz-server-side-encryption-customer-algorithm")) {
+            (Some(kms), _) if kms == "aws:kms" => format!("SSE-KMS, key {}", header("x-amz-server-side-encryption-aws-kms-key-id").unwrap_or("default".to_string())),
+            (Some(algorithm), _) => format!("SSE-S3 ({})", algorithm),
+            (None, Some(algorithm)) => format!("SSE-C ({})", algorithm),
+            (None, None) => "none".to_string(),
+        };
+        println!("{}: {}", "server side encryption".blue(), server_side);
+        let mut user_metadata: Vec<_> = resp.user_metadata.iter().collect();
+        user_metadata.sort();
+        for (key, value) in user_metadata {
+            println!("{}: {}={}", "metadata".blue(), key, value);
+        }
+        let args = GetObjectTagsArgs::new(&bucket_name, &object_name).unwrap();
+        if let Ok(tags) = retry(&self.config.retry, "get object tags", true, || client.get_object_tags(&args)).await {
+            let mut tags: Vec<_> = tags.tags.into_iter().collect();
+            tags.sort();
+            for (key, value) in tags {
+                println!("{}: {}={}", "tag".blue(), key, value);
+            }
+        }
+
+        if !object_name.ends_with(".x") {
+            return;
+        }
+        match self.read_header(&client, &bucket_name, &object_name).await {
+            Ok(Some(header)) => {
+                let own = bs58::decode(&self.config.pk_bs58).into_vec().map(|pk| fingerprint(&pk)).unwrap_or_default();
+                let mark = |fingerprint: &str| if fingerprint == own { " (this config)" } else { "" };
+                println!("{}: s3po envelope v{} ({})", "encryption".blue(), header.version, header.cipher);
+                println!("{}: {}{}", "key fingerprint".blue(), header.fingerprint, mark(&header.fingerprint));
+                for recipient in &header.recipients {
+                    println!("{}: {}{}", "recipient".blue(), recipient.fingerprint, mark(&recipient.fingerprint));
+                }
+                println!("{}: {}", "compression".blue(), header.compression);
+                println!("{}: {}", "chunk size".blue(), HumanBytes(header.chunk_size as u64));
+                println!("{}: {} ({})", "plaintext size".blue(), header.plaintext_size, HumanBytes(header.plaintext_size));
+                let sk = bs58::decode(&self.config.sk_bs58).into_vec().unwrap_or_default();
+                let pk = bs58::decode(&self.config.pk_bs58).into_vec().unwrap_or_default();
+                match open_sha256(&sk, &pk, &header) {
+                    Ok(Some(plaintext_sha256)) => println!("{}: {}", "plaintext sha256".blue(), plaintext_sha256),
+                    Ok(None) => {}
+                    Err(err) => println!("{}: present, {}", "plaintext sha256".blue(), err),
+                }
+                match open_metadata(&sk, &pk, &header) {
+                    Ok(sealed_metadata) => {
+                        for (key, value) in sealed_metadata {
+                            println!("{}: {}={}", "sealed metadata".blue(), key, value);
+                        }
+                    }
+                    Err(err) => println!("{}: present, {}", "sealed metadata".blue(), err),
+                }
+            }
+            Ok(None) => {
+                println!("{}: legacy ECIES (no header, single recipient)", "encryption".blue());
+                println!("{}: {}", "plaintext size".blue(), (resp.size as u64).saturating_sub(LEGACY_OVERHEAD));
+            }
+            Err(err) => println!("cant read encryption header of {}: {}", object_name, err),
+        }
+    }
+
+    // reads only the envelope prefix and header, `None` for objects from before the envelope
+    async fn read_header(&self, client: &Client, bucket_name: &str, object_name: &str) -> Result<Option<Header>, String> {
+        let sse_headers = self.read_sse(client, bucket_name, object_name, None).await.map_err(|err| err.to_string())?.read_headers();
+        let prefix = self.read_range(client, bucket_name, object_name, &sse_headers, 0, PREFIX_LEN).await.map_err(|err| err.to_string())?;
+        let Some(len) = header_len(&prefix) else {
+            return Ok(None);
+        };
+        if len > MAX_HEADER_LEN {
+            return Err(format!("broken encryption header: {} bytes long", len));
+        }
+        let header_bytes = self.read_range(client, bucket_name, object_name, &sse_headers, PREFIX_LEN, len).await.map_err(|err| err.to_string())?;
+        parse_header(&header_bytes).map(Some)
+    }
+
+    async fn read_range(&self, client: &Client, bucket_name: &str, object_name: &str, sse_headers: &Multimap, offset: usize, length: usize) -> Result<Vec<u8>, Error> {
+        let mut args = GetObjectArgs::new(bucket_name, object_name)?;
+        args.extra_headers = Some(sse_headers);
+        args.offset = Some(offset);
+        args.length = Some(length);
+        let resp = retry(&self.config.retry, "get object range", true, || client.get_object(&args)).await?;
+        Ok(resp.bytes().await?.to_vec())
+    }
+
+    /// Adds `tags` to an object, keeping its other tags. Without tags prints them.
+    pub(crate) async fn tag(&self, bucket_name: String, object_name: String, tags: Vec<(String, String)>) {
+        let Some(client) = self.connect() else { return };
+
+        let args = GetObjectTagsArgs::new(&bucket_name, &object_name).unwrap();
+        let mut current = match retry(&self.config.retry, "get object tags", true, || client.get_object_tags(&args)).await {
+            Ok(resp) => resp.tags,
+            Err(err) => {
+                println!("cant get tags of {}/{}: {}", bucket_name, object_name, err);
+                return;
+            }
+        };
+        if tags.is_empty() {
+            let mut current: Vec<_> = current.into_iter().collect();
+            current.sort();
+            for (key, value) in current {
+                println!("  {}={}", key, value);
+            }
+            return;
+        }
+        current.extend(tags);
+        self.set_tags(&client, &bucket_name, &object_name, &current).await;
+    }
+
+    /// Removes the tags named in `keys` from an object, or all of its tags.
+    pub(crate) async fn untag(&self, bucket_name: String, object_name: String, keys: Vec<String>) {
+        let Some(client) = self.connect() else { return };
+
+        if keys.is_empty() {
+            let args = DeleteObjectTagsArgs::new(&bucket_name, &object_name).unwrap();
+            match retry(&self.config.retry, "delete object tags", true, || client.delete_object_tags(&args)).await {
+                Ok(_) => println!("all tags removed from {}/{}", bucket_name, object_name),
+                Err(err) => println!("cant remove tags of {}/{}: {}", bucket_name, object_name, err),
+            }
+            return;
+        }
+        let args = GetObjectTagsArgs::new(&bucket_name, &object_name).unwrap();
+        let mut current = match retry(&self.config.retry, "get object tags", true, || client.get_object_tags(&args)).await {
+            Ok(resp) => resp.tags,
+            Err(err) => {
+                println!("cant get tags of {}/{}: {}", bucket_name, object_name, err);
+                return;
+            }
+        };
+        current.retain(|key, _| !keys.contains(key));
+        self.set_tags(&client, &bucket_name, &object_name, &current).await;
+    }
+
+    async fn set_tags(&self, client: &Client, bucket_name: &str, object_name: &str, tags: &HashMap<String, String>) {
+        let args = SetObjectTagsArgs::new(bucket_name, object_name, tags).unwrap();
+        match retry(&self.config.retry, "set object tags", true, || client.set_object_tags(&args)).await {
+            Ok(_) => println!("{}/{} has {} tag(s) now", bucket_name, object_name, tags.len()),
+            Err(err) => println!("cant set tags of {}/{}: {}", bucket_name, object_name, err),
+        }
+    }
+
+    /// `bucket policy|lifecycle|cors get|set|rm`. Documents are JSON or, for `.toml`
+    /// files, TOML and are checked locally before they are sent.
+    pub(crate) async fn bucket_config(&self, kind: String, action: String, bucket_name: String, path: Option<String>) {
+        let Some(client) = self.connect() else { return };
+
+        let (subresource, missing) = match kind.as_str() {
+            "policy" => ("policy", "NoSuchBucketPolicy"),
+            "lifecycle" => ("lifecycle", "NoSuchLifecycleConfiguration"),
+            _ => ("cors", "NoSuchCORSConfiguration"),
+        };
+        match action.as_str() {
+            "get" => {
+                let document = match self.subresource(&client, Method::GET, &bucket_name, subresource, None).await {
+                    Ok(document) => document,
+                    Err(Error::S3Error(err)) if err.code == missing => {
+                        println!("bucket {} has no {} configuration", bucket_name, kind);
+                        return;
+                    }
+                    Err(err) => {
+                        println!("cant get {} of bucket {}: {}", kind, bucket_name, err);
+                        return;
+                    }
+                };
+                let document = match kind.as_str() {
+                    "policy" => serde_json::from_str(&document).map_err(|err| err.to_string()),
+                    "lifecycle" => Lifecycle::from_xml(&document).and_then(|lifecycle| serde_json::to_value(lifecycle).map_err(|err| err.to_string())),
+                    _ => Cors::from_xml(&document).and_then(|cors| serde_json::to_value(cors).map_err(|err| err.to_string())),
+                };
+                let path = path.unwrap_or_default();
+                match document.and_then(|document| render::<serde_json::Value>(&document, &path)) {
+                    Ok(text) if path.is_empty() => println!("{}", text),
+                    Ok(text) => match fs::write(&path, text) {
+                        Ok(_) => println!("{} of bucket {} saved to {}", kind, bucket_name, path),
+                        Err(err) => println!("error writing {}: {}", path, err),
+                    },
+                    Err(err) => println!("cant read {} of bucket {}: {}", kind, bucket_name, err),
+                }
+            }
+            "set" => {
+                let Some(path) = path else {
+                    println!("{}", format!("usage: bucket {} set <bucket> <file.json|file.toml>", kind).yellow());
+                    return;
+                };
+                let body = match kind.as_str() {
+                    "policy" => load_policy(&path),
+                    "lifecycle" => load::<Lifecycle>(&path).and_then(|lifecycle| lifecycle.validate().map(|_| lifecycle.to_xml())),
+                    _ => load::<Cors>(&path).and_then(|cors| cors.validate().map(|_| cors.to_xml())),
+                };
+                let body = match body {
+                    Ok(body) => body,
+                    Err(err) => {
+                        println!("{} {}", "nothing sent,".yellow(), err.yellow());
+                        return;
+                    }
+                };
+                match self.subresource(&client, Method::PUT, &bucket_name, subresource, Some(body.as_bytes())).await {
+                    Ok(_) => println!("{} of bucket {} set from {}", kind, bucket_name, path),
+                    Err(err) => println!("cant set {} of bucket {}: {}", kind, bucket_name, err),
+                }
+            }
+            _ => {
+                match self.subresource(&client, Method::DELETE, &bucket_name, subresource, None).await {
+                    Ok(_) => println!("{} of bucket {} removed", kind, bucket_name),
+                    Err(err) => println!("cant remove {} of bucket {}: {}", kind, bucket_name, err),
+                }
+            }
+        }
+    }
+
+    // bucket sub resources like ?policy or ?cors that minio has no typed API for
+    async fn subresource(&self, client: &Client, method: Method, bucket_name: &str, subresource: &str, body: Option<&[u8]>) -> Result<String, Error> {
+        let region = client.get_region(bucket_name, None).await?;
+        let mut query_params = Multimap::new();
+        query_params.insert(subresource.to_string(), String::new());
+        let what = format!("{} bucket {}", method.as_str().to_lowercase(), subresource);
+        let resp = retry(&self.config.retry, &what, true, || {
+            let mut headers = Multimap::new();
+            // lifecycle and CORS are refused without Content-MD5
+            if let Some(body) = body {
+                headers.insert("Content-MD5".to_string(), md5sum_hash(body));
+            }
+            let (method, region, query_params) = (method.clone(), region.clone(), query_params.clone());
+            async move { client.execute(method, &region, &mut headers, &query_params, Some(bucket_name), None, body).await }
+        }).await?;
+        Ok(resp.text().await?)
+    }
+
+    /// Signs a GET or PUT url for the object with the config credentials. Nothing is
+    /// sent to the server, the url is valid for `expires` seconds.
+    pub(crate) async fn presign(&self, method: Method, bucket_name: String, object_name: String, expires: u64) -> Option<String> {
+        let client = self.connect()?;
+        // the signature only holds in the region of the bucket, asked with GetBucketLocation
+        // when the endpoint doesn't name one
+        let region = match base_url(&self.config) {
+            Ok(base_url) if !base_url.region.is_empty() => base_url.region,
+            _ => match retry(&self.config.retry, "get bucket location", true, || client.get_region(&bucket_name, None)).await {
+                // buckets made before regions had names answer EU for eu-west-1
+                Ok(region) if region == "EU" => "eu-west-1".to_string(),
+                Ok(region) => region,
+                Err(err) => {
+                    println!("{} {}: {}, {}", "cant find the region of bucket".yellow(), bucket_name, err, "signing for us-east-1".yellow());
+                    "us-east-1".to_string()
+                }
+            },
+        };
+        let mut args = match GetPresignedObjectUrlArgs::new(&bucket_name, &object_name, method) {
+            Ok(args) => args,
+            Err(err) => {
+                println!("cant presign {}/{}: {}", bucket_name, object_name, err);
+                return None;
+            }
+        };
+        args.region = Some(&region);
+        args.expiry_seconds = Some(expires.min(MAX_PRESIGN_SECS) as u32);
+        if let Ok(Sse::C(_)) = self.sse(&bucket_name, &object_name) {
+            println!("{}", "with sse c the url only works together with the SSE-C key headers".yellow());
+        }
+        match client.get_presigned_object_url(&args).await {
+            Ok(resp) => Some(resp.url),
+            Err(err) => {
+                println!("cant presign {}/{}: {}", bucket_name, object_name, err);
+                None
+            }
+        }
+    }
+
+    /// Encrypts a file with a fresh random key, uploads it and returns a presigned
+    /// url that carries the key in its fragment, which browsers never send to the server.
+    pub(crate) async fn share_link(&self, bucket_name: String, remote_file_name: String, local_file_path: String, expires: u64) -> Option<String> {
+        let client = self.connect()?;
+
+        match self.exists(&client, &bucket_name).await {
+            Ok(true) => {}
+            Ok(false) => {
+                println!("bucket {} does not exists", bucket_name);
+                return None;
+            }
+            Err(err) => {
+                println!("error while checking existence of bucket: {}: {}", bucket_name, err);
+                return None;
+            }
+        }
+        let file_bytes = match fs::read(&local_file_path) {
+            Ok(file_bytes) => file_bytes,
+            Err(err) => {
+                println!("cant read file {}: {}", local_file_path, err);
+                return None;
+            }
+        };
+        let key = random_key();
+        // one AEAD call with no chunks to report, so no progress bar
+        let encrypted_bytes = encrypt_with_key(&key, &file_bytes);
+        let size = encrypted_bytes.len() as u64;
+        // a link can't carry the SSE-C key headers, so those objects would be unreadable
+        let sse = match self.sse(&bucket_name, &remote_file_name) {
+            Ok(Sse::C(_)) => {
+                println!("{}", "sse c is skipped for share links, the file is still encrypted with the link key".yellow());
+                Sse::Off
+            }
+            Ok(sse) => sse,
+            Err(err) => {
+                println!("{}", err.red());
+                return None;
+            }
+        };
+        let options = PutOptions { sse: Some(sse), ..PutOptions::default() };
+        if let Err(err) = self.upload(&client, &bucket_name, &remote_file_name, &mut Cursor::new(encrypted_bytes), size, &options).await {
+            println!("cant put file: {} to bucket: {}: {}", remote_file_name, bucket_name, err);
+            return None;
+        }
+        let url = self.presign(Method::GET, bucket_name, remote_file_name, expires).await?;
+        Some(format!("{}#key={}", url, bs58::encode(key).into_string()))
+    }
+
+    /// Lists every object under `prefix`, following continuation tokens past 1000 keys.
+    async fn list_all(&self, client: &Client, bucket_name: &str, prefix: &str) -> Result<Vec<Item>, Error> {
+        let mut items: Vec<Item> = Vec::new();
+        let mut continuation_token: Option<String> = None;
+        loop {
+            let mut args = ListObjectsV2Args::new(bucket_name)?;
+            if !prefix.is_empty() {
+                args.prefix = Some(prefix);
+            }
+            args.continuation_token = continuation_token.clone();
+            let resp = retry(&self.config.retry, "list objects", true, || client.list_objects_v2(&args)).await?;
+            items.extend(resp.contents);
+            match resp.next_continuation_token {
+                Some(token) if resp.is_truncated => continuation_token = Some(token),
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    /// Lists every version and delete marker under `prefix`, following key markers.
+    async fn list_versions(&self, client: &Client, bucket_name: &str, prefix: &str) -> Result<Vec<Item>, Error> {
+        let mut items: Vec<Item> = Vec::new();
+        let mut markers: (Option<String>, Option<String>) = (None, None);
+        loop {
+            let mut args = ListObjectVersionsArgs::new(bucket_name)?;
+            if !prefix.is_empty() {
+                args.prefix = Some(prefix);
+            }
+            (args.key_marker, args.version_id_marker) = markers.clone();
+            let resp = retry(&self.config.retry, "list object versions", true, || client.list_object_versions(&args)).await?;
+            items.extend(resp.contents);
+            match resp.next_key_marker {
+                Some(key_marker) if resp.is_truncated => markers = (Some(key_marker), resp.next_version_id_marker),
+                _ => break,
+            }
+        }
+        // deleted markers are parsed after all versions, put them back in key and time order
+        items.sort_by(|a, b| a.name.cmp(&b.name).then(b.last_modified.cmp(&a.last_modified)));
+        Ok(items)
+    }
+
+    fn part_size(&self) -> usize {
+        match current_rate(&self.config) {
+            Some(_) => MIN_PART_SIZE,
+            None => PART_SIZE,
+        }
+    }
+
+    /// Sends `size` bytes from `stream` as a single PutObject or, for big objects,
+    /// as a multipart upload where only failed parts are sent again.
+    async fn upload(&self, client: &Client, bucket_name: &str, object_name: &str, stream: &mut dyn Read, size: u64, options: &PutOptions) -> Result<PutObjectApiResponse, Error> {
+        let sse = match &options.sse {
+            Some(sse) => sse.clone(),
+            None => self.sse(bucket_name, object_name).map_err(config_error)?,
+        };
+        let transfer = Transfer::start(Stage::Upload, object_name, size);
+        let mut headers = options.headers();
+        merge(&mut headers, &sse.write_headers());
+        let res = self.upload_stream(client, (bucket_name, object_name), stream, size, &headers, &transfer).await;
+        match res {
+            Ok(_) => transfer.finish(),
+            Err(_) => transfer.fail(),
+        }
+        res
+    }
+
+    async fn upload_stream(&self, client: &Client, object: (&str, &str), stream: &mut dyn Read, size: u64, headers: &Multimap, transfer: &Transfer) -> Result<PutObjectApiResponse, Error> {
+        let (bucket_name, object_name) = object;
+        if size as usize <= self.part_size() {
+            let mut data = Vec::with_capacity(size as usize);
+            stream.read_to_end(&mut data)?;
+            consume(&self.config, data.len() as u64).await;
+            // object lock refuses uploads without Content-MD5
+            let mut headers = headers.clone();
+            headers.insert("Content-MD5".to_string(), md5sum_hash(&data));
+            let mut args = PutObjectApiArgs::new(bucket_name, object_name, &data)?;
+            args.headers = Some(&headers);
+            let resp = retry(&self.config.retry, "put object", true, || client.put_object_api(&args)).await?;
+            transfer.inc(data.len() as u64);
+            return Ok(resp);
+        }
+
+        // minio only sends the extra headers of a CreateMultipartUpload
+        let mut args = CreateMultipartUploadArgs::new(bucket_name, object_name)?;
+        args.extra_headers = Some(headers);
+        let upload_id = retry(&self.config.retry, "create multipart upload", false, || client.create_multipart_upload(&args)).await?.upload_id;
+        let res = self.upload_parts(client, (bucket_name, object_name, &upload_id), stream, headers, transfer).await;
+        if res.is_err() {
+            if let Ok(args) = AbortMultipartUploadArgs::new(bucket_name, object_name, &upload_id) {
+                let _ = retry(&self.config.retry, "abort multipart upload", true, || client.abort_multipart_upload(&args)).await;
+            }
+        }
+        res
+    }
+
+    // parts repeat the SSE-C key of `headers`, the other upload headers only go with the create
+    async fn upload_parts(&self, client: &Client, upload: (&str, &str, &str), stream: &mut dyn Read, headers: &Multimap, transfer: &Transfer) -> Result<PutObjectApiResponse, Error> {
+        let (bucket_name, object_name, upload_id) = upload;
+        let part_size = self.part_size();
+        let mut parts: Vec<Part> = Vec::new();
+        let mut buf = vec![0u8; part_size];
+        loop {
+            let mut filled = 0;
+            while filled < part_size {
+                let n = stream.read(&mut buf[filled..])?;
+                if n == 0 {
+                    break;
+                }
+                filled += n;
+            }
+            if filled == 0 {
+                break;
+            }
+            let part_number = parts.len() as u16 + 1;
+            consume(&self.config, filled as u64).await;
+            let mut part_headers = Multimap::new();
+            for (name, value) in headers.iter() {
+                if name.starts_with("x-amz-server-side-encryption-customer-") {
+                    part_headers.insert(name.clone(), value.clone());
+                }
+            }
+            part_headers.insert("Content-MD5".to_string(), md5sum_hash(&buf[..filled]));
+            let mut args = UploadPartArgs::new(bucket_name, object_name, upload_id, part_number, &buf[..filled])?;
+            args.headers = Some(&part_headers);
+            let what = format!("upload part {}", part_number);
+            let resp = retry(&self.config.retry, &what, true, || client.upload_part(&args)).await?;
+            parts.push(Part { number: part_number, etag: resp.etag });
+            transfer.inc(filled as u64);
+            if filled < part_size {
+                break;
+            }
+        }
+        let args = CompleteMultipartUploadArgs::new(bucket_name, object_name, upload_id, &parts)?;
+        retry(&self.config.retry, "complete multipart upload", false, || client.complete_multipart_upload(&args)).await
+    }
+
+    /// Streams an object into `sink`. A connection dropped halfway resumes from the
+    /// last received byte instead of starting over.
+    async fn download(&self, client: &Client, bucket_name: &str, object_name: &str, version_id: Option<&str>, sink: &mut dyn Sink) -> Result<(), Error> {
+        let mut received: u64 = 0;
+        let mut transfer: Option<Transfer> = None;
+        let res = self.download_stream(client, (bucket_name, object_name, version_id), sink, &mut received, &mut transfer).await;
+        if let Some(transfer) = transfer {
+            match res {
+                Ok(_) => transfer.finish(),
+                Err(_) => transfer.fail(),
+            }
+        }
+        res
+    }
+
+    // a resumed download asks for the ETag and version of the first response, so it can't
+    // continue with the bytes of an object that was replaced in between. When it was, the
+    // download starts over
+    async fn download_stream(&self, client: &Client, object: (&str, &str, Option<&str>), sink: &mut dyn Sink, received: &mut u64, transfer: &mut Option<Transfer>) -> Result<(), Error> {
+        let (bucket_name, object_name, version_id) = object;
+        let policy = &self.config.retry;
+        let mut resumes: u32 = 0;
+        let sse_headers = self.read_sse(client, bucket_name, object_name, version_id).await?.read_headers();
+        let mut pinned: Option<(String, Option<String>)> = None;
+        loop {
+            let mut args = GetObjectArgs::new(bucket_name, object_name)?;
+            args.version_id = version_id.or(pinned.as_ref().and_then(|(_, version)| version.as_deref()));
+            args.extra_headers = Some(&sse_headers);
+            if *received > 0 {
+                args.offset = Some(*received as usize);
+                args.match_etag = pinned.as_ref().map(|(etag, _)| etag.as_str());
+            }
+            let changed = match retry(policy, "get object", true, || client.get_object(&args)).await {
+                Ok(resp) if *received > 0 && pinned.as_ref().map(|(etag, _)| etag.as_str()) != response_header(&resp, "etag") => Err(Some(resp)),
+                Ok(resp) => Ok(resp),
+                Err(err) if *received > 0 && is_precondition_failed(&err) => Err(None),
+                Err(err) => return Err(err),
+            };
+            let mut resp = match changed {
+                Ok(resp) => resp,
+                Err(_) => {
+                    println!("{} changed during the download, starting over", object_name);
+                    sink.restart()?;
+                    *received = 0;
+                    pinned = None;
+                    continue;
+                }
+            };
+            if pinned.is_none() {
+                pinned = response_header(&resp, "etag").map(|etag| (etag.to_string(), response_header(&resp, "x-amz-version-id").map(String::from)));
+            }
+            let total = resp.content_length().unwrap_or(0);
+            match transfer {
+                None => *transfer = Some(Transfer::start(Stage::Download, object_name, total)),
+                Some(progress) if *received == 0 => progress.restart(total),
+                Some(_) => {}
+            }
+            let progress = transfer.as_ref().unwrap();
+
+            let err = loop {
+                match with_timeout(policy.request_timeout(), async { resp.chunk().await.map_err(Error::HttpError) }).await {
+                    Ok(Some(chunk)) => {
+                        consume(&self.config, chunk.len() as u64).await;
+                        sink.write_all(&chunk)?;
+                        *received += chunk.len() as u64;
+                        progress.inc(chunk.len() as u64);
+                    }
+                    Ok(None) => return Ok(()),
+                    Err(err) => break err,
+                }
+            };
+            resumes += 1;
+            if !is_transient(&err) || resumes >= policy.max_attempts {
+                return Err(err);
+            }
+            // without an ETag a resume can't tell whether the object is still the same
+            if pinned.is_none() {
+                sink.restart()?;
+                *received = 0;
+            }
+            println!("download of {} interrupted ({}), resuming from byte {}", object_name, err, received);
+        }
+    }
+}
+
+/// Where a download is written. It can be emptied to start the download over.
+pub(crate) trait Sink: Write {
+    fn restart(&mut self) -> std::io::Result<()>;
+}
+
+impl Sink for File {
+    fn restart(&mut self) -> std::io::Result<()> {
+        self.set_len(0)?;
+        self.rewind()
+    }
+}
+
+impl Sink for Vec<u8> {
+    fn restart(&mut self) -> std::io::Result<()> {
+        self.clear();
+        Ok(())
+    }
+}
+
+fn response_header<'a>(resp: &'a reqwest::Response, name: &str) -> Option<&'a str> {
+    resp.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Downloads a presigned url and, when its fragment carries a share key, decrypts
+/// it. Works without any config, so the receiver of a share link needs no credentials.
+pub(crate) async fn fetch(url: String, local_file_path: Option<String>) {
+    let (url, key) = match url.split_once("#key=") {
+        Some((url, key)) => match bs58::decode(key).into_vec().ok().and_then(|key| <[u8; 32]>::try_from(key).ok()) {
+            Some(key) => (url.to_string(), Some(key)),
+            None => {
+                println!("{}", "the key in the link is broken, check it was copied completely".yellow());
+                return;
+            }
+        },
+        None => (url, None),
+    };
+    let file_name = match local_file_path {
+        Some(path) => path,
+        None => match url_file_name(&url) {
+            Some(name) => name,
+            None => {
+                println!("{}", "cant get a file name from the url, give one with -o <file name>".yellow());
+                return;
+            }
+        },
+    };
+
+    let policy = RetryPolicy::default();
+    let http = reqwest::Client::new();
+    let mut resp = match retry(&policy, "fetch", true, || async { Ok(http.get(&url).send().await?.error_for_status()?) }).await {
+        Ok(resp) => resp,
+        Err(err) => {
+            println!("cant fetch {}: {}", file_name, err);
+            return;
+        }
+    };
+    // a link without config has no limits of its own, only --limit-rate applies
+    let limits = S3Config::default();
+    let transfer = Transfer::start(Stage::Download, &file_name, resp.content_length().unwrap_or(0));
+    let mut content: Vec<u8> = Vec::new();
+    loop {
+        match with_timeout(policy.request_timeout(), async { resp.chunk().await.map_err(Error::HttpError) }).await {
+            Ok(Some(chunk)) => {
+                consume(&limits, chunk.len() as u64).await;
+                content.extend_from_slice(&chunk);
+                transfer.inc(chunk.len() as u64);
+            }
+            Ok(None) => break,
+            Err(err) => {
+                transfer.fail();
+                println!("cant fetch {}: {}", file_name, err);
+                return;
+            }
+        }
+    }
+    transfer.finish();
+
+    if let Some(key) = key {
+        content = match decrypt_with_key(&key, &content) {
+            Some(content) => content,
+            None => {
+                println!("{}", "cant decrypt the file, the key does not match or the file was changed".red());
+                return;
+            }
+        };
+    }
+    match fs::write(&file_name, content) {
+        Ok(_) => println!("file {} fetched successfully", file_name),
+        Err(err) => println!("error writing file {}: {}", file_name, err),
+    }
+}
+
+// one line of config test
+fn step(name: &str, result: Result<String, String>) {
+    match result {
+        Ok(detail) => println!("  {} {:<12} {}", "pass".green(), name, detail),
+        Err(err) => println!("  {} {:<12} {}", "fail".red(), name, err),
+    }
+}
+
+fn skip(name: &str, why: &str) {
+    println!("  {} {:<12} {}", "skip".yellow(), name, why);
+}
+
+// last path segment of a url, with %XX escapes decoded, None when that is no plain file name
+fn url_file_name(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let segment = url.path_segments()?.next_back()?;
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match (byte, tail.get(..2).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    let name = String::from_utf8(bytes).ok()?;
+    // an escaped / or .. would write outside the working folder
+    if name.contains(['/', '\\']) {
+        return None;
+    }
+    Path::new(&name).file_name().map(|name| name.to_string_lossy().to_string()).filter(|name| name != "." && name != "..")
+}
+
+// a destination that is empty or ends with / takes the file name of the source
+fn single_copy_name(src_name: &str, dst_key: &str) -> String {
+    match dst_key.is_empty() || dst_key.ends_with('/') {
+        true => dst_key.to_string() + src_name.rsplit('/').next().unwrap_or(src_name),
+        false => dst_key.to_string(),
+    }
+}
+
+// where find -get writes an object: the key below the prefix folder, else the last
+// segment of the key. None for keys with .. or a root that would leave the working folder
+fn find_local_path(name: &str, prefix: &str) -> Option<String> {
+    let path = match name.strip_prefix(&as_folder(prefix)) {
+        Some(rest) if !rest.is_empty() => rest,
+        _ => name.rsplit('/').next().unwrap_or(name),
+    };
+    let plain = Path::new(path).components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    match plain && Path::new(path).file_name().is_some() {
+        true => Some(path.to_string()),
+        false => None,
+    }
+}
+
+// a key prefix that only matches whole folders, empty stays empty
+fn as_folder(key: &str) -> String {
+    match key.is_empty() || key.ends_with('/') {
+        true => key.to_string(),
+        false => format!("{}/", key),
+    }
+}
+
+// a setting of the config that can't be used, as the error type of minio calls
+fn config_error(err: String) -> Error {
+    Error::IOError(std::io::Error::new(std::io::ErrorKind::InvalidInput, err))
+}
+
+// downloads are written next to the target and renamed over it once complete,
+// so a failed download never leaves a truncated file under the real name
+fn part_path(path: &str) -> String {
+    format!("{}.part", path)
+}
+
+/// The destination name for a copy of `src_name`: an encrypted `.x` source keeps
+/// the suffix, and a plain source must not get one, which gives `None`.
+fn encrypted_name(src_name: &str, dst_name: &str) -> Option<String> {
+    match (src_name.ends_with(".x"), dst_name.ends_with(".x")) {
+        (true, false) => Some(dst_name.to_string() + ".x"),
+        (false, true) => None,
+        _ => Some(dst_name.to_string()),
+    }
+}
+
+// retention and legal hold as shown by stat and ls -l
+fn describe_lock(resp: &StatObjectResponse) -> String {
+    let mut lock = match (&resp.retention_mode, resp.retention_retain_until_date) {
+        (Some(mode), Some(until)) if until > chrono::Utc::now() => format!("{} until {}", mode, until),
+        (Some(mode), Some(until)) => format!("{} expired {}", mode, until),
+        _ => "no retention".to_string(),
+    };
+    if resp.legal_hold == Some(true) {
+        lock += ", legal hold";
+    }
+    lock
+}
+
+// content type by file extension for plain uploads, anything else is binary
+fn content_type(local_file_path: &str) -> &'static str {
+    let extension = local_file_path.rsplit_once('.').map(|(_, extension)| extension.to_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "htm" | "html" => "text/html",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "toml" => "application/toml",
+        "yaml" | "yml" => "application/yaml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" | "tgz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+impl ::std::default::Default for S3Client {
+    fn default() -> Self { Self { config: S3Config::default(), bucket: "".to_string() } }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use crate::creds::CredentialSource;
+    use crate::crypto::new_keys;
+    use super::*;
+
+    // S3PO_TEST_URL points at a MinIO or other local S3 server, the test does nothing without it
+    fn test_client(sse: &str) -> Option<(S3Client, String)> {
+        let base_url = env::var("S3PO_TEST_URL").ok().filter(|url| !url.is_empty())?;
+        let (sk_bs58, pk_bs58) = new_keys();
+        let config = S3Config {
+            base_url,
+            access_key: env::var("S3PO_TEST_ACCESS_KEY").unwrap_or("minioadmin".to_string()),
+            secret_key: env::var("S3PO_TEST_SECRET_KEY").unwrap_or("minioadmin".to_string()),
+            credentials: CredentialSource { source: "config".to_string(), ..CredentialSource::default() },
+            sk_bs58,
+            pk_bs58,
+            sse: sse.to_string(),
+            ..S3Config::default()
+        };
+        let bucket_name = env::var("S3PO_TEST_BUCKET").unwrap_or("s3po-test".to_string());
+        Some((S3Client { config, bucket: String::new() }, bucket_name))
+    }
+
+    async fn ensure_bucket(s3cli: &S3Client, client: &Client, bucket_name: &str) {
+        if !s3cli.exists(client, bucket_name).await.expect("bucket exists") {
+            client.make_bucket(&MakeBucketArgs::new(bucket_name).unwrap()).await.expect("make bucket");
+        }
+    }
+
+    // put, get and remove with every server side encryption in S3PO_TEST_SSE, "off" by
+    // default: MinIO needs a KMS for s3 and kms, and https for c
+    #[tokio::test]
+    async fn put_get_round_trip() {
+        let settings = env::var("S3PO_TEST_SSE").unwrap_or("off".to_string());
+        for sse in settings.split(',').map(str::trim) {
+            let Some((s3cli, bucket_name)) = test_client(sse) else {
+                eprintln!("S3PO_TEST_URL is not set, skipping");
+                return;
+            };
+            let client = s3cli.client().unwrap();
+            ensure_bucket(&s3cli, &client, &bucket_name).await;
+            let object_name = format!("round-trip-{}.x", sse.replace(':', "-"));
+            let content: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+            let sealed = encrypt_bytes(s3cli.config.clone(), content.clone(), &BTreeMap::new(), &[], "deflate", &|_| {}).unwrap();
+
+            let size = sealed.len() as u64;
+            s3cli.upload(&client, &bucket_name, &object_name, &mut Cursor::new(sealed), size, &PutOptions::default()).await
+                .unwrap_or_else(|err| panic!("put with sse {}: {}", sse, err));
+            let mut downloaded = Vec::new();
+            s3cli.download(&client, &bucket_name, &object_name, None, &mut downloaded).await
+                .unwrap_or_else(|err| panic!("get with sse {}: {}", sse, err));
+            assert_eq!(decrypt_bytes(s3cli.config.clone(), downloaded, &|_| {}).unwrap(), content, "sse {}", sse);
+
+            client.remove_object(&RemoveObjectArgs::new(&bucket_name, &object_name).unwrap()).await.expect("remove object");
+        }
+    }
+
+    #[test]
+    fn find_get_paths_stay_in_the_folder() {
+        assert_eq!(find_local_path("logs/2024/app.log", "logs").as_deref(), Some("2024/app.log"));
+        assert_eq!(find_local_path("logs/2024/app.log", "logs/").as_deref(), Some("2024/app.log"));
+        assert_eq!(find_local_path("logs/2024/app.log", "").as_deref(), Some("logs/2024/app.log"));
+        // a prefix that is no folder of the key keeps the last segment
+        assert_eq!(find_local_path("logs-old/app.log", "logs").as_deref(), Some("app.log"));
+        assert_eq!(find_local_path("logs", "logs").as_deref(), Some("logs"));
+        assert_eq!(find_local_path("logs/../../.bashrc", "logs"), None);
+        assert_eq!(find_local_path("/etc/passwd", ""), None);
+        assert_eq!(find_local_path("logs/..", "logs"), None);
+    }
+
+    #[test]
+    fn url_file_names_stay_in_the_folder() {
+        assert_eq!(url_file_name("https://s3.example.com/bucket/report%20v2.pdf?X-Amz-Signature=1").as_deref(), Some("report v2.pdf"));
+        assert_eq!(url_file_name("https://s3.example.com/bucket/dir/file.txt#key=abc").as_deref(), Some("file.txt"));
+        for url in [
+            "https://s3.example.com/bucket/..%2F..%2F.bashrc",
+            "https://s3.example.com/bucket/%2Fetc%2Fpasswd",
+            "https://s3.example.com/bucket/%2E%2E",
+            "https://s3.example.com/bucket/..%5C..%5Cwin.ini",
+            "https://s3.example.com/bucket/",
+        ] {
+            assert_eq!(url_file_name(url), None, "{}", url);
+        }
+    }
 }
@@ -0,0 +1,192 @@
+use colored::Colorize;
+use minio::s3::utils::{b64encode, md5sum_hash, Multimap};
+use sha2::{Digest, Sha256};
+use crate::config::S3Config;
+
+// mixed into the SSE-C key so it never equals anything else derived from the secret key
+const SSE_C_CONTEXT: &[u8] = b"s3po sse-c v1";
+
+/// Server side encryption asked from the provider, on top of or instead of the
+/// client side encryption of put/get.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Sse {
+    Off,
+    /// SSE-S3, keys managed by the provider
+    S3,
+    /// SSE-KMS with the given key id, or the bucket/account default key
+    Kms(Option<String>),
+    /// SSE-C with a key derived from the config keypair
+    C([u8; 32]),
+}
+
+impl Sse {
+    /// Parses "off", "s3", "kms", "kms:<key id>" or "c", empty is off.
+    pub(crate) fn parse(value: &str, config: &S3Config) -> Result<Sse, String> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "off" | "none" => Ok(Sse::Off),
+            "s3" | "aes256" => Ok(Sse::S3),
+            "kms" | "aws:kms" => Ok(Sse::Kms(None)),
+            "c" | "sse-c" => Ok(Sse::C(derive_key(&config.sk_bs58)?)),
+            _ => match value.trim().split_once(':') {
+                Some((kind, key_id)) if kind.eq_ignore_ascii_case("kms") && !key_id.is_empty() => Ok(Sse::Kms(Some(key_id.to_string()))),
+                _ => Err(format!("can't parse sse \"{}\", expected off, s3, kms, kms:<key id> or c", value)),
+            },
+        }
+    }
+
+    /// Headers of requests that create an object: PutObject, CreateMultipartUpload
+    /// and the destination of CopyObject.
+    pub(crate) fn write_headers(&self) -> Multimap {
+        let mut headers = Multimap::new();
+        match self {
+            Sse::Off => {}
+            Sse::S3 => {
+                headers.insert("x-amz-server-side-encryption".to_string(), "AES256".to_string());
+            }
+            Sse::Kms(key_id) => {
+                headers.insert("x-amz-server-side-encryption".to_string(), "aws:kms".to_string());
+                if let Some(key_id) = key_id {
+                    headers.insert("x-amz-server-side-encryption-aws-kms-key-id".to_string(), key_id.clone());
+                }
+            }
+            Sse::C(key) => customer_headers(&mut headers, "x-amz-server-side-encryption-customer", key),
+        }
+        headers
+    }
+
+    /// Headers of requests that need the key again: GetObject, HeadObject and
+    /// UploadPart. Only SSE-C has any, the provider knows its own keys.
+    pub(crate) fn read_headers(&self) -> Multimap {
+        let mut headers = Multimap::new();
+        if let Sse::C(key) = self {
+            customer_headers(&mut headers, "x-amz-server-side-encryption-customer", key);
+        }
+        headers
+    }
+
+    /// Headers that let CopyObject read an SSE-C source, the destination gets
+    /// `write_headers`.
+    pub(crate) fn copy_source_headers(&self) -> Multimap {
+        let mut headers = Multimap::new();
+        if let Sse::C(key) = self {
+            customer_headers(&mut headers, "x-amz-copy-source-server-side-encryption-customer", key);
+        }
+        headers
+    }
+}
+
+impl std::fmt::Display for Sse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Sse::Off => write!(f, "off"),
+            Sse::S3 => write!(f, "SSE-S3"),
+            Sse::Kms(None) => write!(f, "SSE-KMS with the default key"),
+            Sse::Kms(Some(key_id)) => write!(f, "SSE-KMS with key {}", key_id),
+            Sse::C(_) => write!(f, "SSE-C with a key derived from the config keypair"),
+        }
+    }
+}
+
+/// Warns about an `sse` value in the config that can't be used.
+pub(crate) fn check_sse(config: &S3Config) {
+    if let Err(err) = Sse::parse(&config.sse, config) {
+        println!("{}", err.yellow());
+    }
+}
+
+// the same keypair always gives the same key, so SSE-C objects stay readable
+// from every machine that has the config
+fn derive_key(sk_bs58: &str) -> Result<[u8; 32], String> {
+    let sk = bs58::decode(sk_bs58).into_vec().ok().filter(|sk| !sk.is_empty())
+        .ok_or("sse c needs the config secret key, run keys first".to_string())?;
+    let mut hasher = Sha256::new();
+    hasher.update(SSE_C_CONTEXT);
+    hasher.update(&sk);
+    Ok(hasher.finalize().into())
+}
+
+fn customer_headers(headers: &mut Multimap, prefix: &str, key: &[u8; 32]) {
+    headers.insert(format!("{}-algorithm", prefix), "AES256".to_string());
+    headers.insert(format!("{}-key", prefix), b64encode(key));
+    headers.insert(format!("{}-key-md5", prefix), md5sum_hash(key));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use super::*;
+
+    fn pairs(headers: &Multimap) -> BTreeMap<String, Vec<String>> {
+        headers.iter_all().map(|(key, values)| (key.clone(), values.clone())).collect()
+    }
+
+    fn expected(entries: &[(&str, &str)]) -> BTreeMap<String, Vec<String>> {
+        entries.iter().map(|(key, value)| (key.to_string(), vec![value.to_string()])).collect()
+    }
+
+    fn config() -> S3Config {
+        S3Config { sk_bs58: bs58::encode([7u8; 32]).into_string(), ..S3Config::default() }
+    }
+
+    #[test]
+    fn parses_settings() {
+        let config = config();
+        assert_eq!(Sse::parse("", &config), Ok(Sse::Off));
+        assert_eq!(Sse::parse("off", &config), Ok(Sse::Off));
+        assert_eq!(Sse::parse("AES256", &config), Ok(Sse::S3));
+        assert_eq!(Sse::parse("kms", &config), Ok(Sse::Kms(None)));
+        assert_eq!(Sse::parse("kms:alias/backups", &config), Ok(Sse::Kms(Some("alias/backups".to_string()))));
+        assert!(matches!(Sse::parse("c", &config), Ok(Sse::C(_))));
+        assert!(Sse::parse("kms:", &config).is_err());
+        assert!(Sse::parse("aes", &config).is_err());
+        assert!(Sse::parse("c", &S3Config::default()).is_err());
+    }
+
+    #[test]
+    fn off_sends_no_headers() {
+        assert!(pairs(&Sse::Off.write_headers()).is_empty());
+        assert!(pairs(&Sse::Off.read_headers()).is_empty());
+        assert!(pairs(&Sse::Off.copy_source_headers()).is_empty());
+    }
+
+    #[test]
+    fn sse_s3_headers() {
+        assert_eq!(pairs(&Sse::S3.write_headers()), expected(&[("x-amz-server-side-encryption", "AES256")]));
+        assert!(pairs(&Sse::S3.read_headers()).is_empty());
+    }
+
+    #[test]
+    fn sse_kms_headers() {
+        assert_eq!(pairs(&Sse::Kms(None).write_headers()), expected(&[("x-amz-server-side-encryption", "aws:kms")]));
+        assert_eq!(pairs(&Sse::Kms(Some("key-1".to_string())).write_headers()), expected(&[
+            ("x-amz-server-side-encryption", "aws:kms"),
+            ("x-amz-server-side-encryption-aws-kms-key-id", "key-1"),
+        ]));
+        assert!(pairs(&Sse::Kms(None).read_headers()).is_empty());
+    }
+
+    #[test]
+    fn sse_c_headers() {
+        let key = [1u8; 32];
+        let key_b64 = STANDARD.encode(key);
+        let md5_b64 = STANDARD.encode(md5::compute(key).0);
+        let customer = |prefix: &str| expected(&[
+            (&format!("{}-algorithm", prefix), "AES256"),
+            (&format!("{}-key", prefix), &key_b64),
+            (&format!("{}-key-md5", prefix), &md5_b64),
+        ]);
+        assert_eq!(pairs(&Sse::C(key).write_headers()), customer("x-amz-server-side-encryption-customer"));
+        assert_eq!(pairs(&Sse::C(key).read_headers()), customer("x-amz-server-side-encryption-customer"));
+        assert_eq!(pairs(&Sse::C(key).copy_source_headers()), customer("x-amz-copy-source-server-side-encryption-customer"));
+    }
+
+    #[test]
+    fn sse_c_key_depends_only_on_the_secret_key() {
+        let key = derive_key(&config().sk_bs58).unwrap();
+        assert_eq!(Sse::parse("c", &config()), Ok(Sse::C(key)));
+        let other = S3Config { sk_bs58: bs58::encode([8u8; 32]).into_string(), ..S3Config::default() };
+        assert_ne!(Sse::parse("c", &other), Ok(Sse::C(key)));
+    }
+}
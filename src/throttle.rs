@@ -0,0 +1,158 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use chrono::{Local, NaiveTime};
+use colored::Colorize;
+use indicatif::HumanBytes;
+use crate::config::S3Config;
+use crate::units::parse_size;
+
+/// A limit set with --limit-rate or the limit-rate command, it wins over the config.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Override {
+    Unset,
+    Unlimited,
+    Rate(u64),
+}
+
+static OVERRIDE: Mutex<Override> = Mutex::new(Override::Unset);
+
+/// One token bucket for the whole process, so the limit caps the sum of all
+/// concurrent uploads and downloads.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+fn bucket() -> &'static Mutex<Bucket> {
+    static BUCKET: OnceLock<Mutex<Bucket>> = OnceLock::new();
+    BUCKET.get_or_init(|| Mutex::new(Bucket { tokens: 0.0, updated: Instant::now() }))
+}
+
+/// Parses a rate like `5M` or `off`, where `off`, `0` and an empty string mean unlimited.
+pub(crate) fn parse_rate(rate: &str) -> Result<Option<u64>, String> {
+    let rate = rate.trim();
+    if rate.is_empty() || rate == "off" || rate == "unlimited" {
+        return Ok(None);
+    }
+    match parse_size(rate) {
+        Some(0) => Ok(None),
+        Some(bytes) => Ok(Some(bytes)),
+        None => Err(format!("can't parse rate limit: {}", rate)),
+    }
+}
+
+/// Overrides the limits of the config with `rate`, None turns throttling off.
+pub(crate) fn set_override(rate: Option<u64>) {
+    *OVERRIDE.lock().unwrap() = match rate {
+        Some(rate) => Override::Rate(rate),
+        None => Override::Unlimited,
+    };
+}
+
+/// Goes back to the limits of the config.
+pub(crate) fn clear_override() {
+    *OVERRIDE.lock().unwrap() = Override::Unset;
+}
+
+/// The limit in bytes per second in effect right now: the override, then the first
+/// `limit_schedule` window containing the local time, then `limit_rate`.
+pub(crate) fn current_rate(config: &S3Config) -> Option<u64> {
+    match *OVERRIDE.lock().unwrap() {
+        Override::Rate(rate) => return Some(rate),
+        Override::Unlimited => return None,
+        Override::Unset => {}
+    }
+    let now = Local::now().time();
+    for entry in &config.limit_schedule {
+        // broken entries are reported once by check_limits
+        if let Ok((from, to, rate)) = parse_window(entry) {
+            let inside = match from <= to {
+                true => from <= now && now < to,
+                false => now >= from || now < to,
+            };
+            if inside {
+                return rate;
+            }
+        }
+    }
+    parse_rate(&config.limit_rate).unwrap_or(None)
+}
+
+/// Prints a warning for every rate limit setting of the config that can't be parsed.
+pub(crate) fn check_limits(config: &S3Config) {
+    if let Err(err) = parse_rate(&config.limit_rate) {
+        println!("{}", err.yellow());
+    }
+    for entry in &config.limit_schedule {
+        if let Err(err) = parse_window(entry) {
+            println!("{}", err.yellow());
+        }
+    }
+}
+
+// "08:00-18:00=2M", the window may wrap around midnight
+fn parse_window(entry: &str) -> Result<(NaiveTime, NaiveTime, Option<u64>), String> {
+    let err = || format!("can't parse limit_schedule entry \"{}\", expected like 08:00-18:00=2M", entry);
+    let (window, rate) = entry.split_once('=').ok_or_else(err)?;
+    let (from, to) = window.split_once('-').ok_or_else(err)?;
+    let from = NaiveTime::parse_from_str(from.trim(), "%H:%M").map_err(|_| err())?;
+    let to = NaiveTime::parse_from_str(to.trim(), "%H:%M").map_err(|_| err())?;
+    Ok((from, to, parse_rate(rate)?))
+}
+
+pub(crate) fn describe(config: &S3Config) -> String {
+    match current_rate(config) {
+        Some(rate) => format!("{}/s", HumanBytes(rate)),
+        None => String::from("unlimited"),
+    }
+}
+
+/// Waits until `bytes` may be sent or received without exceeding the current limit.
+pub(crate) async fn consume(config: &S3Config, bytes: u64) {
+    let rate = match current_rate(config) {
+        Some(rate) => rate as f64,
+        None => return,
+    };
+    let wait = {
+        let mut bucket = bucket().lock().unwrap();
+        let now = Instant::now();
+        // at most one second worth of bytes may be saved up for a burst
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate).min(rate);
+        bucket.updated = now;
+        bucket.tokens -= bytes as f64;
+        match bucket.tokens < 0.0 {
+            true => Duration::from_secs_f64(-bucket.tokens / rate),
+            false => Duration::ZERO,
+        }
+    };
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rates() {
+        assert_eq!(parse_rate("5M"), Ok(Some(5 << 20)));
+        assert_eq!(parse_rate("off"), Ok(None));
+        assert_eq!(parse_rate(""), Ok(None));
+        assert_eq!(parse_rate("0"), Ok(None));
+        assert!(parse_rate("fast").is_err());
+    }
+
+    // the only test that touches the process wide override
+    #[test]
+    fn override_off_disables_the_config_limit() {
+        let config = S3Config { limit_rate: "1M".to_string(), ..S3Config::default() };
+        assert_eq!(current_rate(&config), Some(1 << 20));
+        set_override(Some(2 << 20));
+        assert_eq!(current_rate(&config), Some(2 << 20));
+        set_override(None);
+        assert_eq!(current_rate(&config), None);
+        clear_override();
+        assert_eq!(current_rate(&config), Some(1 << 20));
+    }
+}
@@ -0,0 +1,108 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+/// Parses sizes like `512`, `64K`, `5M`, `1.5G` or `2GiB` into bytes (binary multiples).
+pub(crate) fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.trim().to_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return None,
+    };
+    // a cast would turn sizes past u64 into u64::MAX
+    let bytes = number * multiplier as f64;
+    match bytes < u64::MAX as f64 {
+        true => Some(bytes as u64),
+        false => None,
+    }
+}
+
+/// Parses durations like `90`, `90s`, `30m`, `24h` or `7d` into seconds.
+pub(crate) fn parse_duration(duration: &str) -> Option<u64> {
+    let duration = duration.trim();
+    let split = duration.find(|c: char| !c.is_ascii_digit()).unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    number.checked_mul(multiplier)
+}
+
+/// Parses `2024-05-01T12:00:00Z` (any RFC 3339 offset), `2024-05-01 12:00[:00]` in UTC
+/// or a bare date, which means midnight UTC.
+pub(crate) fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    let timestamp = timestamp.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(timestamp) {
+        return Some(time.with_timezone(&Utc));
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(timestamp, format) {
+            return Some(time.and_utc());
+        }
+    }
+    NaiveDate::parse_from_str(timestamp, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0).map(|time| time.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("0"), Some(0));
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("64k"), Some(64 << 10));
+        assert_eq!(parse_size("64K"), Some(64 << 10));
+        assert_eq!(parse_size("5 MB"), Some(5 << 20));
+        assert_eq!(parse_size("2GiB"), Some(2 << 30));
+        assert_eq!(parse_size("2gib"), Some(2 << 30));
+        assert_eq!(parse_size("1.5G"), Some(3 << 29));
+        assert_eq!(parse_size("1T"), Some(1 << 40));
+        assert_eq!(parse_size("16777215T"), Some(16777215 << 40));
+        assert_eq!(parse_size("16777216T"), None);
+        assert_eq!(parse_size("99999999999999999999999"), None);
+        for bad in ["", "G", "-1", "1X", "1PB", "1.2.3K", "1e3"] {
+            assert_eq!(parse_size(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("0"), Some(0));
+        assert_eq!(parse_duration("90"), Some(90));
+        assert_eq!(parse_duration("90s"), Some(90));
+        assert_eq!(parse_duration("30M"), Some(30 * 60));
+        assert_eq!(parse_duration("24h"), Some(24 * 60 * 60));
+        assert_eq!(parse_duration("7D"), Some(7 * 24 * 60 * 60));
+        assert_eq!(parse_duration(&format!("{}d", u64::MAX / 86400)), Some(u64::MAX / 86400 * 86400));
+        assert_eq!(parse_duration(&format!("{}d", u64::MAX / 86400 + 1)), None);
+        assert_eq!(parse_duration("18446744073709551616"), None);
+        for bad in ["", "d", "-1d", "1.5h", "1w", "1 day"] {
+            assert_eq!(parse_duration(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        let noon = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        assert_eq!(parse_timestamp("2024-05-01T12:00:00Z"), Some(noon));
+        assert_eq!(parse_timestamp("2024-05-01T14:00:00+02:00"), Some(noon));
+        assert_eq!(parse_timestamp("2024-05-01 12:00:00"), Some(noon));
+        assert_eq!(parse_timestamp("2024-05-01T12:00"), Some(noon));
+        assert_eq!(parse_timestamp(" 2024-05-01 "), Some(Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap()));
+        for bad in ["", "2024-02-30", "2024-05-01 25:00", "01.05.2024", "yesterday"] {
+            assert_eq!(parse_timestamp(bad), None, "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use chrono::Duration;
+use colored::Colorize;
+use indicatif::HumanBytes;
+use minio::s3::utils::UtcTime;
+use serde_derive::Serialize;
+
+// how many of the biggest objects du lists
+const LARGEST: usize = 10;
+// upper bounds in days of the age groups, the last one takes the rest
+const AGES: [(&str, i64); 6] = [("< 1 day", 1), ("< 7 days", 7), ("< 30 days", 30), ("< 90 days", 90), ("< 1 year", 365), ("older", i64::MAX)];
+
+/// Objects and bytes of one group.
+#[derive(Serialize, Clone, Debug, Default)]
+pub(crate) struct Tally {
+    pub(crate) objects: u64,
+    pub(crate) bytes: u64,
+}
+
+impl Tally {
+    fn add(&mut self, size: u64) {
+        self.objects += 1;
+        self.bytes += size;
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct AgeTally {
+    pub(crate) age: String,
+    #[serde(flatten)]
+    pub(crate) tally: Tally,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct Largest {
+    pub(crate) name: String,
+    pub(crate) bytes: u64,
+    pub(crate) last_modified: Option<UtcTime>,
+}
+
+/// What `du` reports for a bucket or prefix.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct Usage {
+    pub(crate) bucket: String,
+    pub(crate) prefix: String,
+    pub(crate) depth: usize,
+    #[serde(flatten)]
+    pub(crate) total: Tally,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) plaintext_bytes: Option<u64>,
+    pub(crate) by_prefix: BTreeMap<String, Tally>,
+    pub(crate) by_storage_class: BTreeMap<String, Tally>,
+    pub(crate) encrypted: Tally,
+    pub(crate) plain: Tally,
+    pub(crate) by_age: Vec<AgeTally>,
+    pub(crate) largest: Vec<Largest>,
+    #[serde(skip)]
+    now: UtcTime,
+}
+
+impl Usage {
+    pub(crate) fn new(bucket: &str, prefix: &str, depth: usize, plaintext: bool) -> Usage {
+        Usage {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+            depth,
+            total: Tally::default(),
+            plaintext_bytes: if plaintext { Some(0) } else { None },
+            by_prefix: BTreeMap::new(),
+            by_storage_class: BTreeMap::new(),
+            encrypted: Tally::default(),
+            plain: Tally::default(),
+            by_age: AGES.iter().map(|(age, _)| AgeTally { age: age.to_string(), tally: Tally::default() }).collect(),
+            largest: Vec::new(),
+            now: chrono::Utc::now(),
+        }
+    }
+
+    /// Counts one object, `plaintext` is its size before client side encryption
+    /// when that is known.
+    pub(crate) fn add(&mut self, name: &str, size: u64, storage_class: Option<&str>, last_modified: Option<UtcTime>, plaintext: Option<u64>) {
+        self.total.add(size);
+        if let Some(plaintext_bytes) = self.plaintext_bytes.as_mut() {
+            *plaintext_bytes += plaintext.unwrap_or(size);
+        }
+        self.by_prefix.entry(self.group(name)).or_default().add(size);
+        self.by_storage_class.entry(storage_class.unwrap_or("STANDARD").to_string()).or_default().add(size);
+        match name.ends_with(".x") {
+            true => self.encrypted.add(size),
+            false => self.plain.add(size),
+        }
+        if let Some(last_modified) = last_modified {
+            let age = self.now.signed_duration_since(last_modified);
+            let index = AGES.iter().position(|(_, days)| *days == i64::MAX || age < Duration::days(*days)).unwrap_or(AGES.len() - 1);
+            self.by_age[index].tally.add(size);
+        }
+
+        let at = self.largest.partition_point(|largest| largest.bytes >= size);
+        if at < LARGEST {
+            self.largest.insert(at, Largest { name: name.to_string(), bytes: size, last_modified });
+            self.largest.truncate(LARGEST);
+        }
+    }
+
+    // the first `depth` folders of the key below the listed prefix
+    fn group(&self, name: &str) -> String {
+        let rest = name.strip_prefix(&self.prefix).unwrap_or(name);
+        let folders: Vec<&str> = rest.split('/').collect();
+        let folders = &folders[..folders.len() - 1];
+        match folders.is_empty() || self.depth == 0 {
+            true => format!("{}(top level)", self.prefix),
+            false => format!("{}{}/", self.prefix, folders[..folders.len().min(self.depth)].join("/")),
+        }
+    }
+
+    pub(crate) fn print(&self) {
+        let line = |label: &str, tally: &Tally| println!("  {:<28} {:>10} objects {:>12}", label, tally.objects, HumanBytes(tally.bytes).to_string());
+        println!("{} {}/{}", "usage of".blue(), self.bucket, self.prefix);
+        line("total", &self.total);
+        if let Some(plaintext_bytes) = self.plaintext_bytes {
+            println!("  {:<28} {:>31}", "total before encryption", HumanBytes(plaintext_bytes).to_string());
+        }
+        println!("{}", "by prefix".blue());
+        for (prefix, tally) in &self.by_prefix {
+            line(prefix, tally);
+        }
+        println!("{}", "by storage class".blue());
+        for (storage_class, tally) in &self.by_storage_class {
+            line(storage_class, tally);
+        }
+        println!("{}", "by encryption".blue());
+        line("encrypted (.x)", &self.encrypted);
+        line("plain", &self.plain);
+        println!("{}", "by age".blue());
+        for age in self.by_age.iter().filter(|age| age.tally.objects > 0) {
+            line(&age.age, &age.tally);
+        }
+        println!("{}", "largest objects".blue());
+        for largest in &self.largest {
+            let last_modified = largest.last_modified.map(|time| time.format("%Y-%m-%d").to_string()).unwrap_or_default();
+            println!("  {:>12} {} {}", HumanBytes(largest.bytes).to_string(), last_modified, largest.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use super::*;
+
+    fn counts(tally: &Tally) -> (u64, u64) {
+        (tally.objects, tally.bytes)
+    }
+
+    fn usage(depth: usize) -> Usage {
+        let mut usage = Usage::new("bk", "data/", depth, true);
+        usage.now = chrono::Utc.with_ymd_and_hms(2026, 6, 1, 0, 0, 0).unwrap();
+        let ago = |hours: i64| Some(usage.now - Duration::hours(hours));
+        let objects = [
+            ("data/a.txt.x", 100, Some("STANDARD"), ago(12), Some(60)),
+            ("data/logs/2024/app.log", 200, None, ago(3 * 24), None),
+            ("data/logs/2025/x.log", 300, Some("GLACIER"), ago(40 * 24), None),
+            ("data/img/p.png.x", 400, Some("GLACIER"), ago(400 * 24), None),
+            ("data/old", 50, None, None, None),
+            ("data/week.txt", 10, None, ago(7 * 24), None),
+        ];
+        for (name, size, storage_class, last_modified, plaintext) in objects {
+            usage.add(name, size, storage_class, last_modified, plaintext);
+        }
+        usage
+    }
+
+    #[test]
+    fn objects_are_grouped() {
+        let usage = usage(1);
+        assert_eq!(counts(&usage.total), (6, 1060));
+        assert_eq!(usage.plaintext_bytes, Some(1020));
+        let by_prefix: Vec<(&str, (u64, u64))> = usage.by_prefix.iter().map(|(prefix, tally)| (prefix.as_str(), counts(tally))).collect();
+        assert_eq!(by_prefix, [("data/(top level)", (3, 160)), ("data/img/", (1, 400)), ("data/logs/", (2, 500))]);
+        assert_eq!(counts(&usage.by_storage_class["STANDARD"]), (4, 360));
+        assert_eq!(counts(&usage.by_storage_class["GLACIER"]), (2, 700));
+        assert_eq!(counts(&usage.encrypted), (2, 500));
+        assert_eq!(counts(&usage.plain), (4, 560));
+        // an object without a date is in no age group, exactly 7 days is no longer < 7 days
+        let by_age: Vec<(&str, (u64, u64))> = usage.by_age.iter().map(|age| (age.age.as_str(), counts(&age.tally))).collect();
+        assert_eq!(by_age, [("< 1 day", (1, 100)), ("< 7 days", (1, 200)), ("< 30 days", (1, 10)), ("< 90 days", (1, 300)), ("< 1 year", (0, 0)), ("older", (1, 400))]);
+        let largest: Vec<u64> = usage.largest.iter().map(|largest| largest.bytes).collect();
+        assert_eq!(largest, [400, 300, 200, 100, 50, 10]);
+    }
+
+    #[test]
+    fn prefix_depth() {
+        let deeper = usage(2);
+        let prefixes: Vec<&str> = deeper.by_prefix.keys().map(String::as_str).collect();
+        assert_eq!(prefixes, ["data/(top level)", "data/img/", "data/logs/2024/", "data/logs/2025/"]);
+        let flat = usage(0);
+        assert_eq!(counts(&flat.by_prefix["data/(top level)"]), (6, 1060));
+    }
+}